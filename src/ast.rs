@@ -1,13 +1,28 @@
-pub(crate) mod expression;
-pub(crate) mod statement;
+use std::fmt::{self, Display};
 
-pub trait Node {
+use crate::token;
+
+pub mod annotation;
+pub mod arena;
+pub mod expression;
+pub mod json;
+pub mod sexp;
+pub mod statement;
+pub mod visit;
+
+// Display は再度 parse すると同じ木になる、括弧をすべてつけた形で出力する
+pub trait Node: Display {
     fn token_literal(&self) -> &str;
-    fn string(&self) -> String;
+    // ソース上の範囲
+    fn span(&self) -> token::Span;
+    fn string(&self) -> String {
+        self.to_string()
+    }
 }
 
-pub(crate) struct Program {
-    pub(crate) statements: Vec<statement::Statement>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub statements: Vec<statement::Statement>,
 }
 
 impl Node for Program {
@@ -17,10 +32,22 @@ impl Node for Program {
             .map_or_else(|| "", |s| s.token_literal())
     }
 
-    fn string(&self) -> String {
-        self.statements
-            .iter()
-            .map(|s| s.string())
-            .collect::<String>()
+    fn span(&self) -> token::Span {
+        match (self.statements.first(), self.statements.last()) {
+            (Some(first), Some(last)) => first.span().to(last.span()),
+            _ => token::Span::default(),
+        }
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, statement) in self.statements.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            statement.fmt(f)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,81 @@
+use std::fmt::{self, Display};
+
+use crate::token;
+
+use super::Node;
+
+// 型注釈に書ける名前。any は動的型
+pub const NAMES: [&str; 4] = ["int", "bool", "str", "any"];
+
+// `let x: int = 5;` や `fn(a: str) -> bool {}` の型の部分
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeAnnotation {
+    Named(token::Token),
+    // [T]
+    Array {
+        token: token::Token,
+        element: Box<TypeAnnotation>,
+        rbracket: token::Token,
+    },
+    // {K: V}
+    Hash {
+        token: token::Token,
+        key: Box<TypeAnnotation>,
+        value: Box<TypeAnnotation>,
+        rbrace: token::Token,
+    },
+    // fn(A, B) -> R
+    Function {
+        token: token::Token,
+        parameters: Vec<TypeAnnotation>,
+        return_type: Box<TypeAnnotation>,
+    },
+}
+
+impl Node for TypeAnnotation {
+    fn token_literal(&self) -> &str {
+        let token = match self {
+            TypeAnnotation::Named(token)
+            | TypeAnnotation::Array { token, .. }
+            | TypeAnnotation::Hash { token, .. }
+            | TypeAnnotation::Function { token, .. } => token,
+        };
+        std::str::from_utf8(&token.literal[..]).unwrap()
+    }
+    fn span(&self) -> token::Span {
+        match self {
+            TypeAnnotation::Named(token) => token.span(),
+            TypeAnnotation::Array {
+                token, rbracket, ..
+            } => token.span().to(rbracket.span()),
+            TypeAnnotation::Hash { token, rbrace, .. } => token.span().to(rbrace.span()),
+            TypeAnnotation::Function {
+                token, return_type, ..
+            } => token.span().to(return_type.span()),
+        }
+    }
+}
+
+impl Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeAnnotation::Named(_) => f.write_str(self.token_literal()),
+            TypeAnnotation::Array { element, .. } => write!(f, "[{}]", element),
+            TypeAnnotation::Hash { key, value, .. } => write!(f, "{{{}: {}}}", key, value),
+            TypeAnnotation::Function {
+                parameters,
+                return_type,
+                ..
+            } => {
+                f.write_str("fn(")?;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    parameter.fmt(f)?;
+                }
+                write!(f, ") -> {}", return_type)
+            }
+        }
+    }
+}
@@ -0,0 +1,545 @@
+// AST を 1 つの Vec にまとめて持つ表現
+// 子は NodeId で参照するので、解析結果は HashMap<NodeId, T> や SideTable<T> に別に持てる
+use std::ops::Index;
+
+use super::{annotation::TypeAnnotation, expression, statement, Node, Program};
+use crate::token;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum NodeKind {
+    Program {
+        statements: Vec<NodeId>,
+    },
+    LetStatement {
+        name: NodeId,
+        annotation: Option<TypeAnnotation>,
+        value: NodeId,
+    },
+    ReturnStatement {
+        value: NodeId,
+    },
+    ExpressionStatement {
+        expression: NodeId,
+    },
+    ImportStatement {
+        path: NodeId,
+        alias: NodeId,
+    },
+    ExportStatement {
+        statement: NodeId,
+    },
+    ThrowStatement {
+        value: NodeId,
+    },
+    BlockStatement {
+        statements: Vec<NodeId>,
+    },
+    Identifier {
+        name: String,
+    },
+    IntegerLiteral {
+        value: i64,
+    },
+    StringLiteral {
+        value: String,
+    },
+    Boolean {
+        value: bool,
+    },
+    PrefixExpression {
+        operator: String,
+        right: NodeId,
+    },
+    InfixExpression {
+        left: NodeId,
+        operator: String,
+        right: NodeId,
+    },
+    IfExpression {
+        condition: NodeId,
+        consequence: NodeId,
+        alternative: Option<NodeId>,
+    },
+    Parameter {
+        name: NodeId,
+        annotation: Option<TypeAnnotation>,
+    },
+    FunctionLiteral {
+        parameters: Vec<NodeId>,
+        return_type: Option<TypeAnnotation>,
+        body: NodeId,
+    },
+    CallExpression {
+        function: NodeId,
+        arguments: Vec<NodeId>,
+    },
+    ArrayLiteral {
+        elements: Vec<NodeId>,
+    },
+    IndexExpression {
+        left: NodeId,
+        index: NodeId,
+    },
+    HashLiteral {
+        pairs: Vec<(NodeId, NodeId)>,
+    },
+    MemberExpression {
+        object: NodeId,
+        member: NodeId,
+    },
+    TryExpression {
+        block: NodeId,
+        catch: Option<NodeId>,
+        finally: Option<NodeId>,
+    },
+    CatchClause {
+        parameter: NodeId,
+        body: NodeId,
+    },
+}
+
+impl NodeKind {
+    // 子の NodeId をソース上の順番で返す
+    pub fn children(&self) -> Vec<NodeId> {
+        match self {
+            NodeKind::Program { statements } | NodeKind::BlockStatement { statements } => {
+                statements.clone()
+            }
+            NodeKind::LetStatement { name, value, .. } => vec![*name, *value],
+            NodeKind::Parameter { name, .. } => vec![*name],
+            NodeKind::ReturnStatement { value } | NodeKind::ThrowStatement { value } => {
+                vec![*value]
+            }
+            NodeKind::ExpressionStatement { expression } => vec![*expression],
+            NodeKind::ImportStatement { path, alias } => vec![*path, *alias],
+            NodeKind::ExportStatement { statement } => vec![*statement],
+            NodeKind::Identifier { .. }
+            | NodeKind::IntegerLiteral { .. }
+            | NodeKind::StringLiteral { .. }
+            | NodeKind::Boolean { .. } => Vec::new(),
+            NodeKind::PrefixExpression { right, .. } => vec![*right],
+            NodeKind::InfixExpression { left, right, .. } => vec![*left, *right],
+            NodeKind::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let mut children = vec![*condition, *consequence];
+                children.extend(alternative);
+                children
+            }
+            NodeKind::FunctionLiteral {
+                parameters, body, ..
+            } => {
+                let mut children = parameters.clone();
+                children.push(*body);
+                children
+            }
+            NodeKind::CallExpression {
+                function,
+                arguments,
+            } => {
+                let mut children = vec![*function];
+                children.extend(arguments);
+                children
+            }
+            NodeKind::ArrayLiteral { elements } => elements.clone(),
+            NodeKind::IndexExpression { left, index } => vec![*left, *index],
+            NodeKind::HashLiteral { pairs } => pairs.iter().flat_map(|(k, v)| [*k, *v]).collect(),
+            NodeKind::MemberExpression { object, member } => vec![*object, *member],
+            NodeKind::TryExpression {
+                block,
+                catch,
+                finally,
+            } => {
+                let mut children = vec![*block];
+                children.extend(catch);
+                children.extend(finally);
+                children
+            }
+            NodeKind::CatchClause { parameter, body } => vec![*parameter, *body],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NodeData {
+    pub kind: NodeKind,
+    pub span: token::Span,
+    // Program だけ None
+    pub parent: Option<NodeId>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arena {
+    nodes: Vec<NodeData>,
+    root: NodeId,
+}
+
+impl Arena {
+    // 木の AST から作る。NodeId は親が子より小さくなる（前順）
+    pub fn from_program(program: &Program) -> Self {
+        let mut arena = Self {
+            nodes: Vec::new(),
+            root: NodeId(0),
+        };
+        let root = arena.alloc(program.span(), None);
+        let statements = program
+            .statements
+            .iter()
+            .map(|s| arena.statement(s, root))
+            .collect();
+        arena.nodes[root.index()].kind = NodeKind::Program { statements };
+        arena
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> &NodeData {
+        &self.nodes[id.index()]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &NodeData)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (NodeId(i as u32), node))
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.get(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> Vec<NodeId> {
+        self.get(id).kind.children()
+    }
+
+    // offset を含む一番内側のノード。エディタのカーソル位置や token からノードを引くのに使う
+    pub fn node_at(&self, offset: usize) -> Option<NodeId> {
+        let contains = |id: NodeId| {
+            let span = self.get(id).span;
+            span.start.offset <= offset && offset < span.end.offset
+        };
+        let mut id = self.root;
+        if !contains(id) {
+            return None;
+        }
+        while let Some(child) = self.children(id).into_iter().find(|c| contains(*c)) {
+            id = child;
+        }
+        Some(id)
+    }
+
+    // 子を作ってから親の kind を埋めるので、先に仮の kind で確保しておく
+    fn alloc(&mut self, span: token::Span, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(NodeData {
+            kind: NodeKind::Program {
+                statements: Vec::new(),
+            },
+            span,
+            parent,
+        });
+        id
+    }
+
+    fn set(&mut self, id: NodeId, kind: NodeKind) -> NodeId {
+        self.nodes[id.index()].kind = kind;
+        id
+    }
+
+    fn statement(&mut self, s: &statement::Statement, parent: NodeId) -> NodeId {
+        if let statement::Statement::LetStatement(s) = s {
+            return self.let_statement(s, parent);
+        }
+        let id = self.alloc(s.span(), Some(parent));
+        let kind = match s {
+            statement::Statement::LetStatement(_) => unreachable!(),
+            statement::Statement::ReturnStatement(s) => NodeKind::ReturnStatement {
+                value: self.expression(&s.return_value, id),
+            },
+            statement::Statement::ExpressionStatement(s) => NodeKind::ExpressionStatement {
+                expression: self.expression(&s.expression, id),
+            },
+            statement::Statement::ImportStatement(s) => {
+                let path = self.alloc(s.path.span(), Some(id));
+                let value = s.path.value.clone();
+                NodeKind::ImportStatement {
+                    path: self.set(path, NodeKind::StringLiteral { value }),
+                    alias: self.identifier(&s.alias, id),
+                }
+            }
+            statement::Statement::ExportStatement(s) => NodeKind::ExportStatement {
+                statement: self.let_statement(&s.statement, id),
+            },
+            statement::Statement::ThrowStatement(s) => NodeKind::ThrowStatement {
+                value: self.expression(&s.value, id),
+            },
+        };
+        self.set(id, kind)
+    }
+
+    fn let_statement(&mut self, s: &statement::LetStatement, parent: NodeId) -> NodeId {
+        let id = self.alloc(s.span(), Some(parent));
+        let kind = NodeKind::LetStatement {
+            name: self.identifier(&s.name, id),
+            annotation: s.annotation.as_deref().cloned(),
+            value: self.expression(&s.value, id),
+        };
+        self.set(id, kind)
+    }
+
+    fn block(&mut self, block: &statement::BlockStatement, parent: NodeId) -> NodeId {
+        let id = self.alloc(block.span(), Some(parent));
+        let statements = block
+            .statements
+            .iter()
+            .map(|s| self.statement(s, id))
+            .collect();
+        self.set(id, NodeKind::BlockStatement { statements })
+    }
+
+    fn identifier(&mut self, identifier: &expression::Identifier, parent: NodeId) -> NodeId {
+        let id = self.alloc(identifier.span(), Some(parent));
+        self.set(
+            id,
+            NodeKind::Identifier {
+                name: identifier.name().to_string(),
+            },
+        )
+    }
+
+    fn parameter(&mut self, parameter: &expression::Parameter, parent: NodeId) -> NodeId {
+        let id = self.alloc(parameter.span(), Some(parent));
+        let kind = NodeKind::Parameter {
+            name: self.identifier(&parameter.name, id),
+            annotation: parameter.annotation.clone(),
+        };
+        self.set(id, kind)
+    }
+
+    fn catch_clause(&mut self, catch: &expression::CatchClause, parent: NodeId) -> NodeId {
+        let id = self.alloc(catch.span(), Some(parent));
+        let kind = NodeKind::CatchClause {
+            parameter: self.identifier(&catch.parameter, id),
+            body: self.block(&catch.body, id),
+        };
+        self.set(id, kind)
+    }
+
+    fn expressions(
+        &mut self,
+        expressions: &[expression::Expression],
+        parent: NodeId,
+    ) -> Vec<NodeId> {
+        expressions
+            .iter()
+            .map(|e| self.expression(e, parent))
+            .collect()
+    }
+
+    fn expression(&mut self, e: &expression::Expression, parent: NodeId) -> NodeId {
+        if let expression::Expression::Identifier(identifier) = e {
+            return self.identifier(identifier, parent);
+        }
+        let id = self.alloc(e.span(), Some(parent));
+        let kind = match e {
+            expression::Expression::Identifier(_) => unreachable!(),
+            expression::Expression::IntegerLiteral(e) => {
+                NodeKind::IntegerLiteral { value: e.value }
+            }
+            expression::Expression::StringLiteral(e) => NodeKind::StringLiteral {
+                value: e.value.clone(),
+            },
+            expression::Expression::Boolean(e) => NodeKind::Boolean { value: e.value },
+            expression::Expression::PrefixExpression(e) => NodeKind::PrefixExpression {
+                operator: e.operator.clone(),
+                right: self.expression(&e.right, id),
+            },
+            expression::Expression::InfixExpression(e) => NodeKind::InfixExpression {
+                left: self.expression(&e.left, id),
+                operator: e.operator.clone(),
+                right: self.expression(&e.right, id),
+            },
+            expression::Expression::IfExpression(e) => NodeKind::IfExpression {
+                condition: self.expression(&e.condition, id),
+                consequence: self.block(&e.consequence, id),
+                alternative: e.alternative.as_ref().map(|b| self.block(b, id)),
+            },
+            expression::Expression::FunctionLiteral(e) => NodeKind::FunctionLiteral {
+                parameters: e.parameters.iter().map(|p| self.parameter(p, id)).collect(),
+                return_type: e.return_type.clone(),
+                body: self.block(&e.body, id),
+            },
+            expression::Expression::CallExpression(e) => NodeKind::CallExpression {
+                function: self.expression(&e.function, id),
+                arguments: self.expressions(&e.arguments, id),
+            },
+            expression::Expression::ArrayLiteral(e) => NodeKind::ArrayLiteral {
+                elements: self.expressions(&e.elements, id),
+            },
+            expression::Expression::IndexExpression(e) => NodeKind::IndexExpression {
+                left: self.expression(&e.left, id),
+                index: self.expression(&e.index, id),
+            },
+            expression::Expression::HashLiteral(e) => NodeKind::HashLiteral {
+                pairs: e
+                    .pairs
+                    .iter()
+                    .map(|(k, v)| (self.expression(k, id), self.expression(v, id)))
+                    .collect(),
+            },
+            expression::Expression::MemberExpression(e) => NodeKind::MemberExpression {
+                object: self.expression(&e.object, id),
+                member: self.identifier(&e.member, id),
+            },
+            expression::Expression::TryExpression(e) => NodeKind::TryExpression {
+                block: self.block(&e.block, id),
+                catch: e.catch.as_ref().map(|c| self.catch_clause(c, id)),
+                finally: e.finally.as_ref().map(|b| self.block(b, id)),
+            },
+        };
+        self.set(id, kind)
+    }
+}
+
+impl Index<NodeId> for Arena {
+    type Output = NodeData;
+
+    fn index(&self, id: NodeId) -> &Self::Output {
+        self.get(id)
+    }
+}
+
+// NodeId を添字にした密な表。すべてのノードに値を持たせる解析向け
+#[derive(Debug, Clone)]
+pub struct SideTable<T> {
+    values: Vec<Option<T>>,
+}
+
+impl<T> SideTable<T> {
+    pub fn new(arena: &Arena) -> Self {
+        Self {
+            values: std::iter::repeat_with(|| None).take(arena.len()).collect(),
+        }
+    }
+
+    pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+        self.values[id.index()].replace(value)
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.values[id.index()].as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.values[id.index()].as_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{Arena, NodeId, NodeKind, SideTable};
+    use crate::parser;
+
+    #[test]
+    fn test_from_program() {
+        let program = parser::parse("let x = 1 + y; f(x);").unwrap();
+        let arena = Arena::from_program(&program);
+        let kinds: Vec<String> = arena
+            .iter()
+            .map(|(_, node)| format!("{:?}", node.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "Program { statements: [NodeId(1), NodeId(6)] }",
+                "LetStatement { name: NodeId(2), annotation: None, value: NodeId(3) }",
+                "Identifier { name: \"x\" }",
+                "InfixExpression { left: NodeId(4), operator: \"+\", right: NodeId(5) }",
+                "IntegerLiteral { value: 1 }",
+                "Identifier { name: \"y\" }",
+                "ExpressionStatement { expression: NodeId(7) }",
+                "CallExpression { function: NodeId(8), arguments: [NodeId(9)] }",
+                "Identifier { name: \"f\" }",
+                "Identifier { name: \"x\" }",
+            ]
+        );
+
+        // 親子関係と span
+        assert_eq!(arena.parent(arena.root()), None);
+        for (id, node) in arena.iter() {
+            for child in node.kind.children() {
+                assert_eq!(arena.parent(child), Some(id));
+                assert!(child > id);
+            }
+        }
+        let infix = NodeId(3);
+        assert_eq!(arena.children(infix), vec![NodeId(4), NodeId(5)]);
+        assert_eq!(
+            (arena[infix].span.start.offset, arena[infix].span.end.offset),
+            (8, 13)
+        );
+
+        // 一番内側のノード
+        assert_eq!(arena.node_at(12), Some(NodeId(5)));
+        assert_eq!(arena.node_at(10), Some(infix));
+        assert_eq!(arena.node_at(17), Some(NodeId(9)));
+        assert_eq!(arena.node_at(100), None);
+    }
+
+    #[test]
+    fn test_side_table() {
+        let program =
+            parser::parse("let f = fn(a) { if (a) { [a, {1: 2}] } else { a[0] } }; f(true);")
+                .unwrap();
+        let arena = Arena::from_program(&program);
+
+        // 深さを密な表に、識別子の名前を HashMap に入れる
+        let mut depth = SideTable::new(&arena);
+        let mut names = HashMap::new();
+        for (id, node) in arena.iter() {
+            let d = node.parent.map_or(0, |p| depth.get(p).unwrap() + 1);
+            depth.insert(id, d);
+            if let NodeKind::Identifier { name } = &node.kind {
+                names.insert(id, name.as_str());
+            }
+        }
+        let max = arena.iter().map(|(id, _)| *depth.get(id).unwrap()).max();
+        assert_eq!(max, Some(10));
+        let deepest: Vec<&NodeKind> = arena
+            .iter()
+            .filter(|(id, _)| depth.get(*id) == Some(&10))
+            .map(|(_, node)| &node.kind)
+            .collect();
+        assert_eq!(
+            deepest,
+            vec![
+                &NodeKind::IntegerLiteral { value: 1 },
+                &NodeKind::IntegerLiteral { value: 2 }
+            ]
+        );
+        assert_eq!(names.values().filter(|n| **n == "a").count(), 4);
+    }
+}
@@ -1,38 +1,489 @@
+use std::fmt::{self, Display};
+
 use crate::token;
 
-#[derive(Debug)]
-pub(crate) enum Expression {
+use super::{annotation, statement, Node};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expression {
     Identifier(Identifier),
+    IntegerLiteral(IntegerLiteral),
+    StringLiteral(StringLiteral),
+    Boolean(Boolean),
+    PrefixExpression(PrefixExpression),
+    InfixExpression(InfixExpression),
+    IfExpression(IfExpression),
+    FunctionLiteral(FunctionLiteral),
+    CallExpression(CallExpression),
+    ArrayLiteral(ArrayLiteral),
+    IndexExpression(IndexExpression),
+    HashLiteral(HashLiteral),
+    MemberExpression(MemberExpression),
+    TryExpression(TryExpression),
 }
-impl super::Node for Expression {
+impl Node for Expression {
     fn token_literal(&self) -> &str {
-        todo!()
+        match self {
+            Expression::Identifier(e) => e.token_literal(),
+            Expression::IntegerLiteral(e) => e.token_literal(),
+            Expression::StringLiteral(e) => e.token_literal(),
+            Expression::Boolean(e) => e.token_literal(),
+            Expression::PrefixExpression(e) => e.token_literal(),
+            Expression::InfixExpression(e) => e.token_literal(),
+            Expression::IfExpression(e) => e.token_literal(),
+            Expression::FunctionLiteral(e) => e.token_literal(),
+            Expression::CallExpression(e) => e.token_literal(),
+            Expression::ArrayLiteral(e) => e.token_literal(),
+            Expression::IndexExpression(e) => e.token_literal(),
+            Expression::HashLiteral(e) => e.token_literal(),
+            Expression::MemberExpression(e) => e.token_literal(),
+            Expression::TryExpression(e) => e.token_literal(),
+        }
     }
-
-    fn string(&self) -> String {
+    fn span(&self) -> token::Span {
         match self {
-            Expression::Identifier(identifier) => identifier.string(),
+            Expression::Identifier(e) => e.span(),
+            Expression::IntegerLiteral(e) => e.span(),
+            Expression::StringLiteral(e) => e.span(),
+            Expression::Boolean(e) => e.span(),
+            Expression::PrefixExpression(e) => e.span(),
+            Expression::InfixExpression(e) => e.span(),
+            Expression::IfExpression(e) => e.span(),
+            Expression::FunctionLiteral(e) => e.span(),
+            Expression::CallExpression(e) => e.span(),
+            Expression::ArrayLiteral(e) => e.span(),
+            Expression::IndexExpression(e) => e.span(),
+            Expression::HashLiteral(e) => e.span(),
+            Expression::MemberExpression(e) => e.span(),
+            Expression::TryExpression(e) => e.span(),
+        }
+    }
+}
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Identifier(e) => e.fmt(f),
+            Expression::IntegerLiteral(e) => e.fmt(f),
+            Expression::StringLiteral(e) => e.fmt(f),
+            Expression::Boolean(e) => e.fmt(f),
+            Expression::PrefixExpression(e) => e.fmt(f),
+            Expression::InfixExpression(e) => e.fmt(f),
+            Expression::IfExpression(e) => e.fmt(f),
+            Expression::FunctionLiteral(e) => e.fmt(f),
+            Expression::CallExpression(e) => e.fmt(f),
+            Expression::ArrayLiteral(e) => e.fmt(f),
+            Expression::IndexExpression(e) => e.fmt(f),
+            Expression::HashLiteral(e) => e.fmt(f),
+            Expression::MemberExpression(e) => e.fmt(f),
+            Expression::TryExpression(e) => e.fmt(f),
         }
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct Identifier {
+fn literal(token: &token::Token) -> &str {
+    std::str::from_utf8(&token.literal[..]).unwrap()
+}
+
+fn join<T: Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        item.fmt(f)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Identifier {
     token: token::Token,
 }
 impl Identifier {
-    pub(crate) fn new(token: token::Token) -> Self {
+    pub fn new(token: token::Token) -> Self {
         Self { token }
     }
+    pub fn name(&self) -> &str {
+        literal(&self.token)
+    }
+    pub fn token(&self) -> &token::Token {
+        &self.token
+    }
+}
+impl Node for Identifier {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span()
+    }
+}
+impl Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IntegerLiteral {
+    pub token: token::Token,
+    pub value: i64,
+}
+impl Node for IntegerLiteral {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span()
+    }
+}
+impl Display for IntegerLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StringLiteral {
+    pub token: token::Token,
+    pub value: String,
+}
+impl Node for StringLiteral {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span()
+    }
+}
+impl Display for StringLiteral {
+    // lexer が解釈できる形にエスケープして出力する
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.value.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\t' => f.write_str("\\t")?,
+                '\r' => f.write_str("\\r")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Boolean {
+    pub token: token::Token,
+    pub value: bool,
+}
+impl Node for Boolean {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span()
+    }
+}
+impl Display for Boolean {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PrefixExpression {
+    pub token: token::Token,
+    pub operator: String,
+    pub right: Box<Expression>,
+}
+impl Node for PrefixExpression {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.right.span())
+    }
+}
+impl Display for PrefixExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}{})", self.operator, self.right)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct InfixExpression {
+    pub token: token::Token,
+    pub left: Box<Expression>,
+    pub operator: String,
+    pub right: Box<Expression>,
+}
+impl Node for InfixExpression {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.left.span().to(self.right.span())
+    }
+}
+impl Display for InfixExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} {} {})", self.left, self.operator, self.right)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IfExpression {
+    pub token: token::Token,
+    pub condition: Box<Expression>,
+    pub consequence: statement::BlockStatement,
+    pub alternative: Option<statement::BlockStatement>,
+}
+impl Node for IfExpression {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        let end = self.alternative.as_ref().unwrap_or(&self.consequence);
+        self.token.span().to(end.span())
+    }
+}
+impl Display for IfExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "if ({}) {}", self.condition, self.consequence)?;
+        if let Some(alternative) = &self.alternative {
+            write!(f, " else {}", alternative)?;
+        }
+        Ok(())
+    }
 }
-impl super::Node for Identifier {
+
+// 関数の引数。型注釈は省略できる
+#[derive(Debug, PartialEq, Clone)]
+pub struct Parameter {
+    pub name: Identifier,
+    pub annotation: Option<annotation::TypeAnnotation>,
+}
+impl Node for Parameter {
     fn token_literal(&self) -> &str {
-        std::str::from_utf8(&self.token.literal[..]).unwrap()
+        self.name.token_literal()
+    }
+    fn span(&self) -> token::Span {
+        match &self.annotation {
+            Some(annotation) => self.name.span().to(annotation.span()),
+            None => self.name.span(),
+        }
+    }
+}
+impl Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name.fmt(f)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ": {}", annotation)?;
+        }
+        Ok(())
     }
+}
 
-    fn string(&self) -> String {
-        // Bytes の clone は値自体をコピーしているわけではなさそうだけど、さすがにこの実装ではデータのコピー発生してそう（要検証）
-        // debug 用途なので、一旦許容する
-        String::from_utf8(self.token.literal.clone().to_vec()).unwrap()
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionLiteral {
+    pub token: token::Token,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Option<annotation::TypeAnnotation>,
+    pub body: statement::BlockStatement,
+}
+impl Node for FunctionLiteral {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.body.span())
+    }
+}
+impl Display for FunctionLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("fn(")?;
+        join(f, &self.parameters)?;
+        f.write_str(")")?;
+        if let Some(return_type) = &self.return_type {
+            write!(f, " -> {}", return_type)?;
+        }
+        write!(f, " {}", self.body)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CallExpression {
+    // `(` の token
+    pub token: token::Token,
+    pub function: Box<Expression>,
+    pub arguments: Vec<Expression>,
+    pub rparen: token::Token,
+}
+impl Node for CallExpression {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.function.span().to(self.rparen.span())
+    }
+}
+impl Display for CallExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.function)?;
+        join(f, &self.arguments)?;
+        f.write_str(")")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ArrayLiteral {
+    pub token: token::Token,
+    pub elements: Vec<Expression>,
+    pub rbracket: token::Token,
+}
+impl Node for ArrayLiteral {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.rbracket.span())
+    }
+}
+impl Display for ArrayLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        join(f, &self.elements)?;
+        f.write_str("]")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IndexExpression {
+    // `[` の token
+    pub token: token::Token,
+    pub left: Box<Expression>,
+    pub index: Box<Expression>,
+    pub rbracket: token::Token,
+}
+impl Node for IndexExpression {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.left.span().to(self.rbracket.span())
+    }
+}
+impl Display for IndexExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}[{}])", self.left, self.index)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HashLiteral {
+    pub token: token::Token,
+    // 出力や比較で順序が変わらないように、書かれた順に持つ
+    pub pairs: Vec<(Expression, Expression)>,
+    pub rbrace: token::Token,
+}
+impl Node for HashLiteral {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.rbrace.span())
+    }
+}
+impl Display for HashLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, (key, value)) in self.pairs.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", key, value)?;
+        }
+        f.write_str("}")
+    }
+}
+
+// モジュールが export した名前を取り出す `lib.name`
+#[derive(Debug, PartialEq, Clone)]
+pub struct MemberExpression {
+    // `.` の token
+    pub token: token::Token,
+    pub object: Box<Expression>,
+    pub member: Identifier,
+}
+impl Node for MemberExpression {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.object.span().to(self.member.span())
+    }
+}
+impl Display for MemberExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}.{})", self.object, self.member)
+    }
+}
+
+// `try { } catch (e) { } finally { }`。catch と finally の少なくとも一方がある
+// 値は try のブロックか、エラーを受け取ったときは catch のブロックの値
+#[derive(Debug, PartialEq, Clone)]
+pub struct TryExpression {
+    pub token: token::Token,
+    pub block: statement::BlockStatement,
+    pub catch: Option<Box<CatchClause>>,
+    pub finally: Option<statement::BlockStatement>,
+}
+impl Node for TryExpression {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        let end = match (&self.catch, &self.finally) {
+            (_, Some(finally)) => finally.span(),
+            (Some(catch), None) => catch.span(),
+            (None, None) => self.block.span(),
+        };
+        self.token.span().to(end)
+    }
+}
+impl Display for TryExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "try {}", self.block)?;
+        if let Some(catch) = &self.catch {
+            write!(f, " {}", catch)?;
+        }
+        if let Some(finally) = &self.finally {
+            write!(f, " finally {}", finally)?;
+        }
+        Ok(())
+    }
+}
+
+// `catch (e) { }`。e には throw した値か実行時エラーのエラー値が入る
+#[derive(Debug, PartialEq, Clone)]
+pub struct CatchClause {
+    // `catch` の token
+    pub token: token::Token,
+    pub parameter: Identifier,
+    pub body: statement::BlockStatement,
+}
+impl Node for CatchClause {
+    fn token_literal(&self) -> &str {
+        literal(&self.token)
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.body.span())
+    }
+}
+impl Display for CatchClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "catch ({}) {}", self.parameter, self.body)
     }
 }
@@ -0,0 +1,677 @@
+// AST と JSON の相互変換
+// 各ノードは {"kind": ノード名, "span": 範囲, ...子ノード} の形にする
+// JSON から AST に戻すときは span は読まない（外部で生成したものには位置がないため）
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use super::{annotation, expression, statement, Node, Program};
+use crate::{parser, token};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{path}: {message}")]
+pub struct FromJsonError {
+    // エラーになった箇所。`$.statements[0].value` のような形
+    pub path: String,
+    pub message: String,
+}
+
+pub fn to_json(program: &Program) -> Value {
+    json!({
+        "kind": "Program",
+        "span": span(program.span()),
+        "statements": program.statements.iter().map(statement_to_json).collect::<Vec<_>>(),
+    })
+}
+
+pub fn position(pos: token::Position) -> Value {
+    json!({"offset": pos.offset, "line": pos.line, "column": pos.column})
+}
+
+pub fn span(span: token::Span) -> Value {
+    json!({"start": position(span.start), "end": position(span.end)})
+}
+
+fn node(kind: &str, node: &dyn Node, fields: Value) -> Value {
+    let mut map = Map::new();
+    map.insert("kind".to_string(), json!(kind));
+    map.insert("span".to_string(), span(node.span()));
+    if let Value::Object(fields) = fields {
+        map.extend(fields);
+    }
+    Value::Object(map)
+}
+
+fn statement_to_json(s: &statement::Statement) -> Value {
+    match s {
+        statement::Statement::LetStatement(s) => node(
+            "LetStatement",
+            s,
+            json!({
+                "name": identifier_to_json(&s.name),
+                "annotation": annotation_to_json(s.annotation.as_deref()),
+                "value": expression_to_json(&s.value),
+            }),
+        ),
+        statement::Statement::ReturnStatement(s) => node(
+            "ReturnStatement",
+            s,
+            json!({"value": expression_to_json(&s.return_value)}),
+        ),
+        statement::Statement::ExpressionStatement(s) => node(
+            "ExpressionStatement",
+            s,
+            json!({"expression": expression_to_json(&s.expression)}),
+        ),
+        statement::Statement::ImportStatement(s) => node(
+            "ImportStatement",
+            s,
+            json!({"path": string_to_json(&s.path), "alias": identifier_to_json(&s.alias)}),
+        ),
+        statement::Statement::ExportStatement(s) => node(
+            "ExportStatement",
+            s,
+            json!({"statement": statement_to_json(
+                &statement::Statement::LetStatement(s.statement.clone()),
+            )}),
+        ),
+        statement::Statement::ThrowStatement(s) => node(
+            "ThrowStatement",
+            s,
+            json!({"value": expression_to_json(&s.value)}),
+        ),
+    }
+}
+
+// 型注釈はソースと同じ書き方の文字列にする
+fn annotation_to_json(annotation: Option<&annotation::TypeAnnotation>) -> Value {
+    json!(annotation.map(|a| a.to_string()))
+}
+
+fn parameter_to_json(parameter: &expression::Parameter) -> Value {
+    node(
+        "Parameter",
+        parameter,
+        json!({
+            "name": identifier_to_json(&parameter.name),
+            "annotation": annotation_to_json(parameter.annotation.as_ref()),
+        }),
+    )
+}
+
+fn block_to_json(block: &statement::BlockStatement) -> Value {
+    node(
+        "BlockStatement",
+        block,
+        json!({"statements": block.statements.iter().map(statement_to_json).collect::<Vec<_>>()}),
+    )
+}
+
+fn identifier_to_json(identifier: &expression::Identifier) -> Value {
+    node("Identifier", identifier, json!({"name": identifier.name()}))
+}
+
+fn string_to_json(literal: &expression::StringLiteral) -> Value {
+    node("StringLiteral", literal, json!({"value": literal.value}))
+}
+
+fn expressions_to_json(expressions: &[expression::Expression]) -> Value {
+    Value::Array(expressions.iter().map(expression_to_json).collect())
+}
+
+fn expression_to_json(e: &expression::Expression) -> Value {
+    match e {
+        expression::Expression::Identifier(e) => identifier_to_json(e),
+        expression::Expression::IntegerLiteral(e) => {
+            node("IntegerLiteral", e, json!({"value": e.value}))
+        }
+        expression::Expression::StringLiteral(e) => string_to_json(e),
+        expression::Expression::Boolean(e) => node("Boolean", e, json!({"value": e.value})),
+        expression::Expression::PrefixExpression(e) => node(
+            "PrefixExpression",
+            e,
+            json!({"operator": e.operator, "right": expression_to_json(&e.right)}),
+        ),
+        expression::Expression::InfixExpression(e) => node(
+            "InfixExpression",
+            e,
+            json!({
+                "left": expression_to_json(&e.left),
+                "operator": e.operator,
+                "right": expression_to_json(&e.right),
+            }),
+        ),
+        expression::Expression::IfExpression(e) => node(
+            "IfExpression",
+            e,
+            json!({
+                "condition": expression_to_json(&e.condition),
+                "consequence": block_to_json(&e.consequence),
+                "alternative": e.alternative.as_ref().map(block_to_json),
+            }),
+        ),
+        expression::Expression::FunctionLiteral(e) => node(
+            "FunctionLiteral",
+            e,
+            json!({
+                "parameters": e.parameters.iter().map(parameter_to_json).collect::<Vec<_>>(),
+                "return_type": annotation_to_json(e.return_type.as_ref()),
+                "body": block_to_json(&e.body),
+            }),
+        ),
+        expression::Expression::CallExpression(e) => node(
+            "CallExpression",
+            e,
+            json!({
+                "function": expression_to_json(&e.function),
+                "arguments": expressions_to_json(&e.arguments),
+            }),
+        ),
+        expression::Expression::ArrayLiteral(e) => node(
+            "ArrayLiteral",
+            e,
+            json!({"elements": expressions_to_json(&e.elements)}),
+        ),
+        expression::Expression::IndexExpression(e) => node(
+            "IndexExpression",
+            e,
+            json!({"left": expression_to_json(&e.left), "index": expression_to_json(&e.index)}),
+        ),
+        expression::Expression::HashLiteral(e) => node(
+            "HashLiteral",
+            e,
+            json!({
+                "pairs": e.pairs.iter().map(|(k, v)| json!({
+                    "key": expression_to_json(k),
+                    "value": expression_to_json(v),
+                })).collect::<Vec<_>>(),
+            }),
+        ),
+        expression::Expression::MemberExpression(e) => node(
+            "MemberExpression",
+            e,
+            json!({"object": expression_to_json(&e.object), "member": identifier_to_json(&e.member)}),
+        ),
+        expression::Expression::TryExpression(e) => node(
+            "TryExpression",
+            e,
+            json!({
+                "block": block_to_json(&e.block),
+                "catch": e.catch.as_ref().map(|c| node(
+                    "CatchClause",
+                    &**c,
+                    json!({
+                        "parameter": identifier_to_json(&c.parameter),
+                        "body": block_to_json(&c.body),
+                    }),
+                )),
+                "finally": e.finally.as_ref().map(block_to_json),
+            }),
+        ),
+    }
+}
+
+pub fn from_json(value: &Value) -> Result<Program, FromJsonError> {
+    let r = Reader {
+        value,
+        path: "$".to_string(),
+    };
+    r.expect_kind("Program")?;
+    Ok(Program {
+        statements: r.list("statements", |r| r.statement())?,
+    })
+}
+
+fn token(typ: token::TokenType, literal: &str) -> token::Token {
+    token::Token::new(typ, Bytes::from(literal.to_string()))
+}
+
+// 位置を保持しながら JSON を読む
+struct Reader<'a> {
+    value: &'a Value,
+    path: String,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: impl Into<String>) -> FromJsonError {
+        FromJsonError {
+            path: self.path.clone(),
+            message: message.into(),
+        }
+    }
+
+    fn field(&self, name: &str) -> Result<Reader<'a>, FromJsonError> {
+        match self.value.get(name) {
+            Some(value) => Ok(Reader {
+                value,
+                path: format!("{}.{}", self.path, name),
+            }),
+            None => Err(self.error(format!("missing field {:?}", name))),
+        }
+    }
+
+    fn list<T>(
+        &self,
+        name: &str,
+        f: impl Fn(&Reader<'a>) -> Result<T, FromJsonError>,
+    ) -> Result<Vec<T>, FromJsonError> {
+        let field = self.field(name)?;
+        let items = field
+            .value
+            .as_array()
+            .ok_or_else(|| field.error("expected array"))?;
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                f(&Reader {
+                    value,
+                    path: format!("{}[{}]", field.path, i),
+                })
+            })
+            .collect()
+    }
+
+    fn str(&self, name: &str) -> Result<&'a str, FromJsonError> {
+        let field = self.field(name)?;
+        field
+            .value
+            .as_str()
+            .ok_or_else(|| field.error("expected string"))
+    }
+
+    fn kind(&self) -> Result<&'a str, FromJsonError> {
+        self.str("kind")
+    }
+
+    fn expect_kind(&self, kind: &str) -> Result<(), FromJsonError> {
+        match self.kind()? {
+            k if k == kind => Ok(()),
+            k => Err(self.error(format!("expected {}, got {}", kind, k))),
+        }
+    }
+
+    fn statement(&self) -> Result<statement::Statement, FromJsonError> {
+        Ok(match self.kind()? {
+            "LetStatement" => statement::Statement::LetStatement(statement::LetStatement {
+                token: token(token::TokenType::Let, "let"),
+                name: self.field("name")?.identifier()?,
+                annotation: self.annotation("annotation")?.map(Box::new),
+                value: self.field("value")?.expression()?,
+            }),
+            "ReturnStatement" => {
+                statement::Statement::ReturnStatement(statement::ReturnStatement {
+                    token: token(token::TokenType::Retrun, "return"),
+                    return_value: self.field("value")?.expression()?,
+                })
+            }
+            "ExpressionStatement" => {
+                statement::Statement::ExpressionStatement(statement::ExpressionStatement {
+                    expression: self.field("expression")?.expression()?,
+                })
+            }
+            "ImportStatement" => {
+                statement::Statement::ImportStatement(statement::ImportStatement {
+                    token: token(token::TokenType::Import, "import"),
+                    path: self.field("path")?.string()?,
+                    alias: self.field("alias")?.identifier()?,
+                })
+            }
+            "ExportStatement" => {
+                let field = self.field("statement")?;
+                match field.statement()? {
+                    statement::Statement::LetStatement(s) => {
+                        statement::Statement::ExportStatement(statement::ExportStatement {
+                            token: token(token::TokenType::Export, "export"),
+                            statement: s,
+                        })
+                    }
+                    _ => return Err(field.error("expected LetStatement")),
+                }
+            }
+            "ThrowStatement" => statement::Statement::ThrowStatement(statement::ThrowStatement {
+                token: token(token::TokenType::Throw, "throw"),
+                value: self.field("value")?.expression()?,
+            }),
+            kind => return Err(self.error(format!("unknown statement kind {}", kind))),
+        })
+    }
+
+    fn block(&self) -> Result<statement::BlockStatement, FromJsonError> {
+        self.expect_kind("BlockStatement")?;
+        Ok(statement::BlockStatement {
+            token: token(token::TokenType::Lbrace, "{"),
+            statements: self.list("statements", |r| r.statement())?,
+            rbrace: token(token::TokenType::Rbrace, "}"),
+        })
+    }
+
+    fn identifier(&self) -> Result<expression::Identifier, FromJsonError> {
+        self.expect_kind("Identifier")?;
+        let name = self.str("name")?;
+        // lexer が識別子として読める名前でなければ、出力したときに別の木になってしまう
+        let valid = !name.is_empty()
+            && name.bytes().all(|b| b.is_ascii_alphabetic() || b == b'_')
+            && crate::lexer::tokenize(name)
+                .map(|t| t.len() == 1 && t[0].typ == token::TokenType::Ident)
+                .unwrap_or(false);
+        if !valid {
+            return Err(self.field("name")?.error("invalid identifier"));
+        }
+        Ok(expression::Identifier::new(token(
+            token::TokenType::Ident,
+            name,
+        )))
+    }
+
+    fn string(&self) -> Result<expression::StringLiteral, FromJsonError> {
+        self.expect_kind("StringLiteral")?;
+        let value = self.str("value")?;
+        Ok(expression::StringLiteral {
+            token: token(token::TokenType::String, value),
+            value: value.to_string(),
+        })
+    }
+
+    fn catch(&self) -> Result<expression::CatchClause, FromJsonError> {
+        self.expect_kind("CatchClause")?;
+        Ok(expression::CatchClause {
+            token: token(token::TokenType::Catch, "catch"),
+            parameter: self.field("parameter")?.identifier()?,
+            body: self.field("body")?.block()?,
+        })
+    }
+
+    fn parameter(&self) -> Result<expression::Parameter, FromJsonError> {
+        self.expect_kind("Parameter")?;
+        Ok(expression::Parameter {
+            name: self.field("name")?.identifier()?,
+            annotation: self.annotation("annotation")?,
+        })
+    }
+
+    // 省略されているか null なら注釈なし
+    fn annotation(&self, name: &str) -> Result<Option<annotation::TypeAnnotation>, FromJsonError> {
+        match self.value.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(_) => {
+                let field = self.field(name)?;
+                let source = self.str(name)?;
+                parser::parse_type_annotation(source)
+                    .map(Some)
+                    .map_err(|_| field.error("invalid type annotation"))
+            }
+        }
+    }
+
+    fn operator(&self, prefix: bool) -> Result<(token::TokenType, String), FromJsonError> {
+        let operator = self.str("operator")?;
+        let typ = match (prefix, operator) {
+            (true, "!") => token::TokenType::Bang,
+            (true, "-") | (false, "-") => token::TokenType::Minus,
+            (false, "+") => token::TokenType::Plus,
+            (false, "*") => token::TokenType::Asterisk,
+            (false, "/") => token::TokenType::Slash,
+            (false, "<") => token::TokenType::Lt,
+            (false, ">") => token::TokenType::Gt,
+            (false, "==") => token::TokenType::Eq,
+            (false, "!=") => token::TokenType::NotEq,
+            _ => {
+                return Err(self
+                    .field("operator")?
+                    .error(format!("unknown operator {:?}", operator)))
+            }
+        };
+        Ok((typ, operator.to_string()))
+    }
+
+    fn boxed(&self, name: &str) -> Result<Box<expression::Expression>, FromJsonError> {
+        Ok(Box::new(self.field(name)?.expression()?))
+    }
+
+    fn expression(&self) -> Result<expression::Expression, FromJsonError> {
+        Ok(match self.kind()? {
+            "Identifier" => expression::Expression::Identifier(self.identifier()?),
+            "IntegerLiteral" => {
+                let field = self.field("value")?;
+                let value = field
+                    .value
+                    .as_i64()
+                    .filter(|v| *v >= 0)
+                    .ok_or_else(|| field.error("expected non-negative integer"))?;
+                expression::Expression::IntegerLiteral(expression::IntegerLiteral {
+                    token: token(token::TokenType::Int, &value.to_string()),
+                    value,
+                })
+            }
+            "StringLiteral" => expression::Expression::StringLiteral(self.string()?),
+            "Boolean" => {
+                let field = self.field("value")?;
+                let value = field
+                    .value
+                    .as_bool()
+                    .ok_or_else(|| field.error("expected boolean"))?;
+                let typ = if value {
+                    token::TokenType::True
+                } else {
+                    token::TokenType::False
+                };
+                expression::Expression::Boolean(expression::Boolean {
+                    token: token(typ, &value.to_string()),
+                    value,
+                })
+            }
+            "PrefixExpression" => {
+                let (typ, operator) = self.operator(true)?;
+                expression::Expression::PrefixExpression(expression::PrefixExpression {
+                    token: token(typ, &operator),
+                    operator,
+                    right: self.boxed("right")?,
+                })
+            }
+            "InfixExpression" => {
+                let (typ, operator) = self.operator(false)?;
+                expression::Expression::InfixExpression(expression::InfixExpression {
+                    token: token(typ, &operator),
+                    left: self.boxed("left")?,
+                    operator,
+                    right: self.boxed("right")?,
+                })
+            }
+            "IfExpression" => {
+                let alternative = match self.value.get("alternative") {
+                    None | Some(Value::Null) => None,
+                    Some(_) => Some(self.field("alternative")?.block()?),
+                };
+                expression::Expression::IfExpression(expression::IfExpression {
+                    token: token(token::TokenType::If, "if"),
+                    condition: self.boxed("condition")?,
+                    consequence: self.field("consequence")?.block()?,
+                    alternative,
+                })
+            }
+            "FunctionLiteral" => {
+                expression::Expression::FunctionLiteral(expression::FunctionLiteral {
+                    token: token(token::TokenType::Function, "fn"),
+                    parameters: self.list("parameters", |r| r.parameter())?,
+                    return_type: self.annotation("return_type")?,
+                    body: self.field("body")?.block()?,
+                })
+            }
+            "CallExpression" => {
+                expression::Expression::CallExpression(expression::CallExpression {
+                    token: token(token::TokenType::Lparne, "("),
+                    function: self.boxed("function")?,
+                    arguments: self.list("arguments", |r| r.expression())?,
+                    rparen: token(token::TokenType::Rparne, ")"),
+                })
+            }
+            "ArrayLiteral" => expression::Expression::ArrayLiteral(expression::ArrayLiteral {
+                token: token(token::TokenType::Lbracket, "["),
+                elements: self.list("elements", |r| r.expression())?,
+                rbracket: token(token::TokenType::Rbracket, "]"),
+            }),
+            "IndexExpression" => {
+                expression::Expression::IndexExpression(expression::IndexExpression {
+                    token: token(token::TokenType::Lbracket, "["),
+                    left: self.boxed("left")?,
+                    index: self.boxed("index")?,
+                    rbracket: token(token::TokenType::Rbracket, "]"),
+                })
+            }
+            "HashLiteral" => expression::Expression::HashLiteral(expression::HashLiteral {
+                token: token(token::TokenType::Lbrace, "{"),
+                pairs: self.list("pairs", |r| {
+                    Ok((
+                        r.field("key")?.expression()?,
+                        r.field("value")?.expression()?,
+                    ))
+                })?,
+                rbrace: token(token::TokenType::Rbrace, "}"),
+            }),
+            "MemberExpression" => {
+                expression::Expression::MemberExpression(expression::MemberExpression {
+                    token: token(token::TokenType::Dot, "."),
+                    object: self.boxed("object")?,
+                    member: self.field("member")?.identifier()?,
+                })
+            }
+            "TryExpression" => {
+                let catch = match self.value.get("catch") {
+                    None | Some(Value::Null) => None,
+                    Some(_) => Some(self.field("catch")?.catch()?),
+                };
+                let finally = match self.value.get("finally") {
+                    None | Some(Value::Null) => None,
+                    Some(_) => Some(self.field("finally")?.block()?),
+                };
+                if catch.is_none() && finally.is_none() {
+                    return Err(self.error("expected catch or finally"));
+                }
+                expression::Expression::TryExpression(expression::TryExpression {
+                    token: token(token::TokenType::Try, "try"),
+                    block: self.field("block")?.block()?,
+                    catch: catch.map(Box::new),
+                    finally,
+                })
+            }
+            kind => return Err(self.error(format!("unknown expression kind {}", kind))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{from_json, to_json, FromJsonError};
+    use crate::parser;
+
+    #[test]
+    fn test_to_json() {
+        let program = parser::parse("let x = -a + 10;").unwrap();
+        let value = to_json(&program);
+        assert_eq!(value["kind"], "Program");
+        let stmt = &value["statements"][0];
+        assert_eq!(stmt["kind"], "LetStatement");
+        assert_eq!(stmt["name"]["name"], "x");
+        assert_eq!(
+            stmt["span"]["start"],
+            json!({"offset": 0, "line": 1, "column": 1})
+        );
+        assert_eq!(
+            stmt["span"]["end"],
+            json!({"offset": 15, "line": 1, "column": 16})
+        );
+        let infix = &stmt["value"];
+        assert_eq!(infix["kind"], "InfixExpression");
+        assert_eq!(infix["operator"], "+");
+        assert_eq!(infix["left"]["kind"], "PrefixExpression");
+        assert_eq!(infix["left"]["right"]["name"], "a");
+        assert_eq!(infix["right"]["value"], 10);
+        assert_eq!(infix["span"]["start"]["offset"], 8);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = r#"let f: fn(int, any) -> [str] = fn(a: int, b) -> [str] { if (a < b) { return [a, b][0]; } else { {"k": !true}["k"] } };
+        f(1, "two\n");
+        import "lib/a.mk" as a; export let b = a.c(1).d;
+        try { throw 1; } catch (e) { e } finally { 2 }; try { 3 } finally { 4 };"#;
+        let program = parser::parse(input).unwrap();
+        let value = to_json(&program);
+        let decoded = from_json(&value).unwrap();
+        assert_eq!(decoded, program);
+        assert_eq!(decoded.to_string(), program.to_string());
+
+        // 文字列を経由しても同じ
+        let text = serde_json::to_string(&value).unwrap();
+        let decoded = from_json(&serde_json::from_str(&text).unwrap()).unwrap();
+        assert_eq!(decoded, program);
+    }
+
+    #[test]
+    fn test_from_json_without_span() {
+        let value = json!({
+            "kind": "Program",
+            "statements": [{
+                "kind": "ExpressionStatement",
+                "expression": {
+                    "kind": "CallExpression",
+                    "function": {"kind": "Identifier", "name": "puts"},
+                    "arguments": [{"kind": "StringLiteral", "value": "hi"}],
+                },
+            }],
+        });
+        assert_eq!(from_json(&value).unwrap().to_string(), r#"puts("hi");"#);
+    }
+
+    #[test]
+    fn test_from_json_error() {
+        for (value, path, message) in [
+            (json!({"kind": "Block"}), "$", "expected Program, got Block"),
+            (
+                json!({"kind": "Program", "statements": [{"kind": "ReturnStatement"}]}),
+                "$.statements[0]",
+                "missing field \"value\"",
+            ),
+            (
+                json!({"kind": "Program", "statements": [{"kind": "LetStatement",
+                    "name": {"kind": "Identifier", "name": "let"},
+                    "value": {"kind": "Boolean", "value": true}}]}),
+                "$.statements[0].name.name",
+                "invalid identifier",
+            ),
+            (
+                json!({"kind": "Program", "statements": [{"kind": "ExpressionStatement",
+                    "expression": {"kind": "InfixExpression", "operator": "%",
+                        "left": {"kind": "IntegerLiteral", "value": 1},
+                        "right": {"kind": "IntegerLiteral", "value": 2}}}]}),
+                "$.statements[0].expression.operator",
+                "unknown operator \"%\"",
+            ),
+            (
+                json!({"kind": "Program", "statements": [{"kind": "LetStatement",
+                    "name": {"kind": "Identifier", "name": "x"}, "annotation": "integer",
+                    "value": {"kind": "IntegerLiteral", "value": 1}}]}),
+                "$.statements[0].annotation",
+                "invalid type annotation",
+            ),
+            (
+                json!({"kind": "Program", "statements": [{"kind": "ExpressionStatement",
+                    "expression": {"kind": "TryExpression",
+                        "block": {"kind": "BlockStatement", "statements": []}}}]}),
+                "$.statements[0].expression",
+                "expected catch or finally",
+            ),
+        ] {
+            assert_eq!(
+                from_json(&value).unwrap_err(),
+                FromJsonError {
+                    path: path.to_string(),
+                    message: message.to_string(),
+                }
+            );
+        }
+    }
+}
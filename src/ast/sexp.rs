@@ -0,0 +1,271 @@
+// AST を S 式で出力する
+// 文ごとに 1 行にし、ノードは `(種類 子...)` の形にする
+use super::{annotation::TypeAnnotation, expression, statement, Program};
+
+pub fn to_sexp(program: &Program) -> String {
+    let mut out = String::from("(program");
+    for s in &program.statements {
+        out.push_str("\n  ");
+        write_statement(&mut out, s);
+    }
+    out.push(')');
+    out
+}
+
+fn write_statement(out: &mut String, s: &statement::Statement) {
+    match s {
+        statement::Statement::LetStatement(s) => write_let(out, s),
+        statement::Statement::ReturnStatement(s) => {
+            out.push_str("(return ");
+            write_expression(out, &s.return_value);
+        }
+        statement::Statement::ExpressionStatement(s) => {
+            out.push_str("(expr ");
+            write_expression(out, &s.expression);
+        }
+        statement::Statement::ImportStatement(s) => {
+            out.push_str(&format!("(import {} {}", s.path, s.alias.name()));
+        }
+        statement::Statement::ExportStatement(s) => {
+            out.push_str("(export ");
+            write_let(out, &s.statement);
+            out.push(')');
+        }
+        statement::Statement::ThrowStatement(s) => {
+            out.push_str("(throw ");
+            write_expression(out, &s.value);
+        }
+    }
+    out.push(')');
+}
+
+// 閉じ括弧は呼び出し側でつける
+fn write_let(out: &mut String, s: &statement::LetStatement) {
+    out.push_str("(let ");
+    write_name(out, &s.name, s.annotation.as_deref());
+    out.push(' ');
+    write_expression(out, &s.value);
+}
+
+// 型注釈があれば `(: name type)` にする
+fn write_name(
+    out: &mut String,
+    name: &expression::Identifier,
+    annotation: Option<&TypeAnnotation>,
+) {
+    match annotation {
+        Some(annotation) => {
+            out.push_str(&format!("(: {} ", name.name()));
+            write_annotation(out, annotation);
+            out.push(')');
+        }
+        None => out.push_str(name.name()),
+    }
+}
+
+fn write_annotation(out: &mut String, annotation: &TypeAnnotation) {
+    match annotation {
+        TypeAnnotation::Named(_) => out.push_str(&annotation.to_string()),
+        TypeAnnotation::Array { element, .. } => {
+            out.push_str("(array ");
+            write_annotation(out, element);
+            out.push(')');
+        }
+        TypeAnnotation::Hash { key, value, .. } => {
+            out.push_str("(hash ");
+            write_annotation(out, key);
+            out.push(' ');
+            write_annotation(out, value);
+            out.push(')');
+        }
+        TypeAnnotation::Function {
+            parameters,
+            return_type,
+            ..
+        } => {
+            out.push_str("(fn (");
+            for (i, parameter) in parameters.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_annotation(out, parameter);
+            }
+            out.push_str(") ");
+            write_annotation(out, return_type);
+            out.push(')');
+        }
+    }
+}
+
+fn write_block(out: &mut String, block: &statement::BlockStatement) {
+    out.push_str("(block");
+    for s in &block.statements {
+        out.push(' ');
+        write_statement(out, s);
+    }
+    out.push(')');
+}
+
+fn write_list(out: &mut String, head: &str, items: &[expression::Expression]) {
+    out.push('(');
+    out.push_str(head);
+    for item in items {
+        out.push(' ');
+        write_expression(out, item);
+    }
+    out.push(')');
+}
+
+fn write_expression(out: &mut String, e: &expression::Expression) {
+    match e {
+        expression::Expression::Identifier(e) => {
+            out.push_str(&format!("(ident {})", e.name()));
+        }
+        expression::Expression::IntegerLiteral(e) => out.push_str(&format!("(int {})", e.value)),
+        // 文字列のエスケープは Display と同じにする
+        expression::Expression::StringLiteral(e) => out.push_str(&format!("(string {})", e)),
+        expression::Expression::Boolean(e) => out.push_str(&format!("(bool {})", e.value)),
+        expression::Expression::PrefixExpression(e) => {
+            out.push_str(&format!("(prefix {} ", e.operator));
+            write_expression(out, &e.right);
+            out.push(')');
+        }
+        expression::Expression::InfixExpression(e) => {
+            out.push_str(&format!("(infix {} ", e.operator));
+            write_expression(out, &e.left);
+            out.push(' ');
+            write_expression(out, &e.right);
+            out.push(')');
+        }
+        expression::Expression::IfExpression(e) => {
+            out.push_str("(if ");
+            write_expression(out, &e.condition);
+            out.push(' ');
+            write_block(out, &e.consequence);
+            if let Some(alternative) = &e.alternative {
+                out.push(' ');
+                write_block(out, alternative);
+            }
+            out.push(')');
+        }
+        expression::Expression::FunctionLiteral(e) => {
+            out.push_str("(fn (");
+            for (i, parameter) in e.parameters.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_name(out, &parameter.name, parameter.annotation.as_ref());
+            }
+            out.push_str(") ");
+            if let Some(return_type) = &e.return_type {
+                out.push_str("(-> ");
+                write_annotation(out, return_type);
+                out.push_str(") ");
+            }
+            write_block(out, &e.body);
+            out.push(')');
+        }
+        expression::Expression::CallExpression(e) => {
+            out.push_str("(call ");
+            write_expression(out, &e.function);
+            for argument in &e.arguments {
+                out.push(' ');
+                write_expression(out, argument);
+            }
+            out.push(')');
+        }
+        expression::Expression::ArrayLiteral(e) => write_list(out, "array", &e.elements),
+        expression::Expression::IndexExpression(e) => {
+            out.push_str("(index ");
+            write_expression(out, &e.left);
+            out.push(' ');
+            write_expression(out, &e.index);
+            out.push(')');
+        }
+        expression::Expression::HashLiteral(e) => {
+            out.push_str("(hash");
+            for (key, value) in &e.pairs {
+                out.push_str(" (");
+                write_expression(out, key);
+                out.push(' ');
+                write_expression(out, value);
+                out.push(')');
+            }
+            out.push(')');
+        }
+        expression::Expression::MemberExpression(e) => {
+            out.push_str("(member ");
+            write_expression(out, &e.object);
+            out.push_str(&format!(" {})", e.member.name()));
+        }
+        // `(try (block ...) (catch e (block ...)) (finally (block ...)))`
+        expression::Expression::TryExpression(e) => {
+            out.push_str("(try ");
+            write_block(out, &e.block);
+            if let Some(catch) = &e.catch {
+                out.push_str(&format!(" (catch {} ", catch.parameter.name()));
+                write_block(out, &catch.body);
+                out.push(')');
+            }
+            if let Some(finally) = &e.finally {
+                out.push_str(" (finally ");
+                write_block(out, finally);
+                out.push(')');
+            }
+            out.push(')');
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_sexp;
+    use crate::parser;
+
+    #[test]
+    fn test_annotations() {
+        let program =
+            parser::parse("let f: fn(int) -> [str] = fn(a: {str: int}, b) -> bool { true };")
+                .unwrap();
+        assert_eq!(
+            to_sexp(&program),
+            "(program\n  (let (: f (fn (int) (array str))) (fn ((: a (hash str int)) b) (-> bool) (block (expr (bool true))))))"
+        );
+    }
+
+    #[test]
+    fn test_modules() {
+        let program =
+            parser::parse(r#"import "lib.mk" as lib; export let x = lib.f(1).y;"#).unwrap();
+        assert_eq!(
+            to_sexp(&program),
+            r#"(program
+  (import "lib.mk" lib)
+  (export (let x (member (call (member (ident lib) f) (int 1)) y))))"#
+        );
+    }
+
+    #[test]
+    fn test_try() {
+        let program =
+            parser::parse("try { throw error(1); } catch (e) { e } finally { 2 }").unwrap();
+        assert_eq!(
+            to_sexp(&program),
+            "(program\n  (expr (try (block (throw (call (ident error) (int 1)))) (catch e (block (expr (ident e)))) (finally (block (expr (int 2)))))))"
+        );
+    }
+
+    #[test]
+    fn test_to_sexp() {
+        let program = parser::parse(
+            r#"let add = fn(a, b) { a + b }; if (!ok) { add(1, -2) } else { [x[0], {"k\n": true}] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            to_sexp(&program),
+            r#"(program
+  (let add (fn (a b) (block (expr (infix + (ident a) (ident b))))))
+  (expr (if (prefix ! (ident ok)) (block (expr (call (ident add) (int 1) (prefix - (int 2))))) (block (expr (array (index (ident x) (int 0)) (hash ((string "k\n") (bool true)))))))))"#
+        );
+    }
+}
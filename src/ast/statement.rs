@@ -1,85 +1,214 @@
+use std::fmt::{self, Display};
+
 use crate::token;
 
-use super::expression;
+use super::{annotation, expression, Node};
 
-#[derive(Debug)]
-pub(crate) enum Statement {
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
     LetStatement(LetStatement),
     ReturnStatement(ReturnStatement),
+    ExpressionStatement(ExpressionStatement),
+    ImportStatement(ImportStatement),
+    ExportStatement(ExportStatement),
+    ThrowStatement(ThrowStatement),
 }
-impl super::Node for Statement {
+impl Node for Statement {
     fn token_literal(&self) -> &str {
         match self {
             Statement::LetStatement(let_statement) => let_statement.token_literal(),
             Statement::ReturnStatement(return_statement) => return_statement.token_literal(),
+            Statement::ExpressionStatement(expression_statement) => {
+                expression_statement.token_literal()
+            }
+            Statement::ImportStatement(import_statement) => import_statement.token_literal(),
+            Statement::ExportStatement(export_statement) => export_statement.token_literal(),
+            Statement::ThrowStatement(throw_statement) => throw_statement.token_literal(),
         }
     }
-    fn string(&self) -> String {
+    fn span(&self) -> token::Span {
         match self {
-            Statement::LetStatement(let_statement) => let_statement.string(),
-            Statement::ReturnStatement(return_statement) => return_statement.string(),
+            Statement::LetStatement(let_statement) => let_statement.span(),
+            Statement::ReturnStatement(return_statement) => return_statement.span(),
+            Statement::ExpressionStatement(expression_statement) => expression_statement.span(),
+            Statement::ImportStatement(import_statement) => import_statement.span(),
+            Statement::ExportStatement(export_statement) => export_statement.span(),
+            Statement::ThrowStatement(throw_statement) => throw_statement.span(),
+        }
+    }
+}
+impl Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::LetStatement(let_statement) => let_statement.fmt(f),
+            Statement::ReturnStatement(return_statement) => return_statement.fmt(f),
+            Statement::ExpressionStatement(expression_statement) => expression_statement.fmt(f),
+            Statement::ImportStatement(import_statement) => import_statement.fmt(f),
+            Statement::ExportStatement(export_statement) => export_statement.fmt(f),
+            Statement::ThrowStatement(throw_statement) => throw_statement.fmt(f),
         }
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct LetStatement {
-    pub(crate) token: token::Token,
-    pub(crate) name: expression::Identifier,
-    pub(crate) value: Option<expression::Expression>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct LetStatement {
+    pub token: token::Token,
+    pub name: expression::Identifier,
+    pub annotation: Option<Box<annotation::TypeAnnotation>>,
+    pub value: expression::Expression,
 }
 
-impl super::Node for LetStatement {
+impl Node for LetStatement {
     fn token_literal(&self) -> &str {
         std::str::from_utf8(&self.token.literal[..]).unwrap()
     }
-    fn string(&self) -> String {
-        format!(
-            "{} {} = {};",
-            self.token_literal(),
-            self.name.string(),
-            self.value
-                .as_ref()
-                .map_or_else(|| "".to_string(), |v| v.string())
-        )
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.value.span())
+    }
+}
+impl Display for LetStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.token_literal(), self.name)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ": {}", annotation)?;
+        }
+        write!(f, " = {};", self.value)
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct ReturnStatement {
-    pub(crate) token: token::Token,
-    pub(crate) return_value: Option<expression::Expression>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReturnStatement {
+    pub token: token::Token,
+    pub return_value: expression::Expression,
 }
 
-impl super::Node for ReturnStatement {
+impl Node for ReturnStatement {
     fn token_literal(&self) -> &str {
         std::str::from_utf8(&self.token.literal[..]).unwrap()
     }
-    fn string(&self) -> String {
-        format!(
-            "{} {}",
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.return_value.span())
+    }
+}
+impl Display for ReturnStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {};", self.token_literal(), self.return_value)
+    }
+}
+
+// `throw value;`。値は一番近い catch まで評価を打ち切って届く
+#[derive(Debug, PartialEq, Clone)]
+pub struct ThrowStatement {
+    pub token: token::Token,
+    pub value: expression::Expression,
+}
+
+impl Node for ThrowStatement {
+    fn token_literal(&self) -> &str {
+        std::str::from_utf8(&self.token.literal[..]).unwrap()
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.value.span())
+    }
+}
+impl Display for ThrowStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {};", self.token_literal(), self.value)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExpressionStatement {
+    pub expression: expression::Expression,
+}
+
+impl Node for ExpressionStatement {
+    fn token_literal(&self) -> &str {
+        self.expression.token_literal()
+    }
+    fn span(&self) -> token::Span {
+        self.expression.span()
+    }
+}
+impl Display for ExpressionStatement {
+    // 次の文が `(` で始まると呼び出しとして読まれてしまうので、必ず `;` をつける
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};", self.expression)
+    }
+}
+
+// `import "path" as name;`。トップレベルにだけ書ける
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImportStatement {
+    pub token: token::Token,
+    pub path: expression::StringLiteral,
+    pub alias: expression::Identifier,
+}
+
+impl Node for ImportStatement {
+    fn token_literal(&self) -> &str {
+        std::str::from_utf8(&self.token.literal[..]).unwrap()
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.alias.span())
+    }
+}
+impl Display for ImportStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} as {};",
             self.token_literal(),
-            self.return_value
-                .as_ref()
-                .map_or_else(|| "".to_string(), |v| v.string())
+            self.path,
+            self.alias
         )
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct ExpresstionStatement {
-    pub(crate) token: token::Token,
-    pub(crate) exresstion: Option<expression::Expression>,
+// `export let ...;`。トップレベルにだけ書ける
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExportStatement {
+    pub token: token::Token,
+    pub statement: LetStatement,
 }
 
-impl super::Node for ExpresstionStatement {
+impl Node for ExportStatement {
     fn token_literal(&self) -> &str {
         std::str::from_utf8(&self.token.literal[..]).unwrap()
     }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.statement.span())
+    }
+}
+impl Display for ExportStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.token_literal(), self.statement)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BlockStatement {
+    // `{` の token
+    pub token: token::Token,
+    pub statements: Vec<Statement>,
+    // `}` の token
+    pub rbrace: token::Token,
+}
 
-    fn string(&self) -> String {
-        self.exresstion
-            .as_ref()
-            .map_or_else(|| "".to_string(), |v| v.string())
+impl Node for BlockStatement {
+    fn token_literal(&self) -> &str {
+        std::str::from_utf8(&self.token.literal[..]).unwrap()
+    }
+    fn span(&self) -> token::Span {
+        self.token.span().to(self.rbrace.span())
+    }
+}
+impl Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{ ")?;
+        for statement in &self.statements {
+            write!(f, "{} ", statement)?;
+        }
+        f.write_str("}")
     }
 }
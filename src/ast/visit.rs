@@ -0,0 +1,615 @@
+// AST を走査するための trait
+// 必要なノードの visit_* だけを上書きし、子の走査は walk_* に任せる
+// 上書きした visit_* の中で walk_* を呼ばなければ、その子は走査されない
+use super::{expression, statement, Program};
+
+pub trait Visitor<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program)
+    }
+    fn visit_statement(&mut self, statement: &'ast statement::Statement) {
+        walk_statement(self, statement)
+    }
+    fn visit_let_statement(&mut self, statement: &'ast statement::LetStatement) {
+        walk_let_statement(self, statement)
+    }
+    fn visit_return_statement(&mut self, statement: &'ast statement::ReturnStatement) {
+        walk_return_statement(self, statement)
+    }
+    fn visit_expression_statement(&mut self, statement: &'ast statement::ExpressionStatement) {
+        walk_expression_statement(self, statement)
+    }
+    fn visit_import_statement(&mut self, statement: &'ast statement::ImportStatement) {
+        walk_import_statement(self, statement)
+    }
+    fn visit_export_statement(&mut self, statement: &'ast statement::ExportStatement) {
+        walk_export_statement(self, statement)
+    }
+    fn visit_throw_statement(&mut self, statement: &'ast statement::ThrowStatement) {
+        walk_throw_statement(self, statement)
+    }
+    fn visit_block_statement(&mut self, block: &'ast statement::BlockStatement) {
+        walk_block_statement(self, block)
+    }
+    fn visit_expression(&mut self, expression: &'ast expression::Expression) {
+        walk_expression(self, expression)
+    }
+    fn visit_identifier(&mut self, _identifier: &'ast expression::Identifier) {}
+    fn visit_integer_literal(&mut self, _literal: &'ast expression::IntegerLiteral) {}
+    fn visit_string_literal(&mut self, _literal: &'ast expression::StringLiteral) {}
+    fn visit_boolean(&mut self, _boolean: &'ast expression::Boolean) {}
+    fn visit_prefix_expression(&mut self, expression: &'ast expression::PrefixExpression) {
+        walk_prefix_expression(self, expression)
+    }
+    fn visit_infix_expression(&mut self, expression: &'ast expression::InfixExpression) {
+        walk_infix_expression(self, expression)
+    }
+    fn visit_if_expression(&mut self, expression: &'ast expression::IfExpression) {
+        walk_if_expression(self, expression)
+    }
+    fn visit_function_literal(&mut self, function: &'ast expression::FunctionLiteral) {
+        walk_function_literal(self, function)
+    }
+    fn visit_call_expression(&mut self, expression: &'ast expression::CallExpression) {
+        walk_call_expression(self, expression)
+    }
+    fn visit_array_literal(&mut self, array: &'ast expression::ArrayLiteral) {
+        walk_array_literal(self, array)
+    }
+    fn visit_index_expression(&mut self, expression: &'ast expression::IndexExpression) {
+        walk_index_expression(self, expression)
+    }
+    fn visit_hash_literal(&mut self, hash: &'ast expression::HashLiteral) {
+        walk_hash_literal(self, hash)
+    }
+    fn visit_member_expression(&mut self, expression: &'ast expression::MemberExpression) {
+        walk_member_expression(self, expression)
+    }
+    fn visit_try_expression(&mut self, expression: &'ast expression::TryExpression) {
+        walk_try_expression(self, expression)
+    }
+    fn visit_catch_clause(&mut self, catch: &'ast expression::CatchClause) {
+        walk_catch_clause(self, catch)
+    }
+}
+
+pub fn walk_program<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, program: &'ast Program) {
+    for statement in &program.statements {
+        v.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    statement: &'ast statement::Statement,
+) {
+    match statement {
+        statement::Statement::LetStatement(s) => v.visit_let_statement(s),
+        statement::Statement::ReturnStatement(s) => v.visit_return_statement(s),
+        statement::Statement::ExpressionStatement(s) => v.visit_expression_statement(s),
+        statement::Statement::ImportStatement(s) => v.visit_import_statement(s),
+        statement::Statement::ExportStatement(s) => v.visit_export_statement(s),
+        statement::Statement::ThrowStatement(s) => v.visit_throw_statement(s),
+    }
+}
+
+pub fn walk_let_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    statement: &'ast statement::LetStatement,
+) {
+    v.visit_identifier(&statement.name);
+    v.visit_expression(&statement.value);
+}
+
+pub fn walk_return_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    statement: &'ast statement::ReturnStatement,
+) {
+    v.visit_expression(&statement.return_value);
+}
+
+pub fn walk_expression_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    statement: &'ast statement::ExpressionStatement,
+) {
+    v.visit_expression(&statement.expression);
+}
+
+pub fn walk_import_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    statement: &'ast statement::ImportStatement,
+) {
+    v.visit_string_literal(&statement.path);
+    v.visit_identifier(&statement.alias);
+}
+
+pub fn walk_export_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    statement: &'ast statement::ExportStatement,
+) {
+    v.visit_let_statement(&statement.statement);
+}
+
+pub fn walk_throw_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    statement: &'ast statement::ThrowStatement,
+) {
+    v.visit_expression(&statement.value);
+}
+
+pub fn walk_block_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    block: &'ast statement::BlockStatement,
+) {
+    for statement in &block.statements {
+        v.visit_statement(statement);
+    }
+}
+
+pub fn walk_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::Expression,
+) {
+    match expression {
+        expression::Expression::Identifier(e) => v.visit_identifier(e),
+        expression::Expression::IntegerLiteral(e) => v.visit_integer_literal(e),
+        expression::Expression::StringLiteral(e) => v.visit_string_literal(e),
+        expression::Expression::Boolean(e) => v.visit_boolean(e),
+        expression::Expression::PrefixExpression(e) => v.visit_prefix_expression(e),
+        expression::Expression::InfixExpression(e) => v.visit_infix_expression(e),
+        expression::Expression::IfExpression(e) => v.visit_if_expression(e),
+        expression::Expression::FunctionLiteral(e) => v.visit_function_literal(e),
+        expression::Expression::CallExpression(e) => v.visit_call_expression(e),
+        expression::Expression::ArrayLiteral(e) => v.visit_array_literal(e),
+        expression::Expression::IndexExpression(e) => v.visit_index_expression(e),
+        expression::Expression::HashLiteral(e) => v.visit_hash_literal(e),
+        expression::Expression::MemberExpression(e) => v.visit_member_expression(e),
+        expression::Expression::TryExpression(e) => v.visit_try_expression(e),
+    }
+}
+
+pub fn walk_prefix_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::PrefixExpression,
+) {
+    v.visit_expression(&expression.right);
+}
+
+pub fn walk_infix_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::InfixExpression,
+) {
+    v.visit_expression(&expression.left);
+    v.visit_expression(&expression.right);
+}
+
+pub fn walk_if_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::IfExpression,
+) {
+    v.visit_expression(&expression.condition);
+    v.visit_block_statement(&expression.consequence);
+    if let Some(alternative) = &expression.alternative {
+        v.visit_block_statement(alternative);
+    }
+}
+
+pub fn walk_function_literal<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    function: &'ast expression::FunctionLiteral,
+) {
+    for parameter in &function.parameters {
+        v.visit_identifier(&parameter.name);
+    }
+    v.visit_block_statement(&function.body);
+}
+
+pub fn walk_call_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::CallExpression,
+) {
+    v.visit_expression(&expression.function);
+    for argument in &expression.arguments {
+        v.visit_expression(argument);
+    }
+}
+
+pub fn walk_array_literal<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    array: &'ast expression::ArrayLiteral,
+) {
+    for element in &array.elements {
+        v.visit_expression(element);
+    }
+}
+
+pub fn walk_index_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::IndexExpression,
+) {
+    v.visit_expression(&expression.left);
+    v.visit_expression(&expression.index);
+}
+
+pub fn walk_hash_literal<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    hash: &'ast expression::HashLiteral,
+) {
+    for (key, value) in &hash.pairs {
+        v.visit_expression(key);
+        v.visit_expression(value);
+    }
+}
+
+// member は名前の参照ではないので visit_identifier しない
+pub fn walk_member_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::MemberExpression,
+) {
+    v.visit_expression(&expression.object);
+}
+
+pub fn walk_try_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    expression: &'ast expression::TryExpression,
+) {
+    v.visit_block_statement(&expression.block);
+    if let Some(catch) = &expression.catch {
+        v.visit_catch_clause(catch);
+    }
+    if let Some(finally) = &expression.finally {
+        v.visit_block_statement(finally);
+    }
+}
+
+pub fn walk_catch_clause<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    catch: &'ast expression::CatchClause,
+) {
+    v.visit_identifier(&catch.parameter);
+    v.visit_block_statement(&catch.body);
+}
+
+// AST を書き換えながら走査するための trait
+// visit_expression_mut で `*expression = ...` とすれば式ごと置き換えられる
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+    fn visit_statement_mut(&mut self, statement: &mut statement::Statement) {
+        walk_statement_mut(self, statement)
+    }
+    fn visit_let_statement_mut(&mut self, statement: &mut statement::LetStatement) {
+        walk_let_statement_mut(self, statement)
+    }
+    fn visit_return_statement_mut(&mut self, statement: &mut statement::ReturnStatement) {
+        walk_return_statement_mut(self, statement)
+    }
+    fn visit_expression_statement_mut(&mut self, statement: &mut statement::ExpressionStatement) {
+        walk_expression_statement_mut(self, statement)
+    }
+    fn visit_import_statement_mut(&mut self, statement: &mut statement::ImportStatement) {
+        walk_import_statement_mut(self, statement)
+    }
+    fn visit_export_statement_mut(&mut self, statement: &mut statement::ExportStatement) {
+        walk_export_statement_mut(self, statement)
+    }
+    fn visit_throw_statement_mut(&mut self, statement: &mut statement::ThrowStatement) {
+        walk_throw_statement_mut(self, statement)
+    }
+    fn visit_block_statement_mut(&mut self, block: &mut statement::BlockStatement) {
+        walk_block_statement_mut(self, block)
+    }
+    fn visit_expression_mut(&mut self, expression: &mut expression::Expression) {
+        walk_expression_mut(self, expression)
+    }
+    fn visit_identifier_mut(&mut self, _identifier: &mut expression::Identifier) {}
+    fn visit_integer_literal_mut(&mut self, _literal: &mut expression::IntegerLiteral) {}
+    fn visit_string_literal_mut(&mut self, _literal: &mut expression::StringLiteral) {}
+    fn visit_boolean_mut(&mut self, _boolean: &mut expression::Boolean) {}
+    fn visit_prefix_expression_mut(&mut self, expression: &mut expression::PrefixExpression) {
+        walk_prefix_expression_mut(self, expression)
+    }
+    fn visit_infix_expression_mut(&mut self, expression: &mut expression::InfixExpression) {
+        walk_infix_expression_mut(self, expression)
+    }
+    fn visit_if_expression_mut(&mut self, expression: &mut expression::IfExpression) {
+        walk_if_expression_mut(self, expression)
+    }
+    fn visit_function_literal_mut(&mut self, function: &mut expression::FunctionLiteral) {
+        walk_function_literal_mut(self, function)
+    }
+    fn visit_call_expression_mut(&mut self, expression: &mut expression::CallExpression) {
+        walk_call_expression_mut(self, expression)
+    }
+    fn visit_array_literal_mut(&mut self, array: &mut expression::ArrayLiteral) {
+        walk_array_literal_mut(self, array)
+    }
+    fn visit_index_expression_mut(&mut self, expression: &mut expression::IndexExpression) {
+        walk_index_expression_mut(self, expression)
+    }
+    fn visit_hash_literal_mut(&mut self, hash: &mut expression::HashLiteral) {
+        walk_hash_literal_mut(self, hash)
+    }
+    fn visit_member_expression_mut(&mut self, expression: &mut expression::MemberExpression) {
+        walk_member_expression_mut(self, expression)
+    }
+    fn visit_try_expression_mut(&mut self, expression: &mut expression::TryExpression) {
+        walk_try_expression_mut(self, expression)
+    }
+    fn visit_catch_clause_mut(&mut self, catch: &mut expression::CatchClause) {
+        walk_catch_clause_mut(self, catch)
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(v: &mut V, program: &mut Program) {
+    for statement in &mut program.statements {
+        v.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(v: &mut V, statement: &mut statement::Statement) {
+    match statement {
+        statement::Statement::LetStatement(s) => v.visit_let_statement_mut(s),
+        statement::Statement::ReturnStatement(s) => v.visit_return_statement_mut(s),
+        statement::Statement::ExpressionStatement(s) => v.visit_expression_statement_mut(s),
+        statement::Statement::ImportStatement(s) => v.visit_import_statement_mut(s),
+        statement::Statement::ExportStatement(s) => v.visit_export_statement_mut(s),
+        statement::Statement::ThrowStatement(s) => v.visit_throw_statement_mut(s),
+    }
+}
+
+pub fn walk_let_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    statement: &mut statement::LetStatement,
+) {
+    v.visit_identifier_mut(&mut statement.name);
+    v.visit_expression_mut(&mut statement.value);
+}
+
+pub fn walk_return_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    statement: &mut statement::ReturnStatement,
+) {
+    v.visit_expression_mut(&mut statement.return_value);
+}
+
+pub fn walk_expression_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    statement: &mut statement::ExpressionStatement,
+) {
+    v.visit_expression_mut(&mut statement.expression);
+}
+
+pub fn walk_import_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    statement: &mut statement::ImportStatement,
+) {
+    v.visit_string_literal_mut(&mut statement.path);
+    v.visit_identifier_mut(&mut statement.alias);
+}
+
+pub fn walk_export_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    statement: &mut statement::ExportStatement,
+) {
+    v.visit_let_statement_mut(&mut statement.statement);
+}
+
+pub fn walk_throw_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    statement: &mut statement::ThrowStatement,
+) {
+    v.visit_expression_mut(&mut statement.value);
+}
+
+pub fn walk_block_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    block: &mut statement::BlockStatement,
+) {
+    for statement in &mut block.statements {
+        v.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::Expression,
+) {
+    match expression {
+        expression::Expression::Identifier(e) => v.visit_identifier_mut(e),
+        expression::Expression::IntegerLiteral(e) => v.visit_integer_literal_mut(e),
+        expression::Expression::StringLiteral(e) => v.visit_string_literal_mut(e),
+        expression::Expression::Boolean(e) => v.visit_boolean_mut(e),
+        expression::Expression::PrefixExpression(e) => v.visit_prefix_expression_mut(e),
+        expression::Expression::InfixExpression(e) => v.visit_infix_expression_mut(e),
+        expression::Expression::IfExpression(e) => v.visit_if_expression_mut(e),
+        expression::Expression::FunctionLiteral(e) => v.visit_function_literal_mut(e),
+        expression::Expression::CallExpression(e) => v.visit_call_expression_mut(e),
+        expression::Expression::ArrayLiteral(e) => v.visit_array_literal_mut(e),
+        expression::Expression::IndexExpression(e) => v.visit_index_expression_mut(e),
+        expression::Expression::HashLiteral(e) => v.visit_hash_literal_mut(e),
+        expression::Expression::MemberExpression(e) => v.visit_member_expression_mut(e),
+        expression::Expression::TryExpression(e) => v.visit_try_expression_mut(e),
+    }
+}
+
+pub fn walk_prefix_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::PrefixExpression,
+) {
+    v.visit_expression_mut(&mut expression.right);
+}
+
+pub fn walk_infix_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::InfixExpression,
+) {
+    v.visit_expression_mut(&mut expression.left);
+    v.visit_expression_mut(&mut expression.right);
+}
+
+pub fn walk_if_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::IfExpression,
+) {
+    v.visit_expression_mut(&mut expression.condition);
+    v.visit_block_statement_mut(&mut expression.consequence);
+    if let Some(alternative) = &mut expression.alternative {
+        v.visit_block_statement_mut(alternative);
+    }
+}
+
+pub fn walk_function_literal_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    function: &mut expression::FunctionLiteral,
+) {
+    for parameter in &mut function.parameters {
+        v.visit_identifier_mut(&mut parameter.name);
+    }
+    v.visit_block_statement_mut(&mut function.body);
+}
+
+pub fn walk_call_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::CallExpression,
+) {
+    v.visit_expression_mut(&mut expression.function);
+    for argument in &mut expression.arguments {
+        v.visit_expression_mut(argument);
+    }
+}
+
+pub fn walk_array_literal_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    array: &mut expression::ArrayLiteral,
+) {
+    for element in &mut array.elements {
+        v.visit_expression_mut(element);
+    }
+}
+
+pub fn walk_index_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::IndexExpression,
+) {
+    v.visit_expression_mut(&mut expression.left);
+    v.visit_expression_mut(&mut expression.index);
+}
+
+pub fn walk_hash_literal_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    hash: &mut expression::HashLiteral,
+) {
+    for (key, value) in &mut hash.pairs {
+        v.visit_expression_mut(key);
+        v.visit_expression_mut(value);
+    }
+}
+
+pub fn walk_member_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::MemberExpression,
+) {
+    v.visit_expression_mut(&mut expression.object);
+}
+
+pub fn walk_try_expression_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    expression: &mut expression::TryExpression,
+) {
+    v.visit_block_statement_mut(&mut expression.block);
+    if let Some(catch) = &mut expression.catch {
+        v.visit_catch_clause_mut(catch);
+    }
+    if let Some(finally) = &mut expression.finally {
+        v.visit_block_statement_mut(finally);
+    }
+}
+
+pub fn walk_catch_clause_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    catch: &mut expression::CatchClause,
+) {
+    v.visit_identifier_mut(&mut catch.parameter);
+    v.visit_block_statement_mut(&mut catch.body);
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::{Visitor, VisitorMut};
+    use crate::ast::{expression, Program};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::token;
+
+    fn parse(input: &str) -> Program {
+        let mut p = Parser::new(Lexer::new(input.to_string()));
+        let program = p.parse_program();
+        assert!(p.errors().is_empty());
+        program
+    }
+
+    #[test]
+    fn test_visitor() {
+        // 使われている識別子を集める。関数の引数は数えない
+        struct Identifiers<'ast> {
+            names: Vec<&'ast str>,
+        }
+        impl<'ast> Visitor<'ast> for Identifiers<'ast> {
+            fn visit_identifier(&mut self, identifier: &'ast expression::Identifier) {
+                self.names.push(identifier.name());
+            }
+            fn visit_function_literal(&mut self, function: &'ast expression::FunctionLiteral) {
+                self.visit_block_statement(&function.body);
+            }
+        }
+
+        let program = parse(
+            r#"let f = fn(x) { x + y }; if (f(a)) { [b, c[d]] } else { {e: -g} }; return h;"#,
+        );
+        let mut v = Identifiers { names: Vec::new() };
+        v.visit_program(&program);
+        assert_eq!(
+            v.names,
+            vec!["f", "x", "y", "f", "a", "b", "c", "d", "e", "g", "h"]
+        );
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        // 整数リテラルをすべて 2 倍にし、`!true` を `false` に置き換える
+        struct Rewrite;
+        impl VisitorMut for Rewrite {
+            fn visit_integer_literal_mut(&mut self, literal: &mut expression::IntegerLiteral) {
+                literal.value *= 2;
+            }
+            fn visit_expression_mut(&mut self, expr: &mut expression::Expression) {
+                super::walk_expression_mut(self, expr);
+                if let expression::Expression::PrefixExpression(p) = expr {
+                    if let expression::Expression::Boolean(b) = p.right.as_ref() {
+                        let value = !b.value;
+                        let typ = if value {
+                            token::TokenType::True
+                        } else {
+                            token::TokenType::False
+                        };
+                        *expr = expression::Expression::Boolean(expression::Boolean {
+                            token: token::Token::new(typ, Bytes::from(value.to_string())),
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut program = parse("let a = [1, 2 * 3]; fn(x) { if (!true) { x + 4 } };");
+        Rewrite.visit_program_mut(&mut program);
+        assert_eq!(
+            program.to_string(),
+            "let a = [2, (4 * 6)];\nfn(x) { if (false) { (x + 8); }; };"
+        );
+    }
+}
@@ -0,0 +1,838 @@
+use std::io::{self, IsTerminal};
+
+use crate::{
+    ast, evaluator, formatter, highlight, lint, lsp, object, optimizer, parser, repl, resolver,
+    token, types,
+};
+
+const USAGE: &str =
+    "usage: interpreter-rs [ast [--format json|sexp] [FILE] | fmt [--check] [FILE...] \
+| lint [--format human|json] [--disable RULE] [--only RULE] [FILE] | check [FILE] \
+| run [--opt-level 0|1|2] [--allow-read=DIR] [--allow-write=DIR] [--allow-env] [--allow-args] [FILE [ARGS...]] \
+| lsp | highlight [--format ansi|html|textmate] [FILE]]";
+
+// コマンドライン引数（プログラム名を除く）を解釈して実行し、終了コードを返す
+// 引数がなければ REPL を起動する
+pub fn run<R: io::Read, W: io::Write, E: io::Write>(
+    args: &[String],
+    mut stdin: R,
+    mut stdout: W,
+    mut stderr: E,
+) -> i32 {
+    let result = match args.split_first() {
+        None => {
+            // 端末なら行編集と履歴を使う
+            if io::stdin().is_terminal() && io::stdout().is_terminal() {
+                repl::start_interactive(stdout);
+            } else {
+                repl::start(stdin, stdout);
+            }
+            Ok(0)
+        }
+        Some((command, rest)) if command == "ast" => {
+            ast_command(rest, &mut stdin, &mut stdout, &mut stderr)
+        }
+        Some((command, rest)) if command == "fmt" => {
+            fmt_command(rest, &mut stdin, &mut stdout, &mut stderr)
+        }
+        Some((command, rest)) if command == "check" => {
+            check_command(rest, &mut stdin, &mut stdout, &mut stderr)
+        }
+        Some((command, rest)) if command == "lint" => {
+            lint_command(rest, &mut stdin, &mut stdout, &mut stderr)
+        }
+        Some((command, rest)) if command == "run" => {
+            run_command(rest, &mut stdin, &mut stdout, &mut stderr)
+        }
+        Some((command, rest)) if command == "highlight" => {
+            highlight_command(rest, &mut stdin, &mut stdout, &mut stderr)
+        }
+        Some((command, rest)) if command == "lsp" && rest.is_empty() => {
+            lsp::serve(io::BufReader::new(&mut stdin), &mut stdout)
+        }
+        Some(_) => {
+            let _ = writeln!(stderr, "{}", USAGE);
+            Ok(2)
+        }
+    };
+    result.unwrap_or_else(|e| {
+        let _ = writeln!(stderr, "error: {}", e);
+        1
+    })
+}
+
+// FILE が省略されたか `-` なら標準入力から読む
+fn read_source<R: io::Read>(path: Option<&String>, stdin: &mut R) -> io::Result<String> {
+    match path {
+        Some(path) if path != "-" => std::fs::read_to_string(path),
+        _ => {
+            let mut buf = String::new();
+            stdin.read_to_string(&mut buf)?;
+            Ok(buf)
+        }
+    }
+}
+
+// parse に失敗したらエラーを出力して None を返す
+fn parse_source<E: io::Write>(source: &str, stderr: &mut E) -> Option<ast::Program> {
+    match parser::parse(source) {
+        Ok(program) => Some(program),
+        Err(errors) => {
+            for e in errors {
+                let _ = writeln!(stderr, "error[{}]: {}", e.code(), e);
+            }
+            None
+        }
+    }
+}
+
+// 解決や型検査のエラーのメッセージは位置から始まるので、位置を別に出すときは取り除く
+fn without_position(message: &str, span: token::Span) -> &str {
+    message
+        .strip_prefix(&format!("{}: ", span.start))
+        .unwrap_or(message)
+}
+
+fn ast_command<R: io::Read, W: io::Write, E: io::Write>(
+    args: &[String],
+    stdin: &mut R,
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut format = "json";
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(|s| s.as_str()) {
+                Some(f @ ("json" | "sexp")) => format = f,
+                _ => {
+                    writeln!(stderr, "--format must be json or sexp")?;
+                    return Ok(2);
+                }
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                writeln!(stderr, "{}", USAGE)?;
+                return Ok(2);
+            }
+        }
+    }
+
+    let source = read_source(path, stdin)?;
+    let program = match parse_source(&source, stderr) {
+        Some(program) => program,
+        None => return Ok(1),
+    };
+    match format {
+        "json" => {
+            let json = serde_json::to_string_pretty(&ast::json::to_json(&program))?;
+            writeln!(stdout, "{}", json)?;
+        }
+        _ => writeln!(stdout, "{}", ast::sexp::to_sexp(&program))?,
+    }
+    Ok(0)
+}
+
+// FILE を整形して書き換える。FILE がなければ標準入力を整形して標準出力に書く
+// --check のときは書き換えず、整形されていないものがあれば 1 を返す
+fn fmt_command<R: io::Read, W: io::Write, E: io::Write>(
+    args: &[String],
+    stdin: &mut R,
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if let Some(arg) = paths.iter().find(|arg| arg.starts_with("--")) {
+        writeln!(stderr, "unknown option {}\n{}", arg, USAGE)?;
+        return Ok(2);
+    }
+
+    if paths.is_empty() {
+        let source = read_source(None, stdin)?;
+        let formatted = match format_source(&source, stderr) {
+            Some(formatted) => formatted,
+            None => return Ok(1),
+        };
+        if check {
+            return Ok(if formatted == source { 0 } else { 1 });
+        }
+        stdout.write_all(formatted.as_bytes())?;
+        return Ok(0);
+    }
+
+    // 読み書きできないファイルがあっても残りのファイルは整形する
+    let mut code = 0;
+    for path in paths {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                writeln!(stderr, "error: cannot read {}: {}", path, e)?;
+                code = 1;
+                continue;
+            }
+        };
+        let formatted = match format_source(&source, stderr) {
+            Some(formatted) => formatted,
+            None => {
+                code = 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            writeln!(stdout, "{} is not formatted", path)?;
+            code = 1;
+        } else if let Err(e) = std::fs::write(path, formatted) {
+            writeln!(stderr, "error: cannot write {}: {}", path, e)?;
+            code = 1;
+        }
+    }
+    Ok(code)
+}
+
+// 指摘があれば 1 を返す
+// --disable は指定したルールを無効にし、--only は指定したルールだけを有効にする
+fn lint_command<R: io::Read, W: io::Write, E: io::Write>(
+    args: &[String],
+    stdin: &mut R,
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut json = false;
+    let mut config = lint::Config::default();
+    let mut only = false;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(|s| s.as_str()) {
+                Some("human") => json = false,
+                Some("json") => json = true,
+                _ => {
+                    writeln!(stderr, "--format must be human or json")?;
+                    return Ok(2);
+                }
+            },
+            option @ ("--disable" | "--only") => {
+                let rule = match args.next().map(|s| s.parse::<lint::Rule>()) {
+                    Some(Ok(rule)) => rule,
+                    Some(Err(e)) => {
+                        writeln!(stderr, "{}", e)?;
+                        return Ok(2);
+                    }
+                    None => {
+                        writeln!(stderr, "{} requires a rule name", option)?;
+                        return Ok(2);
+                    }
+                };
+                config = if option == "--disable" {
+                    config.disable(rule)
+                } else if only {
+                    config.enable(rule)
+                } else {
+                    only = true;
+                    lint::Config::none().enable(rule)
+                };
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                writeln!(stderr, "{}", USAGE)?;
+                return Ok(2);
+            }
+        }
+    }
+
+    let source = read_source(path, stdin)?;
+    let program = match parse_source(&source, stderr) {
+        Some(program) => program,
+        None => return Ok(1),
+    };
+    let diagnostics = lint::lint(&program, &config);
+    if json {
+        let json = serde_json::to_string_pretty(&lint::to_json(&diagnostics))?;
+        writeln!(stdout, "{}", json)?;
+    } else {
+        let name = path.map_or("-", |path| path.as_str());
+        for d in &diagnostics {
+            writeln!(stdout, "{}:{}", name, d)?;
+        }
+    }
+    Ok(if diagnostics.is_empty() { 0 } else { 1 })
+}
+
+// 未定義の識別子と型の誤りを実行せずに報告する。エラーがあれば 1 を返す
+fn check_command<R: io::Read, W: io::Write, E: io::Write>(
+    args: &[String],
+    stdin: &mut R,
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    if args.len() > 1 {
+        writeln!(stderr, "{}", USAGE)?;
+        return Ok(2);
+    }
+    let path = args.first();
+    let source = read_source(path, stdin)?;
+    let program = match parse_source(&source, stderr) {
+        Some(program) => program,
+        None => return Ok(1),
+    };
+    let arena = ast::arena::Arena::from_program(&program);
+    let resolution = resolver::resolve(&arena, &evaluator::BUILTINS);
+    let typing = types::check(&arena);
+    let mut errors: Vec<(token::Span, &str, String)> = resolution
+        .errors()
+        .iter()
+        .map(|e| (e.span(), e.code(), e.to_string()))
+        .chain(
+            typing
+                .errors()
+                .iter()
+                .map(|e| (e.span(), e.code(), e.to_string())),
+        )
+        .collect();
+    errors.sort_by_key(|(span, _, _)| span.start.offset);
+
+    let name = path.map_or("-", |path| path.as_str());
+    for (span, code, message) in &errors {
+        writeln!(
+            stdout,
+            "{}:{}: error[{}]: {}",
+            name,
+            span.start,
+            code,
+            without_position(message, *span)
+        )?;
+    }
+    Ok(if errors.is_empty() { 0 } else { 1 })
+}
+
+// 未定義の識別子があれば実行せずに 1 を返す。最後の値が null でなければ出力する
+// FILE より後の引数はスクリプトに渡す。ファイルと環境変数と引数は --allow-* で許可したものだけ使える
+fn run_command<R: io::Read, W: io::Write, E: io::Write>(
+    args: &[String],
+    stdin: &mut R,
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut level = 1;
+    let mut capabilities = evaluator::Capabilities::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(dir) = arg.strip_prefix("--allow-read=") {
+            capabilities = capabilities.allow_read(dir);
+            continue;
+        }
+        if let Some(dir) = arg.strip_prefix("--allow-write=") {
+            capabilities = capabilities.allow_write(dir);
+            continue;
+        }
+        match arg.as_str() {
+            "--allow-env" => capabilities = capabilities.allow_env(),
+            "--allow-args" => capabilities = capabilities.allow_args(),
+            "--opt-level" => match args.next().map(|s| s.parse::<u8>()) {
+                Some(Ok(n)) if n <= optimizer::MAX_LEVEL => level = n,
+                _ => {
+                    writeln!(
+                        stderr,
+                        "--opt-level must be between 0 and {}",
+                        optimizer::MAX_LEVEL
+                    )?;
+                    return Ok(2);
+                }
+            },
+            // 綴りを間違えた許可をファイル名として読まないようにする
+            _ if arg.starts_with("--") => {
+                writeln!(stderr, "unknown option {}\n{}", arg, USAGE)?;
+                return Ok(2);
+            }
+            _ => {
+                path = Some(arg);
+                break;
+            }
+        }
+    }
+    let script_args = args.cloned().collect();
+
+    let source = read_source(path, stdin)?;
+    let mut program = match parse_source(&source, stderr) {
+        Some(program) => program,
+        None => return Ok(1),
+    };
+    let resolution = resolver::resolve(
+        &ast::arena::Arena::from_program(&program),
+        &evaluator::BUILTINS,
+    );
+    if !resolution.errors().is_empty() {
+        // 実行時エラーと同じ形で出す
+        for e in resolution.errors() {
+            let message = e.to_string();
+            writeln!(
+                stderr,
+                "error[{}]: {}\n    at {}",
+                e.code(),
+                without_position(&message, e.span()),
+                e.span().start
+            )?;
+        }
+        return Ok(1);
+    }
+
+    optimizer::optimize(&mut program, level);
+    // import はファイルのディレクトリから探す。標準入力ならカレントディレクトリから
+    let file = path.map(std::path::Path::new);
+    // puts や read_line もこのコマンドの入出力を使う
+    let context = evaluator::Context::new(capabilities, script_args)
+        .with_stdin(io::BufReader::new(&mut *stdin))
+        .with_stdout(&mut *stdout)
+        .with_stderr(&mut *stderr);
+    let mut loader = evaluator::Loader::with_context(context);
+    let result = loader.eval_program(&program, &object::Environment::new(), file);
+    let (span, trace) = (loader.span(), loader.trace().to_vec());
+    drop(loader);
+    match result {
+        Ok(object::Object::Null) => Ok(0),
+        Ok(object) => {
+            writeln!(stdout, "{}", object)?;
+            Ok(0)
+        }
+        // エラーになった位置と、呼び出し中だった関数を内側から順に出す
+        Err(e) => {
+            writeln!(stderr, "error[{}]: {}", e.code(), e)?;
+            if let Some(span) = span {
+                writeln!(stderr, "    at {}", span.start)?;
+            }
+            for line in evaluator::trace_lines(&trace) {
+                writeln!(stderr, "    {}", line)?;
+            }
+            Ok(1)
+        }
+    }
+}
+
+// textmate は FILE を読まずに文法を出力する
+fn highlight_command<R: io::Read, W: io::Write, E: io::Write>(
+    args: &[String],
+    stdin: &mut R,
+    stdout: &mut W,
+    stderr: &mut E,
+) -> io::Result<i32> {
+    let mut format = "ansi";
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().map(|s| s.as_str()) {
+                Some(f @ ("ansi" | "html" | "textmate")) => format = f,
+                _ => {
+                    writeln!(stderr, "--format must be ansi, html or textmate")?;
+                    return Ok(2);
+                }
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                writeln!(stderr, "{}", USAGE)?;
+                return Ok(2);
+            }
+        }
+    }
+
+    if format == "textmate" {
+        let grammar = serde_json::to_string_pretty(&highlight::textmate_grammar())?;
+        writeln!(stdout, "{}", grammar)?;
+        return Ok(0);
+    }
+    let source = read_source(path, stdin)?;
+    let highlights = highlight::classify_with_ast(&source);
+    let out = match format {
+        "html" => highlight::to_html(&source, &highlights),
+        _ => highlight::to_ansi(&source, &highlights),
+    };
+    stdout.write_all(out.as_bytes())?;
+    Ok(0)
+}
+
+fn format_source<E: io::Write>(source: &str, stderr: &mut E) -> Option<String> {
+    match formatter::format(source) {
+        Ok(formatted) => Some(formatted),
+        Err(formatter::FormatError::Parse(errors)) => {
+            for e in errors {
+                let _ = writeln!(stderr, "error[{}]: {}", e.code(), e);
+            }
+            None
+        }
+        Err(e) => {
+            let _ = writeln!(stderr, "error[{}]: {}", e.code(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::run;
+
+    fn run_with(args: &[&str], stdin: &str) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let code = run(&args, stdin.as_bytes(), &mut stdout, &mut stderr);
+        (
+            code,
+            String::from_utf8(stdout).unwrap(),
+            String::from_utf8(stderr).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_ast_command() {
+        let (code, out, _) = run_with(&["ast", "--format", "sexp"], "let x = 1 + 2;");
+        assert_eq!(code, 0);
+        assert_eq!(out, "(program\n  (let x (infix + (int 1) (int 2))))\n");
+
+        let (code, out, _) = run_with(&["ast"], "x;");
+        assert_eq!(code, 0);
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["statements"][0]["expression"]["name"], "x");
+
+        let (code, _, err) = run_with(&["ast", "--format", "xml"], "");
+        assert_eq!((code, err.as_str()), (2, "--format must be json or sexp\n"));
+
+        let (code, out, err) = run_with(&["ast", "-"], "let = 1;");
+        assert_eq!(code, 1);
+        assert_eq!(out, "");
+        assert!(err.starts_with("error[E0101]: expected next token to be Ident"));
+    }
+
+    #[test]
+    fn test_fmt_command() {
+        let (code, out, _) = run_with(&["fmt"], "let x=1");
+        assert_eq!((code, out.as_str()), (0, "let x = 1;\n"));
+        let (code, out, _) = run_with(&["fmt", "--check"], "let x=1");
+        assert_eq!((code, out.as_str()), (1, ""));
+        let (code, _, _) = run_with(&["fmt", "--check"], "let x = 1;\n");
+        assert_eq!(code, 0);
+
+        let dir = std::env::temp_dir().join(format!("interpreter-rs-fmt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.monkey");
+        std::fs::write(&path, "f( 1 ,2 )").unwrap();
+        let path = path.to_str().unwrap();
+
+        let (code, out, _) = run_with(&["fmt", "--check", path], "");
+        assert_eq!((code, out), (1, format!("{} is not formatted\n", path)));
+        assert_eq!(run_with(&["fmt", path], "").0, 0);
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "f(1, 2);\n");
+        assert_eq!(
+            run_with(&["fmt", "--check", path], ""),
+            (0, String::new(), String::new())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (code, _, err) = run_with(&["fmt", "--diff"], "");
+        assert_eq!(code, 2);
+        assert!(err.starts_with("unknown option --diff"));
+    }
+
+    #[test]
+    fn test_fmt_unreadable_file() {
+        // 読めないファイルを報告して、続くファイルも整形し、最後に 1 で終わる
+        let dir = std::env::temp_dir().join(format!(
+            "interpreter-rs-fmt-unreadable-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let path = dir.join("a.monkey");
+        std::fs::write(&path, "f( 1 ,2 )").unwrap();
+        let path = path.to_str().unwrap();
+        let missing = dir.join("missing.monkey");
+        let missing = missing.to_str().unwrap();
+        let sub = dir.join("sub");
+        let sub = sub.to_str().unwrap();
+
+        let (code, out, err) = run_with(&["fmt", missing, sub, path], "");
+        assert_eq!((code, out.as_str()), (1, ""));
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", err);
+        assert!(lines[0].starts_with(&format!("error: cannot read {}: ", missing)));
+        assert!(lines[1].starts_with(&format!("error: cannot read {}: ", sub)));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "f(1, 2);\n");
+
+        // --check でも残りのファイルを調べる
+        std::fs::write(path, "f( 1 ,2 )").unwrap();
+        let (code, out, _) = run_with(&["fmt", "--check", missing, path], "");
+        assert_eq!((code, out), (1, format!("{} is not formatted\n", path)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lint_command() {
+        let input = "let x = 1; if (true) { 0 };";
+        let (code, out, _) = run_with(&["lint"], input);
+        assert_eq!(code, 1);
+        assert_eq!(
+            out,
+            "-:1:5: unused-let: `x` is never used\n-:1:16: constant-condition: condition `true` is constant\n"
+        );
+
+        let (code, out, _) = run_with(
+            &["lint", "--disable", "unused-let", "--format", "json"],
+            input,
+        );
+        assert_eq!(code, 1);
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value[0]["rule"], "constant-condition");
+        assert_eq!(value.as_array().unwrap().len(), 1);
+
+        let (code, out, _) = run_with(&["lint", "--only", "shadowing"], input);
+        assert_eq!((code, out.as_str()), (0, ""));
+
+        let (code, _, err) = run_with(&["lint", "--disable", "typo"], input);
+        assert_eq!((code, err.as_str()), (2, "unknown lint rule \"typo\"\n"));
+    }
+
+    #[test]
+    fn test_check_command() {
+        let (code, out, _) = run_with(&["check"], "let x: int = true; y;");
+        assert_eq!(code, 1);
+        assert_eq!(
+            out,
+            "-:1:14: error[E0301]: type mismatch: expected int, found bool\n-:1:20: error[E0204]: undefined identifier \"y\"\n"
+        );
+        assert_eq!(run_with(&["check"], "let f = fn(a: int) { a }; f(1);").0, 0);
+    }
+
+    #[test]
+    fn test_check_forward_reference() {
+        // 囲んでいる関数やグローバルで後から宣言する名前は、未定義として報告しない
+        let input = "let outer = fn() { let f = fn() { g() }; let g = fn() { h }; f() };\nlet h = 1;\nouter();";
+        assert_eq!(
+            run_with(&["check"], input),
+            (0, String::new(), String::new())
+        );
+        assert_eq!(
+            run_with(&["run", "-"], input),
+            (0, "1\n".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        // 実行時エラーと、実行前に見つけたエラーは同じ形でコードをつけて出す
+        assert_eq!(
+            run_with(&["run", "-"], "let a = [1, 2, 3];\na[3]"),
+            (
+                1,
+                String::new(),
+                "error[E0220]: index out of range: 3 for length 3\n    at 2:3\n".to_string()
+            )
+        );
+        assert_eq!(
+            run_with(&["run", "-"], "let a = 1;\na + b"),
+            (
+                1,
+                String::new(),
+                "error[E0204]: undefined identifier \"b\"\n    at 2:5\n".to_string()
+            )
+        );
+        let (code, out, _) = run_with(&["check"], "let s = \"a\";\ns(1); 1[0];");
+        assert_eq!(code, 1);
+        assert_eq!(
+            out,
+            "-:2:1: error[E0303]: str is not a function\n-:2:7: error[E0304]: int cannot be indexed\n"
+        );
+    }
+
+    #[test]
+    fn test_highlight_command() {
+        let (code, out, _) = run_with(&["highlight"], "let x = 1;\n");
+        assert_eq!(
+            (code, out.as_str()),
+            (0, "\x1b[35mlet\x1b[0m x = \x1b[33m1\x1b[0m;\n")
+        );
+        let (_, out, _) = run_with(&["highlight", "--format", "html"], "1");
+        assert_eq!(
+            out,
+            "<pre class=\"monkey\"><span class=\"number\">1</span></pre>\n"
+        );
+        let (_, out, _) = run_with(&["highlight", "--format", "textmate"], "");
+        let grammar: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(grammar["name"], "Monkey");
+    }
+
+    #[test]
+    fn test_run_command() {
+        let input = "let double = fn(x) { x * 2 }; double(1 + 2)";
+        for level in &["0", "1", "2"] {
+            let (code, out, _) = run_with(&["run", "--opt-level", level], input);
+            assert_eq!((code, out.as_str()), (0, "6\n"), "level {}", level);
+        }
+        assert_eq!(
+            run_with(&["run"], "let x = 1;"),
+            (0, String::new(), String::new())
+        );
+
+        // 組み込みのモジュールは宣言しなくても使える
+        assert_eq!(
+            run_with(&["run"], "string.join(array.map([1, -2], math.abs), \",\")"),
+            (0, "1,2\n".to_string(), String::new())
+        );
+
+        let (code, _, err) = run_with(&["run"], "1 + true");
+        assert_eq!(
+            (code, err.as_str()),
+            (
+                1,
+                "error[E0201]: type mismatch: INTEGER + BOOLEAN\n    at 1:1\n"
+            )
+        );
+        // 関数の中のエラーは内側の呼び出しから順にトレースを出す
+        let (code, _, err) = run_with(
+            &["run", "--opt-level", "0"],
+            "let inner = fn(x) { x + true };\nlet run = fn(f) { f() };\nrun(fn() { inner(1) })",
+        );
+        assert_eq!(
+            (code, err.as_str()),
+            (
+                1,
+                "error[E0201]: type mismatch: INTEGER + BOOLEAN\n    at 1:21\n    in <anonymous> (let inner) at 3:12\n    in <anonymous> at 2:19\n    in <anonymous> (let run) at 3:1\n"
+            )
+        );
+        // 止まらない再帰はプロセスを落とさずにエラーになり、同じ呼び出しはまとめて出す
+        let (code, _, err) = run_with(
+            &["run", "--opt-level", "0"],
+            "let f = fn(n) { f(n + 1) };\nf(0)",
+        );
+        assert_eq!(
+            (code, err.as_str()),
+            (
+                1,
+                "error[E0221]: maximum call depth exceeded: 10000\n    at 1:17\n    in <anonymous> (let f) at 1:17\n    ... repeated 9998 more times\n    in <anonymous> (let f) at 2:1\n"
+            )
+        );
+
+        // 囲んでいる関数で後から宣言する関数も呼べる
+        assert_eq!(
+            run_with(
+                &["run"],
+                "let outer = fn() { let f = fn() { g() }; let g = fn() { 1 }; f() }; outer();"
+            ),
+            (0, "1\n".to_string(), String::new())
+        );
+
+        // 未定義の識別子は実行する前に報告する
+        let (code, _, err) = run_with(&["run"], "if (false) { y }");
+        assert_eq!(
+            (code, err.as_str()),
+            (1, "error[E0204]: undefined identifier \"y\"\n    at 1:14\n")
+        );
+
+        let dir = std::env::temp_dir().join(format!("interpreter-rs-run-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("lib/a.mk"), "export let inc = fn(x) { x + 1 };").unwrap();
+        std::fs::write(dir.join("main.mk"), "import \"lib/a.mk\" as a; a.inc(41)").unwrap();
+        let main = dir.join("main.mk").display().to_string();
+        let allow = format!("--allow-read={}", dir.display());
+        assert_eq!(
+            run_with(&["run", &allow, &main], ""),
+            (0, "42\n".to_string(), String::new())
+        );
+        // import するファイルも --allow-read で許可したものだけ読める
+        assert_eq!(
+            run_with(&["run", &main], ""),
+            (
+                1,
+                String::new(),
+                format!(
+                    "error[E0217]: permission denied: import needs read access to {}\n    at 1:1\n",
+                    dir.join("lib/a.mk").display()
+                )
+            )
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // スクリプトの入出力はコマンドの入出力になる
+        let dir =
+            std::env::temp_dir().join(format!("interpreter-rs-run-io-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hello = dir.join("hello.mk");
+        std::fs::write(
+            &hello,
+            "let name = read_line(); print(\"hello,\", name); puts(\"!\"); eputs(\"done\"); 1",
+        )
+        .unwrap();
+        assert_eq!(
+            run_with(&["run", hello.to_str().unwrap()], "monkey\n"),
+            (0, "hello, monkey!\n1\n".to_string(), "done\n".to_string())
+        );
+
+        // FILE より後ろはスクリプトの引数
+        let script = dir.join("copy.mk");
+        std::fs::write(
+            &script,
+            "let a = args(); write_file(a[1], read_file(a[0])); a",
+        )
+        .unwrap();
+        std::fs::write(dir.join("in.txt"), "hello").unwrap();
+        let script = script.display().to_string();
+        let input = dir.join("in.txt").display().to_string();
+        let output = dir.join("out.txt").display().to_string();
+        let (code, _, err) = run_with(&["run", &script, &input, &output], "");
+        assert_eq!(
+            (code, err.as_str()),
+            (
+                1,
+                "error[E0217]: permission denied: args needs args access\n    at 1:9\n    in args at 1:9\n"
+            )
+        );
+        let (code, _, err) = run_with(&["run", "--allow-args", &script, &input, &output], "");
+        assert_eq!(
+            (code, err),
+            (
+                1,
+                format!(
+                    "error[E0217]: permission denied: read_file needs read access to {}\n    at 1:34\n    in read_file at 1:34\n",
+                    input
+                )
+            )
+        );
+        let read = format!("--allow-read={}", dir.display());
+        let write = format!("--allow-write={}", dir.display());
+        let (code, out, _) = run_with(
+            &[
+                "run",
+                &read,
+                &write,
+                "--allow-args",
+                &script,
+                &input,
+                &output,
+            ],
+            "",
+        );
+        assert_eq!((code, out), (0, format!("[{:?}, {:?}]\n", input, output)));
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "hello");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (code, _, err) = run_with(&["run", "--opt-level", "3"], "");
+        assert_eq!(
+            (code, err.as_str()),
+            (2, "--opt-level must be between 0 and 2\n")
+        );
+    }
+
+    #[test]
+    fn test_run_unknown_option() {
+        // FILE の前の未知のオプションは使い方の誤り。FILE の後ろはスクリプトの引数
+        let (code, out, err) = run_with(&["run", "--allow-wirte=/tmp", "x.mk"], "");
+        assert_eq!((code, out.as_str()), (2, ""));
+        assert!(err.starts_with("unknown option --allow-wirte=/tmp\nusage: "));
+        let (code, out, _) = run_with(&["run", "--allow-args", "-", "--verbose"], "args()");
+        assert_eq!((code, out.as_str()), (0, "[\"--verbose\"]\n"));
+    }
+}
@@ -0,0 +1,261 @@
+// 入力途中のソースとカーソル位置から補完の候補を出す
+// 名前の出どころは Source で差し替え、REPL では評価中の環境、language server では文書の解析結果を使う
+use crate::{evaluator, lexer, object, token};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Kind {
+    Keyword,
+    Variable,
+    Function,
+    Builtin,
+    // 組み込みのモジュール
+    Module,
+    // ハッシュの文字列のキー
+    Key,
+    // REPL のメタコマンド
+    Command,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Candidate {
+    pub label: String,
+    pub kind: Kind,
+}
+
+// start から cursor までを候補で置き換える
+#[derive(Debug, PartialEq, Clone)]
+pub struct Completions {
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+pub trait Source {
+    // 参照できる名前。組み込み関数も含める
+    fn names(&self) -> Vec<(String, Kind)>;
+    // name に束縛されたハッシュの文字列のキー
+    fn keys(&self, name: &str) -> Vec<String>;
+}
+
+impl Source for object::Environment {
+    fn names(&self) -> Vec<(String, Kind)> {
+        self.bindings()
+            .into_iter()
+            .map(|(name, object)| {
+                let kind = match object {
+                    object::Object::Function(_) | object::Object::Builtin(_) => Kind::Function,
+                    _ => Kind::Variable,
+                };
+                (name, kind)
+            })
+            .chain(
+                evaluator::BUILTINS
+                    .iter()
+                    .map(|name| (name.to_string(), builtin_kind(name))),
+            )
+            .collect()
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        match self.get(name) {
+            Some(object::Object::Hash(pairs)) => pairs
+                .keys()
+                .filter_map(|key| match key {
+                    object::HashKey::String(s) => Some(s.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// evaluator::BUILTINS の名前がモジュールか関数か
+pub fn builtin_kind(name: &str) -> Kind {
+    match evaluator::builtins::lookup(name) {
+        Some(object::Object::Module(_)) => Kind::Module,
+        _ => Kind::Builtin,
+    }
+}
+
+// cursor は input のバイト位置。文字の途中なら手前の境界に寄せる
+pub fn complete(input: &str, cursor: usize, source: &dyn Source) -> Completions {
+    let mut cursor = cursor.min(input.len());
+    while !input.is_char_boundary(cursor) {
+        cursor -= 1;
+    }
+    let before = &input[..cursor];
+
+    let mut l = lexer::Lexer::new(before.to_string()).keep_comments(true);
+    let last = (&mut l).last();
+    match l.state().mode {
+        // 文字列の中では `h["` の後だけキーを補完する
+        lexer::LexMode::String => {
+            return match key_context(before) {
+                Some((name, start)) => {
+                    let prefix = &before[start..];
+                    let mut keys: Vec<String> = source
+                        .keys(name)
+                        .into_iter()
+                        .filter(|key| key.starts_with(prefix))
+                        .collect();
+                    keys.sort();
+                    Completions {
+                        start,
+                        candidates: keys
+                            .into_iter()
+                            .map(|label| Candidate {
+                                label,
+                                kind: Kind::Key,
+                            })
+                            .collect(),
+                    }
+                }
+                None => empty(cursor),
+            };
+        }
+        lexer::LexMode::BlockComment => return empty(cursor),
+        lexer::LexMode::Normal => {}
+    }
+    if last.is_some_and(|t| t.typ == token::TokenType::Comment && t.end.offset == cursor) {
+        return empty(cursor);
+    }
+
+    let start = before.trim_end_matches(is_ident_char).len();
+    let prefix = &before[start..];
+    let mut candidates: Vec<Candidate> = lexer::KEYWORDS
+        .iter()
+        .map(|(keyword, _)| (keyword.to_string(), Kind::Keyword))
+        .chain(source.names())
+        .filter(|(label, _)| label.starts_with(prefix))
+        .map(|(label, kind)| Candidate { label, kind })
+        .collect();
+    candidates.sort_by(|a, b| a.label.cmp(&b.label));
+    candidates.dedup_by(|a, b| a.label == b.label);
+    Completions { start, candidates }
+}
+
+fn empty(cursor: usize) -> Completions {
+    Completions {
+        start: cursor,
+        candidates: Vec::new(),
+    }
+}
+
+// 識別子は英字と `_` だけからなる
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+// `name["partial` で終わっていれば name と partial の開始位置を返す
+fn key_context(before: &str) -> Option<(&str, usize)> {
+    let quote = before.rfind('"')?;
+    if before[quote + 1..].contains('\\') {
+        return None;
+    }
+    let target = before[..quote].trim_end().strip_suffix('[')?.trim_end();
+    let name = &target[target.trim_end_matches(is_ident_char).len()..];
+    if name.is_empty() {
+        return None;
+    }
+    Some((name, quote + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{complete, Kind, Source};
+    use crate::{evaluator, object, parser};
+
+    struct Names;
+
+    impl Source for Names {
+        fn names(&self) -> Vec<(String, Kind)> {
+            vec![
+                ("len".to_string(), Kind::Builtin),
+                ("lower".to_string(), Kind::Variable),
+            ]
+        }
+        fn keys(&self, _name: &str) -> Vec<String> {
+            Vec::new()
+        }
+    }
+
+    fn labels(input: &str, source: &dyn Source) -> (usize, Vec<String>) {
+        let completions = complete(input, input.len(), source);
+        (
+            completions.start,
+            completions
+                .candidates
+                .into_iter()
+                .map(|c| c.label)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(
+            labels("1 + l", &Names),
+            (
+                4,
+                vec!["len".to_string(), "let".to_string(), "lower".to_string()]
+            )
+        );
+        assert_eq!(labels("re", &Names), (0, vec!["return".to_string()]));
+        let completions = complete("x + le", 6, &Names);
+        assert_eq!(completions.candidates[0].kind, Kind::Builtin);
+        assert_eq!(completions.candidates[1].kind, Kind::Keyword);
+
+        // カーソルより後ろは見ない
+        assert_eq!(complete("tr + 1", 2, &Names).candidates[0].label, "true");
+        // 文字列やコメントの中では補完しない
+        assert_eq!(labels("\"le", &Names), (3, vec![]));
+        assert_eq!(labels("1 // le", &Names), (7, vec![]));
+        assert_eq!(labels("/* le", &Names), (5, vec![]));
+    }
+
+    #[test]
+    fn test_complete_from_environment() {
+        let env = object::Environment::new();
+        let program = parser::parse(
+            "let double = fn(x) { x * 2 }; let dict = {\"one\": 1, \"two\": 2, 3: 3};",
+        )
+        .unwrap();
+        evaluator::eval_program(&program, &env).unwrap();
+        let env = env.borrow();
+
+        let completions = complete("d", 1, &*env);
+        let found: Vec<(&str, Kind)> = completions
+            .candidates
+            .iter()
+            .map(|c| (c.label.as_str(), c.kind))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("dict", Kind::Variable), ("double", Kind::Function)]
+        );
+        assert_eq!(
+            complete("ma", 2, &*env).candidates[0],
+            super::Candidate {
+                label: "math".to_string(),
+                kind: Kind::Module
+            }
+        );
+        assert_eq!(
+            complete("json_p", 6, &*env).candidates[0].kind,
+            Kind::Builtin
+        );
+
+        assert_eq!(
+            labels("dict[\"", &*env),
+            (6, vec!["one".to_string(), "two".to_string()])
+        );
+        assert_eq!(
+            labels("1 + dict[ \"t", &*env),
+            (11, vec!["two".to_string()])
+        );
+        assert_eq!(complete("dict[\"t", 7, &*env).candidates[0].kind, Kind::Key);
+        // ハッシュでなければキーはない
+        assert_eq!(labels("double[\"", &*env), (8, vec![]));
+        assert_eq!(labels("[\"", &*env), (2, vec![]));
+    }
+}
@@ -0,0 +1,875 @@
+// 木を直接たどって評価する
+use std::{collections::BTreeMap, convert::TryFrom, path::Path, rc::Rc};
+
+use thiserror::Error;
+
+use crate::{
+    ast::{self, expression, statement, Node},
+    object::{Env, Environment, ErrorValue, Frame, Function, Object},
+    token,
+};
+
+pub mod builtins;
+pub mod context;
+pub mod loader;
+
+use context::Location;
+pub use context::{Capabilities, Context};
+pub use loader::Loader;
+
+// 組み込みのモジュールと関数。同じ名前を束縛すればそちらが優先される
+pub const BUILTINS: [&str; 16] = [
+    "math",
+    "string",
+    "array",
+    "hash",
+    "json_parse",
+    "json_stringify",
+    "read_file",
+    "write_file",
+    "read_line",
+    "print",
+    "puts",
+    "eputs",
+    "env",
+    "args",
+    "error",
+    "is_error",
+];
+
+#[derive(Debug, PartialEq, Error)]
+pub enum RuntimeError {
+    #[error("type mismatch: {left} {operator} {right}")]
+    TypeMismatch {
+        left: &'static str,
+        operator: String,
+        right: &'static str,
+    },
+    #[error("unknown operator: {operator}{right}")]
+    UnknownPrefixOperator {
+        operator: String,
+        right: &'static str,
+    },
+    #[error("unknown operator: {left} {operator} {right}")]
+    UnknownInfixOperator {
+        left: &'static str,
+        operator: String,
+        right: &'static str,
+    },
+    #[error("identifier not found: {name}")]
+    UndefinedIdentifier { name: String },
+    #[error("not a function: {typ}")]
+    NotCallable { typ: &'static str },
+    #[error("wrong number of arguments: want={want}, got={got}")]
+    WrongArity { want: usize, got: usize },
+    #[error("index operator not supported: {left}[{index}]")]
+    IndexNotSupported {
+        left: &'static str,
+        index: &'static str,
+    },
+    #[error("index out of range: {index} for length {length}")]
+    IndexOutOfRange { index: i64, length: usize },
+    #[error("unusable as hash key: {typ}")]
+    UnusableHashKey { typ: &'static str },
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("cannot import {path}: {reason}")]
+    ImportFailed { path: String, reason: String },
+    // a.mk -> b.mk -> a.mk の形
+    #[error("import cycle: {cycle}")]
+    ImportCycle { cycle: String },
+    #[error("module {module} has no export {name}")]
+    NoSuchExport { module: String, name: String },
+    #[error("member access not supported: {typ}")]
+    MemberNotSupported { typ: &'static str },
+    // want は "2" や "2 or 3", "at least 1" の形
+    #[error("wrong number of arguments to {function}: want {want}, got {got}")]
+    WrongBuiltinArity {
+        function: &'static str,
+        want: String,
+        got: usize,
+    },
+    #[error("argument {position} to {function} must be {want}, got {got}")]
+    WrongArgumentType {
+        function: &'static str,
+        position: usize,
+        want: &'static str,
+        got: &'static str,
+    },
+    #[error("invalid argument to {function}: {reason}")]
+    InvalidArgument {
+        function: &'static str,
+        reason: String,
+    },
+    // capability は "read access to PATH" や "env access" の形
+    #[error("permission denied: {function} needs {capability}")]
+    PermissionDenied {
+        function: &'static str,
+        capability: String,
+    },
+    #[error("{function} failed: {reason}")]
+    IoFailed {
+        function: &'static str,
+        reason: String,
+    },
+    #[error("maximum call depth exceeded: {limit}")]
+    CallDepthExceeded { limit: usize },
+    // throw した値が catch されずに一番外まで届いた
+    #[error("uncaught {0}")]
+    Thrown(Object),
+}
+
+impl RuntimeError {
+    // ドキュメントやテストで使うエラーコード。番号は変えない
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeError::TypeMismatch { .. } => "E0201",
+            RuntimeError::UnknownPrefixOperator { .. } => "E0202",
+            RuntimeError::UnknownInfixOperator { .. } => "E0203",
+            RuntimeError::UndefinedIdentifier { .. } => "E0204",
+            RuntimeError::NotCallable { .. } => "E0205",
+            RuntimeError::WrongArity { .. } => "E0206",
+            RuntimeError::IndexNotSupported { .. } => "E0207",
+            RuntimeError::UnusableHashKey { .. } => "E0208",
+            RuntimeError::DivisionByZero => "E0209",
+            RuntimeError::ImportFailed { .. } => "E0210",
+            RuntimeError::ImportCycle { .. } => "E0211",
+            RuntimeError::NoSuchExport { .. } => "E0212",
+            RuntimeError::MemberNotSupported { .. } => "E0213",
+            RuntimeError::WrongBuiltinArity { .. } => "E0214",
+            RuntimeError::WrongArgumentType { .. } => "E0215",
+            RuntimeError::InvalidArgument { .. } => "E0216",
+            RuntimeError::PermissionDenied { .. } => "E0217",
+            RuntimeError::IoFailed { .. } => "E0218",
+            RuntimeError::Thrown(_) => "E0219",
+            RuntimeError::IndexOutOfRange { .. } => "E0220",
+            RuntimeError::CallDepthExceeded { .. } => "E0221",
+        }
+    }
+
+    // catch で受け取ったエラー値の kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RuntimeError::TypeMismatch { .. }
+            | RuntimeError::UnknownPrefixOperator { .. }
+            | RuntimeError::UnknownInfixOperator { .. }
+            | RuntimeError::NotCallable { .. }
+            | RuntimeError::IndexNotSupported { .. }
+            | RuntimeError::UnusableHashKey { .. }
+            | RuntimeError::MemberNotSupported { .. }
+            | RuntimeError::WrongArgumentType { .. } => "TypeError",
+            RuntimeError::UndefinedIdentifier { .. } => "NameError",
+            RuntimeError::WrongArity { .. } | RuntimeError::WrongBuiltinArity { .. } => {
+                "ArityError"
+            }
+            RuntimeError::DivisionByZero => "ZeroDivisionError",
+            RuntimeError::IndexOutOfRange { .. } => "IndexError",
+            RuntimeError::CallDepthExceeded { .. } => "RecursionError",
+            RuntimeError::ImportFailed { .. }
+            | RuntimeError::ImportCycle { .. }
+            | RuntimeError::NoSuchExport { .. } => "ImportError",
+            RuntimeError::InvalidArgument { .. } => "ValueError",
+            RuntimeError::PermissionDenied { .. } => "PermissionError",
+            RuntimeError::IoFailed { .. } => "IoError",
+            RuntimeError::Thrown(_) => "Error",
+        }
+    }
+}
+
+// return は呼び出し元まで、エラーは catch か一番外まで評価を打ち切って戻る
+enum Unwind {
+    Return(Object),
+    Error(RuntimeError, Box<Location>),
+}
+
+impl Unwind {
+    // エラーの位置がまだなければ span にする
+    fn at(self, span: token::Span) -> Self {
+        match self {
+            Unwind::Error(e, mut location) if location.span.is_none() => {
+                location.span = Some(span);
+                Unwind::Error(e, location)
+            }
+            unwind => unwind,
+        }
+    }
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(e: RuntimeError) -> Self {
+        Unwind::Error(e, Box::default())
+    }
+}
+
+type Result<T> = std::result::Result<T, Unwind>;
+
+// 最後に評価した文の値を返す。let だけのときは null
+// import はカレントディレクトリから探す
+pub fn eval_program(
+    program: &ast::Program,
+    env: &Env,
+) -> std::result::Result<Object, RuntimeError> {
+    Loader::new().eval_program(program, env, None)
+}
+
+// トップレベルの文を評価する。import はトップレベルにしか書けないので、loader はここでだけ使う
+fn eval_module(
+    program: &ast::Program,
+    env: &Env,
+    loader: &mut Loader,
+    base: &Path,
+) -> std::result::Result<Object, RuntimeError> {
+    let mut result = Object::Null;
+    for statement in &program.statements {
+        let value = match statement {
+            statement::Statement::ImportStatement(statement) => loader
+                .load(&base.join(&statement.path.value))
+                .map(|module| {
+                    env.borrow_mut()
+                        .set(statement.alias.name(), Object::Module(module));
+                    Object::Null
+                })
+                .map_err(|e| Unwind::from(e).at(statement.span())),
+            statement => eval_statement(statement, env, loader.context()),
+        };
+        match value {
+            Ok(object) => result = object,
+            Err(Unwind::Return(object)) => return Ok(object),
+            Err(Unwind::Error(e, location)) => {
+                loader.locate(*location);
+                return Err(e);
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn eval_statements(
+    statements: &[statement::Statement],
+    env: &Env,
+    ctx: &Context,
+) -> Result<Object> {
+    let mut result = Object::Null;
+    for statement in statements {
+        result = eval_statement(statement, env, ctx)?;
+    }
+    Ok(result)
+}
+
+fn eval_statement(statement: &statement::Statement, env: &Env, ctx: &Context) -> Result<Object> {
+    match statement {
+        statement::Statement::LetStatement(statement)
+        | statement::Statement::ExportStatement(statement::ExportStatement { statement, .. }) => {
+            // 関数リテラルなら束縛する名前をトレース用に持たせる
+            let value = match &statement.value {
+                expression::Expression::FunctionLiteral(function) => {
+                    new_function(function, Some(statement.name.name()), env)
+                }
+                value => eval_expression(value, env, ctx)?,
+            };
+            env.borrow_mut().set(statement.name.name(), value);
+            Ok(Object::Null)
+        }
+        statement::Statement::ReturnStatement(statement) => Err(Unwind::Return(eval_expression(
+            &statement.return_value,
+            env,
+            ctx,
+        )?)),
+        statement::Statement::ExpressionStatement(statement) => {
+            eval_expression(&statement.expression, env, ctx)
+        }
+        statement::Statement::ThrowStatement(statement) => {
+            let value = eval_expression(&statement.value, env, ctx)?;
+            Err(Unwind::from(RuntimeError::Thrown(value)).at(statement.span()))
+        }
+        // parser はブロックの中の import を受け付けないが、JSON から作った木にはありうる
+        statement::Statement::ImportStatement(statement) => {
+            Err(Unwind::from(RuntimeError::ImportFailed {
+                path: statement.path.value.clone(),
+                reason: "import is only allowed at the top level".to_string(),
+            })
+            .at(statement.span()))
+        }
+    }
+}
+
+// エラーになった一番内側の式の位置を残す
+fn eval_expression(
+    expression: &expression::Expression,
+    env: &Env,
+    ctx: &Context,
+) -> Result<Object> {
+    eval_expression_kind(expression, env, ctx).map_err(|unwind| unwind.at(expression.span()))
+}
+
+fn eval_expression_kind(
+    expression: &expression::Expression,
+    env: &Env,
+    ctx: &Context,
+) -> Result<Object> {
+    match expression {
+        expression::Expression::Identifier(identifier) => {
+            let name = identifier.name();
+            let value = env.borrow().get(name).or_else(|| builtins::lookup(name));
+            value.ok_or_else(|| {
+                RuntimeError::UndefinedIdentifier {
+                    name: name.to_string(),
+                }
+                .into()
+            })
+        }
+        expression::Expression::IntegerLiteral(literal) => Ok(Object::Integer(literal.value)),
+        expression::Expression::StringLiteral(literal) => {
+            Ok(Object::String(literal.value.as_str().into()))
+        }
+        expression::Expression::Boolean(boolean) => Ok(Object::Boolean(boolean.value)),
+        expression::Expression::PrefixExpression(expression) => {
+            let right = eval_expression(&expression.right, env, ctx)?;
+            Ok(eval_prefix(&expression.operator, right)?)
+        }
+        expression::Expression::InfixExpression(expression) => {
+            let left = eval_expression(&expression.left, env, ctx)?;
+            let right = eval_expression(&expression.right, env, ctx)?;
+            Ok(eval_infix(&expression.operator, left, right)?)
+        }
+        expression::Expression::IfExpression(expression) => {
+            if eval_expression(&expression.condition, env, ctx)?.is_truthy() {
+                eval_statements(&expression.consequence.statements, env, ctx)
+            } else if let Some(alternative) = &expression.alternative {
+                eval_statements(&alternative.statements, env, ctx)
+            } else {
+                Ok(Object::Null)
+            }
+        }
+        expression::Expression::FunctionLiteral(function) => Ok(new_function(function, None, env)),
+        expression::Expression::CallExpression(expression) => {
+            let function = eval_expression(&expression.function, env, ctx)?;
+            let arguments = expression
+                .arguments
+                .iter()
+                .map(|argument| eval_expression(argument, env, ctx))
+                .collect::<Result<Vec<_>>>()?;
+            apply_function(function, arguments, expression.span(), ctx)
+        }
+        expression::Expression::ArrayLiteral(array) => {
+            let elements = array
+                .elements
+                .iter()
+                .map(|element| eval_expression(element, env, ctx))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Object::Array(Rc::new(elements)))
+        }
+        expression::Expression::IndexExpression(expression) => {
+            let left = eval_expression(&expression.left, env, ctx)?;
+            let index = eval_expression(&expression.index, env, ctx)?;
+            // 範囲外は `a[5]` の先頭ではなく添字の位置を指す
+            eval_index(left, index).map_err(|e| match e {
+                RuntimeError::IndexOutOfRange { .. } => Unwind::from(e).at(expression.index.span()),
+                e => e.into(),
+            })
+        }
+        expression::Expression::HashLiteral(hash) => {
+            let mut pairs = BTreeMap::new();
+            for (key, value) in &hash.pairs {
+                let key = eval_expression(key, env, ctx)?;
+                let hash_key = key
+                    .hash_key()
+                    .ok_or_else(|| RuntimeError::UnusableHashKey {
+                        typ: key.type_name(),
+                    })?;
+                pairs.insert(hash_key, eval_expression(value, env, ctx)?);
+            }
+            Ok(Object::Hash(Rc::new(pairs)))
+        }
+        expression::Expression::MemberExpression(expression) => {
+            let object = eval_expression(&expression.object, env, ctx)?;
+            Ok(eval_member(object, expression.member.name())?)
+        }
+        expression::Expression::TryExpression(expression) => eval_try(expression, env, ctx),
+    }
+}
+
+fn new_function(function: &expression::FunctionLiteral, name: Option<&str>, env: &Env) -> Object {
+    Object::Function(Rc::new(Function {
+        name: name.map(str::to_string),
+        parameters: function.parameters.clone(),
+        body: function.body.clone(),
+        env: env.clone(),
+    }))
+}
+
+// catch の引数はほかの束縛と同じく今の環境に入る
+fn eval_try(expression: &expression::TryExpression, env: &Env, ctx: &Context) -> Result<Object> {
+    let result = match (
+        eval_statements(&expression.block.statements, env, ctx),
+        &expression.catch,
+    ) {
+        (Err(Unwind::Error(e, location)), Some(catch)) => {
+            let value = caught(e, location.trace, ctx);
+            env.borrow_mut().set(catch.parameter.name(), value);
+            eval_statements(&catch.body.statements, env, ctx)
+        }
+        (result, _) => result,
+    };
+    // finally の値は捨てる。finally の中の return やエラーはそれまでの結果より優先する
+    if let Some(finally) = &expression.finally {
+        eval_statements(&finally.statements, env, ctx)?;
+    }
+    result
+}
+
+// throw した値はそのまま、実行時エラーはエラー値にして catch に渡す
+fn caught(e: RuntimeError, trace: Option<Vec<Frame>>, ctx: &Context) -> Object {
+    match e {
+        RuntimeError::Thrown(value) => value,
+        e => Object::Error(Rc::new(ErrorValue {
+            kind: e.kind().to_string(),
+            message: e.to_string(),
+            trace: trace.unwrap_or_else(|| ctx.trace()),
+        })),
+    }
+}
+
+fn eval_member(object: Object, name: &str) -> std::result::Result<Object, RuntimeError> {
+    match object {
+        Object::Module(module) => {
+            module
+                .exports
+                .get(name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::NoSuchExport {
+                    module: module.name.clone(),
+                    name: name.to_string(),
+                })
+        }
+        Object::Error(error) => match name {
+            "kind" => Ok(Object::String(error.kind.as_str().into())),
+            "message" => Ok(Object::String(error.message.as_str().into())),
+            "trace" => Ok(Object::Array(Rc::new(
+                error
+                    .trace
+                    .iter()
+                    .map(|frame| Object::String(frame.to_string().into()))
+                    .collect(),
+            ))),
+            _ => Err(RuntimeError::MemberNotSupported { typ: "ERROR" }),
+        },
+        other => Err(RuntimeError::MemberNotSupported {
+            typ: other.type_name(),
+        }),
+    }
+}
+
+// エラーの出力に使う、トレースを内側から順に並べた行。同じ呼び出しが続くところは回数にまとめる
+pub fn trace_lines(trace: &[Frame]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut frames = trace.iter().rev().peekable();
+    while let Some(frame) = frames.next() {
+        lines.push(format!("in {}", frame));
+        let mut repeated = 0;
+        while frames.next_if_eq(&frame).is_some() {
+            repeated += 1;
+        }
+        if repeated > 0 {
+            lines.push(format!("... repeated {} more times", repeated));
+        }
+    }
+    lines
+}
+
+// 組み込み関数から引数の関数を呼ぶときに使う。呼び出した位置は組み込み関数の呼び出しにする
+pub(crate) fn call(
+    function: &Object,
+    arguments: Vec<Object>,
+    ctx: &Context,
+) -> std::result::Result<Object, RuntimeError> {
+    let span = ctx
+        .trace()
+        .last()
+        .map_or_else(token::Span::default, |frame| frame.span);
+    match apply_function(function.clone(), arguments, span, ctx) {
+        Ok(object) | Err(Unwind::Return(object)) => Ok(object),
+        Err(Unwind::Error(e, location)) => {
+            ctx.set_pending(*location);
+            Err(e)
+        }
+    }
+}
+
+// 残りのスタックが RED_ZONE を切ったら STACK_SIZE ずつ確保する
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SIZE: usize = 4 * 1024 * 1024;
+
+// span は呼び出した式の位置。呼び出している間は ctx にフレームを積む
+fn apply_function(
+    function: Object,
+    arguments: Vec<Object>,
+    span: token::Span,
+    ctx: &Context,
+) -> Result<Object> {
+    let (name, binding) = match &function {
+        Object::Function(function) => ("<anonymous>", function.name.clone()),
+        Object::Builtin(builtin) => (builtin.name, None),
+        other => {
+            return Err(RuntimeError::NotCallable {
+                typ: other.type_name(),
+            }
+            .into())
+        }
+    };
+    if !ctx.can_call() {
+        return Err(RuntimeError::CallDepthExceeded {
+            limit: ctx.max_depth(),
+        }
+        .into());
+    }
+    ctx.push_frame(Frame {
+        function: name.to_string(),
+        binding,
+        span,
+    });
+    let result = match function {
+        // 深い再帰でも上限に届く前にスタックが尽きないよう、足りなければヒープに伸ばす
+        Object::Function(function) => stacker::maybe_grow(RED_ZONE, STACK_SIZE, || {
+            apply_user_function(&function, arguments, ctx)
+        }),
+        Object::Builtin(builtin) => {
+            let result =
+                (builtin.function)(builtins::Arguments::new(builtin.name, &arguments, ctx));
+            // 引数の関数の中で起きたエラーなら、そこでの位置とトレースを使う
+            let location = ctx.take_pending();
+            result.map_err(|e| Unwind::Error(e, Box::new(location)))
+        }
+        _ => unreachable!(),
+    };
+    // エラーが最初に関数の呼び出しを抜けるときに、呼び出し中の関数を残す
+    let result = match result {
+        Err(Unwind::Error(e, mut location)) if location.trace.is_none() => {
+            location.trace = Some(ctx.trace());
+            Err(Unwind::Error(e, location))
+        }
+        result => result,
+    };
+    ctx.pop_frame();
+    result
+}
+
+fn apply_user_function(
+    function: &Function,
+    arguments: Vec<Object>,
+    ctx: &Context,
+) -> Result<Object> {
+    if function.parameters.len() != arguments.len() {
+        return Err(RuntimeError::WrongArity {
+            want: function.parameters.len(),
+            got: arguments.len(),
+        }
+        .into());
+    }
+    let env = Environment::enclosed(&function.env);
+    for (parameter, argument) in function.parameters.iter().zip(arguments) {
+        env.borrow_mut().set(parameter.name.name(), argument);
+    }
+    match eval_statements(&function.body.statements, &env, ctx) {
+        Ok(object) | Err(Unwind::Return(object)) => Ok(object),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn eval_prefix(
+    operator: &str,
+    right: Object,
+) -> std::result::Result<Object, RuntimeError> {
+    match (operator, right) {
+        ("!", right) => Ok(Object::Boolean(!right.is_truthy())),
+        ("-", Object::Integer(v)) => Ok(Object::Integer(v.wrapping_neg())),
+        (operator, right) => Err(RuntimeError::UnknownPrefixOperator {
+            operator: operator.to_string(),
+            right: right.type_name(),
+        }),
+    }
+}
+
+// 整数の演算は桁あふれしたら折り返す
+pub(crate) fn eval_infix(
+    operator: &str,
+    left: Object,
+    right: Object,
+) -> std::result::Result<Object, RuntimeError> {
+    match (operator, &left, &right) {
+        (_, Object::Integer(l), Object::Integer(r)) => {
+            let (l, r) = (*l, *r);
+            Ok(match operator {
+                "+" => Object::Integer(l.wrapping_add(r)),
+                "-" => Object::Integer(l.wrapping_sub(r)),
+                "*" => Object::Integer(l.wrapping_mul(r)),
+                "/" if r == 0 => return Err(RuntimeError::DivisionByZero),
+                "/" => Object::Integer(l.wrapping_div(r)),
+                "<" => Object::Boolean(l < r),
+                ">" => Object::Boolean(l > r),
+                "==" => Object::Boolean(l == r),
+                "!=" => Object::Boolean(l != r),
+                _ => return Err(unknown_infix(operator, &left, &right)),
+            })
+        }
+        ("+", Object::String(l), Object::String(r)) => {
+            Ok(Object::String(format!("{}{}", l, r).into()))
+        }
+        ("==", Object::String(l), Object::String(r)) => Ok(Object::Boolean(l == r)),
+        ("!=", Object::String(l), Object::String(r)) => Ok(Object::Boolean(l != r)),
+        (_, l, r) if l.type_name() != r.type_name() => Err(RuntimeError::TypeMismatch {
+            left: l.type_name(),
+            operator: operator.to_string(),
+            right: r.type_name(),
+        }),
+        ("==", Object::Boolean(l), Object::Boolean(r)) => Ok(Object::Boolean(l == r)),
+        ("!=", Object::Boolean(l), Object::Boolean(r)) => Ok(Object::Boolean(l != r)),
+        _ => Err(unknown_infix(operator, &left, &right)),
+    }
+}
+
+fn unknown_infix(operator: &str, left: &Object, right: &Object) -> RuntimeError {
+    RuntimeError::UnknownInfixOperator {
+        left: left.type_name(),
+        operator: operator.to_string(),
+        right: right.type_name(),
+    }
+}
+
+// 存在しないキーは null。配列の範囲外の添字は IndexOutOfRange
+pub(crate) fn eval_index(left: Object, index: Object) -> std::result::Result<Object, RuntimeError> {
+    match (&left, &index) {
+        (Object::Array(elements), Object::Integer(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| elements.get(i))
+            .cloned()
+            .ok_or(RuntimeError::IndexOutOfRange {
+                index: *i,
+                length: elements.len(),
+            }),
+        (Object::Hash(pairs), _) => {
+            let key = index
+                .hash_key()
+                .ok_or_else(|| RuntimeError::UnusableHashKey {
+                    typ: index.type_name(),
+                })?;
+            Ok(pairs.get(&key).cloned().unwrap_or(Object::Null))
+        }
+        _ => Err(RuntimeError::IndexNotSupported {
+            left: left.type_name(),
+            index: index.type_name(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{context::MAX_DEPTH, eval_program, Capabilities, Context, Loader, RuntimeError};
+    use crate::{object::Environment, parser};
+
+    fn eval(input: &str) -> Result<String, RuntimeError> {
+        let program = parser::parse(input).unwrap();
+        eval_program(&program, &Environment::new()).map(|object| object.to_string())
+    }
+
+    #[test]
+    fn test_eval() {
+        let tests =
+            vec![
+            ("5 + 5 * 2 - 10 / 2", "10"),
+            ("-(1 < 2 == true) ", "unknown operator: -BOOLEAN"),
+            ("!5; !!true", "true"),
+            ("\"a\" + \"b\" == \"ab\"", "true"),
+            ("if (1 > 2) { 10 }", "null"),
+            ("if (0) { 1 } else { 2 }", "1"),
+            ("let f = fn(x) { return x * 2; 0 }; f(3) + f(4)", "14"),
+            (
+                "let adder = fn(x) { fn(y) { x + y } }; let addTwo = adder(2); addTwo(5)",
+                "7",
+            ),
+            ("if (true) { if (true) { return 1; } 2 } 3", "1"),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                "610",
+            ),
+            ("[1, \"two\", [3]][1]", "two"),
+            ("[1, \"two\", [3]]", "[1, \"two\", [3]]"),
+            ("[1][5]", "index out of range: 5 for length 1"),
+            ("[1, 2][-1]", "index out of range: -1 for length 2"),
+            ("{\"a\": 1}[\"b\"]", "null"),
+            ("{\"b\": 2, \"a\": 1, true: [\"x\"]}", "{true: [\"x\"], \"a\": 1, \"b\": 2}"),
+            ("let h = {1: \"one\"}; h[1] + h[2 - 1]", "oneone"),
+            ("let x = 1;", "null"),
+            ("9223372036854775807 + 1", "-9223372036854775808"),
+        ];
+        for (input, expected) in tests {
+            let actual = eval(input).unwrap_or_else(|e| e.to_string());
+            assert_eq!(actual, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            ("5 + true; 5", "type mismatch: INTEGER + BOOLEAN"),
+            ("true + false", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("\"a\" - \"b\"", "unknown operator: STRING - STRING"),
+            ("foobar", "identifier not found: foobar"),
+            ("1(2)", "not a function: INTEGER"),
+            ("fn(a) { a }()", "wrong number of arguments: want=1, got=0"),
+            ("1[0]", "index operator not supported: INTEGER[INTEGER]"),
+            ("{[1]: 2}", "unusable as hash key: ARRAY"),
+            ("{}[fn() {}]", "unusable as hash key: FUNCTION"),
+            ("1 / (2 - 2)", "division by zero"),
+            (
+                "if (true) { return 1 + true; } 2",
+                "type mismatch: INTEGER + BOOLEAN",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                eval(input).unwrap_err().to_string(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn test_error_codes() {
+        // コードと、エラーになった一番内側の式の範囲
+        let tests = vec![
+            ("1 + true", "E0201", "1:1-1:9"),
+            ("-true", "E0202", "1:1-1:6"),
+            ("true + true", "E0203", "1:1-1:12"),
+            ("[foo]", "E0204", "1:2-1:5"),
+            ("let f = fn(x) { x };\nf(1, 2)", "E0206", "2:1-2:8"),
+            ("let f = fn(x) { x / 0 };\nf(1)", "E0209", "1:17-1:22"),
+            // 組み込み関数に渡した関数の中
+            ("array.map([1], fn(x) { x(1) })", "E0205", "1:24-1:28"),
+            ("if (true) { throw 1; }", "E0219", "1:13-1:20"),
+            ("let a = [1];\na[1]", "E0220", "2:3-2:4"),
+        ];
+        for (input, code, span) in tests {
+            let program = parser::parse(input).unwrap();
+            let mut loader = Loader::new();
+            let e = loader
+                .eval_program(&program, &Environment::new(), None)
+                .unwrap_err();
+            assert_eq!(e.code(), code, "input: {}", input);
+            assert_eq!(loader.span().unwrap().to_string(), span, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_call_depth() {
+        // 上限より浅い再帰は最後まで評価する
+        let count = format!(
+            "let f = fn(n) {{ if (n == 0) {{ 0 }} else {{ 1 + f(n - 1) }} }}; f({})",
+            MAX_DEPTH - 1
+        );
+        assert_eq!(eval(&count).unwrap(), (MAX_DEPTH - 1).to_string());
+        // 止まらない再帰はスタックを使い切らずにエラーになる
+        assert_eq!(
+            eval("let f = fn(n) { f(n + 1) }; f(0)"),
+            Err(RuntimeError::CallDepthExceeded { limit: MAX_DEPTH })
+        );
+        assert_eq!(
+            eval("try { let f = fn() { f() }; f() } catch (e) { e.kind }").unwrap(),
+            "RecursionError"
+        );
+
+        let program = parser::parse("let f = fn(n) { array.map([n], f) }; f(0)").unwrap();
+        let context = Context::new(Capabilities::default(), Vec::new()).with_max_depth(10);
+        let mut loader = Loader::with_context(context);
+        let e = loader
+            .eval_program(&program, &Environment::new(), None)
+            .unwrap_err();
+        assert_eq!(e, RuntimeError::CallDepthExceeded { limit: 10 });
+        assert_eq!(e.code(), "E0221");
+        assert_eq!(loader.trace().len(), 10);
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        // 配列の範囲外は null ではなくエラー。ハッシュのないキーは null のまま
+        for input in ["[1, 2, 3][3]", "[1, 2, 3][-1]", "[][0]"] {
+            let e = eval(input).unwrap_err();
+            assert!(
+                matches!(e, RuntimeError::IndexOutOfRange { .. }),
+                "input: {}",
+                input
+            );
+            assert_eq!((e.code(), e.kind()), ("E0220", "IndexError"));
+        }
+        assert_eq!(eval("[1, 2, 3][2]").unwrap().to_string(), "3");
+        assert_eq!(eval("{1: 2}[3]").unwrap().to_string(), "null");
+    }
+
+    #[test]
+    fn test_try() {
+        let tests = vec![
+            ("try { 1 } catch (e) { 2 }", "1"),
+            ("try { throw 1; 2 } catch (e) { e + 1 }", "2"),
+            (
+                "try { 1 + true } catch (e) { [e.kind, e.message] }",
+                "[\"TypeError\", \"type mismatch: INTEGER + BOOLEAN\"]",
+            ),
+            (
+                "try { read_file(\"a.txt\") } catch (e) { e.kind }",
+                "PermissionError",
+            ),
+            ("try { [][0] } catch (e) { e.kind }", "IndexError"),
+            // finally の値は捨て、finally の中の return は優先する
+            (
+                "let r = try { 1 } finally { let x = 2; 3 }; [r, x]",
+                "[1, 2]",
+            ),
+            (
+                "let f = fn() { try { return 1; } finally { return 2; } }; f()",
+                "2",
+            ),
+            (
+                "let f = fn() { try { throw 1; } catch (e) { return e; } 2 }; f()",
+                "1",
+            ),
+            ("try { throw 1; } catch (e) { throw e + 1; }", "uncaught 2"),
+            ("try { throw 1; } finally { 2 }", "uncaught 1"),
+            ("throw error(\"boom\");", "uncaught Error: boom"),
+            (
+                "let e = error(\"x\", \"IoError\"); [e, is_error(e), is_error(\"x\")]",
+                "[IoError: x, true, false]",
+            ),
+            ("error(\"x\").nope", "member access not supported: ERROR"),
+        ];
+        for (input, expected) in tests {
+            let actual = eval(input).unwrap_or_else(|e| e.to_string());
+            assert_eq!(actual, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let tests = vec![
+            (
+                "let inner = fn() { 1 / 0 };\nlet outer = fn() { inner() };\ntry { outer() } catch (e) { e.trace }",
+                "[\"<anonymous> (let outer) at 3:7\", \"<anonymous> (let inner) at 2:20\"]",
+            ),
+            // error() の呼び出しは入れない
+            (
+                "let f = fn() { error(\"x\") }; f().trace",
+                "[\"<anonymous> (let f) at 1:30\"]",
+            ),
+            // 組み込み関数に渡した関数の中のエラー
+            (
+                "try { array.map([1], fn(x) { x / 0 }) } catch (e) { e.trace }",
+                "[\"array.map at 1:7\", \"<anonymous> at 1:7\"]",
+            ),
+            ("try { 1 / 0 } catch (e) { e.trace }", "[]"),
+        ];
+        for (input, expected) in tests {
+            let actual = eval(input).unwrap_or_else(|e| e.to_string());
+            assert_eq!(actual, expected, "input: {}", input);
+        }
+    }
+}
@@ -0,0 +1,795 @@
+// 組み込みのモジュール math, string, array, hash と json_parse や read_file, error などの関数
+// 引数の数と型は Arguments で調べ、どの関数でも同じ形のエラーにする
+use std::{cmp::Ordering, collections::BTreeMap, convert::TryFrom, rc::Rc};
+
+use super::{call, Context, RuntimeError};
+use crate::object::{Builtin, HashKey, Module, Object};
+
+mod error;
+mod io;
+mod json;
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+// モジュールに入れずに直接呼ぶ関数
+static FUNCTIONS: [Builtin; 12] = [
+    Builtin {
+        name: "json_parse",
+        function: json::parse,
+    },
+    Builtin {
+        name: "json_stringify",
+        function: json::stringify,
+    },
+    Builtin {
+        name: "read_file",
+        function: io::read_file,
+    },
+    Builtin {
+        name: "write_file",
+        function: io::write_file,
+    },
+    Builtin {
+        name: "read_line",
+        function: io::read_line,
+    },
+    Builtin {
+        name: "print",
+        function: io::print,
+    },
+    Builtin {
+        name: "puts",
+        function: io::puts,
+    },
+    Builtin {
+        name: "eputs",
+        function: io::eputs,
+    },
+    Builtin {
+        name: "env",
+        function: io::env,
+    },
+    Builtin {
+        name: "args",
+        function: io::args,
+    },
+    Builtin {
+        name: "error",
+        function: error::error,
+    },
+    Builtin {
+        name: "is_error",
+        function: error::is_error,
+    },
+];
+
+static MATH: [Builtin; 6] = [
+    Builtin {
+        name: "math.abs",
+        function: abs,
+    },
+    Builtin {
+        name: "math.min",
+        function: min,
+    },
+    Builtin {
+        name: "math.max",
+        function: max,
+    },
+    Builtin {
+        name: "math.pow",
+        function: pow,
+    },
+    Builtin {
+        name: "math.sqrt",
+        function: sqrt,
+    },
+    Builtin {
+        name: "math.floor",
+        function: floor,
+    },
+];
+
+static STRING: [Builtin; 8] = [
+    Builtin {
+        name: "string.split",
+        function: split,
+    },
+    Builtin {
+        name: "string.join",
+        function: join,
+    },
+    Builtin {
+        name: "string.trim",
+        function: trim,
+    },
+    Builtin {
+        name: "string.upper",
+        function: upper,
+    },
+    Builtin {
+        name: "string.lower",
+        function: lower,
+    },
+    Builtin {
+        name: "string.replace",
+        function: replace,
+    },
+    Builtin {
+        name: "string.contains",
+        function: contains,
+    },
+    Builtin {
+        name: "string.format",
+        function: format,
+    },
+];
+
+static ARRAY: [Builtin; 7] = [
+    Builtin {
+        name: "array.map",
+        function: map,
+    },
+    Builtin {
+        name: "array.filter",
+        function: filter,
+    },
+    Builtin {
+        name: "array.reduce",
+        function: reduce,
+    },
+    Builtin {
+        name: "array.sort",
+        function: sort,
+    },
+    Builtin {
+        name: "array.reverse",
+        function: reverse,
+    },
+    Builtin {
+        name: "array.slice",
+        function: slice,
+    },
+    Builtin {
+        name: "array.zip",
+        function: zip,
+    },
+];
+
+static HASH: [Builtin; 5] = [
+    Builtin {
+        name: "hash.keys",
+        function: keys,
+    },
+    Builtin {
+        name: "hash.values",
+        function: values,
+    },
+    Builtin {
+        name: "hash.entries",
+        function: entries,
+    },
+    Builtin {
+        name: "hash.merge",
+        function: merge,
+    },
+    Builtin {
+        name: "hash.has",
+        function: has,
+    },
+];
+
+thread_local! {
+    // 同じ名前はいつも同じモジュールになるよう一度だけ作る
+    static MODULES: [Rc<Module>; 4] = [
+        new_module("math", &MATH),
+        new_module("string", &STRING),
+        new_module("array", &ARRAY),
+        new_module("hash", &HASH),
+    ];
+}
+
+fn new_module(name: &str, functions: &'static [Builtin]) -> Rc<Module> {
+    let exports = functions
+        .iter()
+        .map(|builtin| {
+            let (_, short) = builtin.name.split_once('.').unwrap();
+            (short.to_string(), Object::Builtin(builtin))
+        })
+        .collect();
+    Rc::new(Module {
+        name: name.to_string(),
+        exports,
+    })
+}
+
+// name が super::BUILTINS のどれかならそのモジュールか関数
+pub(crate) fn lookup(name: &str) -> Option<Object> {
+    if let Some(builtin) = FUNCTIONS.iter().find(|builtin| builtin.name == name) {
+        return Some(Object::Builtin(builtin));
+    }
+    MODULES.with(|modules| {
+        modules
+            .iter()
+            .find(|module| module.name == name)
+            .map(|module| Object::Module(module.clone()))
+    })
+}
+
+// 組み込み関数に渡す引数。エラーメッセージに使う関数の名前も持つ
+pub struct Arguments<'a, 'io> {
+    function: &'static str,
+    values: &'a [Object],
+    context: &'a Context<'io>,
+}
+
+impl<'a, 'io> Arguments<'a, 'io> {
+    pub(crate) fn new(
+        function: &'static str,
+        values: &'a [Object],
+        context: &'a Context<'io>,
+    ) -> Self {
+        Self {
+            function,
+            values,
+            context,
+        }
+    }
+
+    // 引数で受け取った関数を呼ぶ
+    fn call(&self, function: &Object, arguments: Vec<Object>) -> Result<Object> {
+        call(function, arguments, self.context)
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    // max が None なら上限はない
+    fn arity(&self, min: usize, max: Option<usize>) -> Result<()> {
+        let got = self.len();
+        if got >= min && max.is_none_or(|max| got <= max) {
+            return Ok(());
+        }
+        let want = match max {
+            Some(max) if max == min => min.to_string(),
+            Some(max) if max == min + 1 => format!("{} or {}", min, max),
+            Some(max) => format!("{} to {}", min, max),
+            None => format!("at least {}", min),
+        };
+        Err(RuntimeError::WrongBuiltinArity {
+            function: self.function,
+            want,
+            got,
+        })
+    }
+
+    fn wrong_type(&self, i: usize, want: &'static str) -> RuntimeError {
+        RuntimeError::WrongArgumentType {
+            function: self.function,
+            position: i + 1,
+            want,
+            got: self.values[i].type_name(),
+        }
+    }
+
+    fn invalid(&self, reason: String) -> RuntimeError {
+        RuntimeError::InvalidArgument {
+            function: self.function,
+            reason,
+        }
+    }
+
+    fn denied(&self, capability: String) -> RuntimeError {
+        RuntimeError::PermissionDenied {
+            function: self.function,
+            capability,
+        }
+    }
+
+    fn io_failed(&self, e: std::io::Error) -> RuntimeError {
+        RuntimeError::IoFailed {
+            function: self.function,
+            reason: e.to_string(),
+        }
+    }
+
+    fn integer(&self, i: usize) -> Result<i64> {
+        match &self.values[i] {
+            Object::Integer(v) => Ok(*v),
+            _ => Err(self.wrong_type(i, "INTEGER")),
+        }
+    }
+
+    fn string(&self, i: usize) -> Result<&'a str> {
+        match &self.values[i] {
+            Object::String(v) => Ok(v),
+            _ => Err(self.wrong_type(i, "STRING")),
+        }
+    }
+
+    fn array(&self, i: usize) -> Result<&'a [Object]> {
+        match &self.values[i] {
+            Object::Array(elements) => Ok(elements),
+            _ => Err(self.wrong_type(i, "ARRAY")),
+        }
+    }
+
+    fn hash(&self, i: usize) -> Result<&'a BTreeMap<HashKey, Object>> {
+        match &self.values[i] {
+            Object::Hash(pairs) => Ok(pairs),
+            _ => Err(self.wrong_type(i, "HASH")),
+        }
+    }
+
+    // 組み込み関数も受け付ける
+    fn function(&self, i: usize) -> Result<&'a Object> {
+        match &self.values[i] {
+            function @ (Object::Function(_) | Object::Builtin(_)) => Ok(function),
+            _ => Err(self.wrong_type(i, "FUNCTION")),
+        }
+    }
+
+    fn integers(&self) -> Result<Vec<i64>> {
+        (0..self.len()).map(|i| self.integer(i)).collect()
+    }
+}
+
+fn string(s: impl Into<Rc<str>>) -> Object {
+    Object::String(s.into())
+}
+
+fn array(elements: Vec<Object>) -> Object {
+    Object::Array(Rc::new(elements))
+}
+
+// 整数しかないので、演算は evaluator と同じく桁あふれしたら折り返す
+fn abs(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(Object::Integer(args.integer(0)?.wrapping_abs()))
+}
+
+fn min(args: Arguments) -> Result<Object> {
+    args.arity(1, None)?;
+    Ok(Object::Integer(args.integers()?.into_iter().min().unwrap()))
+}
+
+fn max(args: Arguments) -> Result<Object> {
+    args.arity(1, None)?;
+    Ok(Object::Integer(args.integers()?.into_iter().max().unwrap()))
+}
+
+fn pow(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let (base, exponent) = (args.integer(0)?, args.integer(1)?);
+    if exponent < 0 {
+        return Err(args.invalid(format!("negative exponent {}", exponent)));
+    }
+    let exponent = u32::try_from(exponent)
+        .map_err(|_| args.invalid(format!("exponent {} is too large", exponent)))?;
+    Ok(Object::Integer(base.wrapping_pow(exponent)))
+}
+
+// 小数点以下は切り捨てる
+fn sqrt(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    let n = args.integer(0)?;
+    if n < 0 {
+        return Err(args.invalid(format!("square root of negative number {}", n)));
+    }
+    // f64 で求めた値は誤差があるので整数で合わせる
+    let mut root = (n as f64).sqrt() as i64;
+    while root.checked_mul(root).is_none_or(|square| square > n) {
+        root -= 1;
+    }
+    while (root + 1)
+        .checked_mul(root + 1)
+        .is_some_and(|square| square <= n)
+    {
+        root += 1;
+    }
+    Ok(Object::Integer(root))
+}
+
+// 小数がないので整数をそのまま返す
+fn floor(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(Object::Integer(args.integer(0)?))
+}
+
+// 区切りが空文字列なら 1 文字ずつに分ける
+fn split(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let (s, separator) = (args.string(0)?, args.string(1)?);
+    let parts = if separator.is_empty() {
+        s.chars().map(|c| string(c.to_string())).collect()
+    } else {
+        s.split(separator).map(string).collect()
+    };
+    Ok(array(parts))
+}
+
+// 文字列以外の要素は出力するときの形にする
+fn join(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let (elements, separator) = (args.array(0)?, args.string(1)?);
+    let parts: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+    Ok(string(parts.join(separator)))
+}
+
+fn trim(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(string(args.string(0)?.trim()))
+}
+
+fn upper(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(string(args.string(0)?.to_uppercase()))
+}
+
+fn lower(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(string(args.string(0)?.to_lowercase()))
+}
+
+fn replace(args: Arguments) -> Result<Object> {
+    args.arity(3, Some(3))?;
+    let (s, from, to) = (args.string(0)?, args.string(1)?, args.string(2)?);
+    if from.is_empty() {
+        return Err(args.invalid("cannot replace an empty string".to_string()));
+    }
+    Ok(string(s.replace(from, to)))
+}
+
+fn contains(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    Ok(Object::Boolean(args.string(0)?.contains(args.string(1)?)))
+}
+
+// `{}` を残りの引数で順に置き換える
+fn format(args: Arguments) -> Result<Object> {
+    args.arity(1, None)?;
+    let template = args.string(0)?;
+    let pieces: Vec<&str> = template.split("{}").collect();
+    if pieces.len() != args.len() {
+        return Err(args.invalid(format!(
+            "{} placeholder(s) but {} value(s)",
+            pieces.len() - 1,
+            args.len() - 1
+        )));
+    }
+    let mut out = pieces[0].to_string();
+    for (value, piece) in args.values[1..].iter().zip(&pieces[1..]) {
+        out.push_str(&value.to_string());
+        out.push_str(piece);
+    }
+    Ok(string(out))
+}
+
+fn map(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let (elements, function) = (args.array(0)?, args.function(1)?);
+    let mapped = elements
+        .iter()
+        .map(|e| args.call(function, vec![e.clone()]))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(array(mapped))
+}
+
+fn filter(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let (elements, function) = (args.array(0)?, args.function(1)?);
+    let mut kept = Vec::new();
+    for e in elements {
+        if args.call(function, vec![e.clone()])?.is_truthy() {
+            kept.push(e.clone());
+        }
+    }
+    Ok(array(kept))
+}
+
+// function は (これまでの値, 要素) を受け取る
+fn reduce(args: Arguments) -> Result<Object> {
+    args.arity(3, Some(3))?;
+    let (elements, function) = (args.array(0)?, args.function(1)?);
+    elements.iter().try_fold(args.values[2].clone(), |acc, e| {
+        args.call(function, vec![acc, e.clone()])
+    })
+}
+
+// 比較する関数は a が先なら負、b が先なら正の整数を返す
+// 省略したときは整数どうしか文字列どうしだけ比べられる
+fn sort(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(2))?;
+    let elements = args.array(0)?.to_vec();
+    let sorted = if args.len() == 2 {
+        let function = args.function(1)?;
+        merge_sort(
+            elements,
+            &mut |a, b| match args.call(function, vec![a.clone(), b.clone()])? {
+                Object::Integer(n) => Ok(n.cmp(&0)),
+                other => Err(args.invalid(format!(
+                    "comparator must return INTEGER, got {}",
+                    other.type_name()
+                ))),
+            },
+        )?
+    } else {
+        merge_sort(elements, &mut |a, b| match (a, b) {
+            (Object::Integer(a), Object::Integer(b)) => Ok(a.cmp(b)),
+            (Object::String(a), Object::String(b)) => Ok(a.cmp(b)),
+            _ => Err(args.invalid(format!(
+                "cannot compare {} and {}",
+                a.type_name(),
+                b.type_name()
+            ))),
+        })?
+    };
+    Ok(array(sorted))
+}
+
+// 比較は失敗することがあり、全順序になっているとも限らないので自前の安定なマージソートで並べる
+fn merge_sort(
+    mut elements: Vec<Object>,
+    compare: &mut dyn FnMut(&Object, &Object) -> Result<Ordering>,
+) -> Result<Vec<Object>> {
+    if elements.len() <= 1 {
+        return Ok(elements);
+    }
+    let right = elements.split_off(elements.len() / 2);
+    let mut left = merge_sort(elements, compare)?.into_iter().peekable();
+    let mut right = merge_sort(right, compare)?.into_iter().peekable();
+    let mut merged = Vec::new();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let next = if compare(r, l)? == Ordering::Less {
+            right.next()
+        } else {
+            left.next()
+        };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+fn reverse(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(array(args.array(0)?.iter().rev().cloned().collect()))
+}
+
+// [start, end) を取り出す。範囲は配列の中に収まるよう切り詰め、end を省略すると最後まで
+fn slice(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(3))?;
+    let elements = args.array(0)?;
+    let clamp = |i: i64| usize::try_from(i).unwrap_or(0).min(elements.len());
+    let start = clamp(args.integer(1)?);
+    let end = if args.len() == 3 {
+        clamp(args.integer(2)?)
+    } else {
+        elements.len()
+    };
+    Ok(array(elements[start..end.max(start)].to_vec()))
+}
+
+// 短い方に合わせて [a[i], b[i]] の配列にする
+fn zip(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let (a, b) = (args.array(0)?, args.array(1)?);
+    Ok(array(
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| array(vec![a.clone(), b.clone()]))
+            .collect(),
+    ))
+}
+
+// ハッシュはキーの順に並んでいるので、結果もその順になる
+fn keys(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(array(
+        args.hash(0)?.keys().map(HashKey::to_object).collect(),
+    ))
+}
+
+fn values(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(array(args.hash(0)?.values().cloned().collect()))
+}
+
+fn entries(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(array(
+        args.hash(0)?
+            .iter()
+            .map(|(key, value)| array(vec![key.to_object(), value.clone()]))
+            .collect(),
+    ))
+}
+
+// 同じキーは後のハッシュの値になる
+fn merge(args: Arguments) -> Result<Object> {
+    args.arity(1, None)?;
+    let mut merged = BTreeMap::new();
+    for i in 0..args.len() {
+        merged.extend(args.hash(i)?.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    Ok(Object::Hash(Rc::new(merged)))
+}
+
+fn has(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let pairs = args.hash(0)?;
+    let key = args.values[1]
+        .hash_key()
+        .ok_or_else(|| RuntimeError::UnusableHashKey {
+            typ: args.values[1].type_name(),
+        })?;
+    Ok(Object::Boolean(pairs.contains_key(&key)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{evaluator, object::Environment, parser};
+
+    fn check(tests: &[(&str, &str)]) {
+        for (input, expected) in tests {
+            let program = parser::parse(input).unwrap();
+            let actual = evaluator::eval_program(&program, &Environment::new())
+                .map_or_else(|e| e.to_string(), |object| object.to_string());
+            assert_eq!(&actual, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_math() {
+        check(&[
+            ("math.abs(-3) + math.abs(4)", "7"),
+            ("math.min(3, -1, 2)", "-1"),
+            ("math.max(3, -1, 2)", "3"),
+            ("math.max(5)", "5"),
+            ("math.pow(2, 10)", "1024"),
+            ("math.pow(7, 0)", "1"),
+            ("math.pow(2, 64)", "0"),
+            (
+                "math.pow(2, -1)",
+                "invalid argument to math.pow: negative exponent -1",
+            ),
+            ("math.sqrt(0)", "0"),
+            ("math.sqrt(15) + math.sqrt(16)", "7"),
+            ("math.sqrt(9223372036854775807)", "3037000499"),
+            (
+                "math.sqrt(-4)",
+                "invalid argument to math.sqrt: square root of negative number -4",
+            ),
+            ("math.floor(-7)", "-7"),
+        ]);
+    }
+
+    #[test]
+    fn test_string() {
+        check(&[
+            (
+                "string.split(\"a,b,,c\", \",\")",
+                "[\"a\", \"b\", \"\", \"c\"]",
+            ),
+            ("string.split(\"abc\", \"\")", "[\"a\", \"b\", \"c\"]"),
+            ("string.join([\"a\", 1, true], \"-\")", "a-1-true"),
+            ("string.join([], \",\")", ""),
+            ("string.trim(\"  a b \\n\")", "a b"),
+            ("string.upper(\"abc\") + string.lower(\"DEF\")", "ABCdef"),
+            ("string.replace(\"aXbXc\", \"X\", \"--\")", "a--b--c"),
+            (
+                "string.replace(\"a\", \"\", \"b\")",
+                "invalid argument to string.replace: cannot replace an empty string",
+            ),
+            ("string.contains(\"hello\", \"ell\")", "true"),
+            ("string.contains(\"hello\", \"xyz\")", "false"),
+            ("string.format(\"{} + {} = {}\", 1, 2, [3])", "1 + 2 = [3]"),
+            (
+                "string.format(\"{} {}\", 1)",
+                "invalid argument to string.format: 2 placeholder(s) but 1 value(s)",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_array() {
+        check(&[
+            ("array.map([1, 2, 3], fn(x) { x * x })", "[1, 4, 9]"),
+            ("array.map([-1, 2], math.abs)", "[1, 2]"),
+            ("array.filter([1, 2, 3, 4], fn(x) { x > 2 })", "[3, 4]"),
+            ("array.reduce([1, 2, 3], fn(acc, x) { acc + x }, 10)", "16"),
+            ("array.reduce([], fn(acc, x) { acc + x }, 0)", "0"),
+            ("array.sort([3, 1, 2])", "[1, 2, 3]"),
+            ("array.sort([\"b\", \"c\", \"a\"])", "[\"a\", \"b\", \"c\"]"),
+            ("array.sort([3, 1, 2], fn(a, b) { b - a })", "[3, 2, 1]"),
+            // 比較する関数が 0 を返した要素は元の順のまま
+            (
+                "array.sort([[2, \"a\"], [1, \"b\"], [2, \"c\"]], fn(a, b) { a[0] - b[0] })",
+                "[[1, \"b\"], [2, \"a\"], [2, \"c\"]]",
+            ),
+            (
+                "array.sort([1, \"a\"])",
+                "invalid argument to array.sort: cannot compare STRING and INTEGER",
+            ),
+            (
+                "array.sort([1, 2], fn(a, b) { true })",
+                "invalid argument to array.sort: comparator must return INTEGER, got BOOLEAN",
+            ),
+            ("array.reverse([1, 2, 3])", "[3, 2, 1]"),
+            ("array.slice([1, 2, 3, 4], 1, 3)", "[2, 3]"),
+            ("array.slice([1, 2, 3, 4], 2)", "[3, 4]"),
+            ("array.slice([1, 2, 3], -5, 10)", "[1, 2, 3]"),
+            ("array.slice([1, 2, 3], 2, 1)", "[]"),
+            (
+                "array.zip([1, 2, 3], [\"a\", \"b\"])",
+                "[[1, \"a\"], [2, \"b\"]]",
+            ),
+            // 関数の中のエラーはそのまま返る
+            (
+                "array.map([1], fn(x) { x + true })",
+                "type mismatch: INTEGER + BOOLEAN",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn test_hash() {
+        check(&[
+            ("hash.keys({\"b\": 1, \"a\": 2})", "[\"a\", \"b\"]"),
+            ("hash.values({\"b\": 1, \"a\": 2})", "[2, 1]"),
+            ("hash.entries({1: true})", "[[1, true]]"),
+            (
+                "hash.merge({\"a\": 1, \"b\": 2}, {\"b\": 3}, {})",
+                "{\"a\": 1, \"b\": 3}",
+            ),
+            ("hash.has({\"a\": 1}, \"a\")", "true"),
+            ("hash.has({\"a\": 1}, \"b\")", "false"),
+            ("hash.has({}, [1])", "unusable as hash key: ARRAY"),
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        check(&[
+            (
+                "math.abs()",
+                "wrong number of arguments to math.abs: want 1, got 0",
+            ),
+            (
+                "math.min()",
+                "wrong number of arguments to math.min: want at least 1, got 0",
+            ),
+            (
+                "array.slice([1])",
+                "wrong number of arguments to array.slice: want 2 or 3, got 1",
+            ),
+            (
+                "math.max(1, \"2\")",
+                "argument 2 to math.max must be INTEGER, got STRING",
+            ),
+            (
+                "string.upper(1)",
+                "argument 1 to string.upper must be STRING, got INTEGER",
+            ),
+            (
+                "array.map({}, fn(x) { x })",
+                "argument 1 to array.map must be ARRAY, got HASH",
+            ),
+            (
+                "array.map([1], 1)",
+                "argument 2 to array.map must be FUNCTION, got INTEGER",
+            ),
+            (
+                "hash.keys([])",
+                "argument 1 to hash.keys must be HASH, got ARRAY",
+            ),
+            ("math.nope", "module math has no export nope"),
+            ("math", "<module math>"),
+            ("math.abs", "<builtin math.abs>"),
+            // 同じ名前を束縛すればそちらを使う
+            ("let math = 1; math", "1"),
+        ]);
+    }
+}
@@ -0,0 +1,29 @@
+// スクリプトからエラー値を作って調べる関数
+use std::rc::Rc;
+
+use super::{Arguments, Result};
+use crate::object::{ErrorValue, Object};
+
+// error(message, kind?)。kind の既定は "Error"
+pub(super) fn error(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(2))?;
+    let message = args.string(0)?;
+    let kind = if args.len() == 2 {
+        args.string(1)?
+    } else {
+        "Error"
+    };
+    // error 自身の呼び出しはトレースに入れない
+    let mut trace = args.context.trace();
+    trace.pop();
+    Ok(Object::Error(Rc::new(ErrorValue {
+        kind: kind.to_string(),
+        message: message.to_string(),
+        trace,
+    })))
+}
+
+pub(super) fn is_error(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    Ok(Object::Boolean(matches!(args.values[0], Object::Error(_))))
+}
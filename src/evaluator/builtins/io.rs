@@ -0,0 +1,244 @@
+// ファイル、標準入出力、環境変数を使う関数
+// どれも Context の Capabilities で許されているときだけ動く
+use std::{fs, io::Write, path::Path};
+
+use super::{array, string, Arguments, Result};
+use crate::object::Object;
+
+fn check_stdio(args: &Arguments) -> Result<()> {
+    if args.context.capabilities.can_use_stdio() {
+        Ok(())
+    } else {
+        Err(args.denied("stdio access".to_string()))
+    }
+}
+
+pub(super) fn read_file(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    let path = args.string(0)?;
+    if !args.context.capabilities.can_read(Path::new(path)) {
+        return Err(args.denied(format!("read access to {}", path)));
+    }
+    fs::read_to_string(path)
+        .map(string)
+        .map_err(|e| args.io_failed(e))
+}
+
+// 文字列以外の値は出力するときの形で書く
+pub(super) fn write_file(args: Arguments) -> Result<Object> {
+    args.arity(2, Some(2))?;
+    let path = args.string(0)?;
+    if !args.context.capabilities.can_write(Path::new(path)) {
+        return Err(args.denied(format!("write access to {}", path)));
+    }
+    fs::write(path, args.values[1].to_string()).map_err(|e| args.io_failed(e))?;
+    Ok(Object::Null)
+}
+
+// 改行を除いた 1 行。入力の終わりなら null
+pub(super) fn read_line(args: Arguments) -> Result<Object> {
+    args.arity(0, Some(0))?;
+    check_stdio(&args)?;
+    let mut buf = String::new();
+    if args
+        .context
+        .read_line(&mut buf)
+        .map_err(|e| args.io_failed(e))?
+        == 0
+    {
+        return Ok(Object::Null);
+    }
+    Ok(string(buf.trim_end_matches(&['\r', '\n'][..])))
+}
+
+// 引数を空白で区切って書く。改行はつけない
+pub(super) fn print(args: Arguments) -> Result<Object> {
+    check_stdio(&args)?;
+    write_values(&args, &mut **args.context.stdout(), "")
+}
+
+// print と同じだが最後に改行をつける
+pub(super) fn puts(args: Arguments) -> Result<Object> {
+    check_stdio(&args)?;
+    write_values(&args, &mut **args.context.stdout(), "\n")
+}
+
+// puts と同じだが標準エラー出力に書く
+pub(super) fn eputs(args: Arguments) -> Result<Object> {
+    check_stdio(&args)?;
+    write_values(&args, &mut **args.context.stderr(), "\n")
+}
+
+fn write_values(args: &Arguments, out: &mut dyn Write, end: &str) -> Result<Object> {
+    let parts: Vec<String> = args.values.iter().map(|v| v.to_string()).collect();
+    write!(out, "{}{}", parts.join(" "), end)
+        .and_then(|_| out.flush())
+        .map_err(|e| args.io_failed(e))?;
+    Ok(Object::Null)
+}
+
+// 設定されていない変数は null
+pub(super) fn env(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    let name = args.string(0)?;
+    if !args.context.capabilities.can_use_env() {
+        return Err(args.denied("env access".to_string()));
+    }
+    Ok(std::env::var(name).map_or(Object::Null, string))
+}
+
+// スクリプトに渡した引数の配列
+pub(super) fn args(args: Arguments) -> Result<Object> {
+    args.arity(0, Some(0))?;
+    if !args.context.capabilities.can_use_args() {
+        return Err(args.denied("args access".to_string()));
+    }
+    Ok(array(
+        args.context
+            .args
+            .iter()
+            .map(|a| string(a.as_str()))
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::{
+        evaluator::{Capabilities, Context, Loader},
+        object::Environment,
+        parser,
+    };
+
+    fn eval(input: &str, context: Context) -> String {
+        let program = parser::parse(input).unwrap();
+        Loader::with_context(context)
+            .eval_program(&program, &Environment::new(), None)
+            .map_or_else(|e| e.to_string(), |object| object.to_string())
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("interpreter-rs-io-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.txt").display().to_string();
+        let write = format!("write_file(\"{}\", [1, \"a\"]);", path);
+        let read = format!("read_file(\"{}\")", path);
+
+        let none = || Context::default();
+        assert_eq!(
+            eval(&write, none()),
+            format!(
+                "permission denied: write_file needs write access to {}",
+                path
+            )
+        );
+        let context = || {
+            Context::new(
+                Capabilities::none().allow_read(&dir).allow_write(&dir),
+                Vec::new(),
+            )
+        };
+        assert_eq!(
+            eval(&format!("{} {}", write, read), context()),
+            "[1, \"a\"]"
+        );
+        assert_eq!(
+            eval(&read, none()),
+            format!("permission denied: read_file needs read access to {}", path)
+        );
+        // 許可したディレクトリの中なら、ないファイルは読み込みの失敗になる
+        assert!(eval(
+            &format!("read_file(\"{}/none.txt\")", dir.display()),
+            context()
+        )
+        .starts_with("read_file failed: "));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 許可したディレクトリの中のリンクから、まだないリンク先に書き込ませない
+    #[cfg(unix)]
+    #[test]
+    fn test_dangling_symlink() {
+        let dir =
+            std::env::temp_dir().join(format!("interpreter-rs-io-link-{}", std::process::id()));
+        fs::create_dir_all(dir.join("data")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside.txt"), dir.join("data/link")).unwrap();
+        let link = dir.join("data/link").display().to_string();
+        let context = Context::new(
+            Capabilities::none()
+                .allow_read(dir.join("data"))
+                .allow_write(dir.join("data")),
+            Vec::new(),
+        );
+        assert_eq!(
+            eval(&format!("write_file(\"{}\", \"x\")", link), context),
+            format!(
+                "permission denied: write_file needs write access to {}",
+                link
+            )
+        );
+        assert!(!dir.join("outside.txt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_env_and_args() {
+        let context = Context::new(
+            Capabilities::none().allow_env().allow_args(),
+            vec!["a".to_string(), "b".to_string()],
+        );
+        assert_eq!(
+            eval(
+                "[env(\"CARGO_PKG_NAME\"), env(\"INTERPRETER_RS_UNSET\"), args()]",
+                context
+            ),
+            "[\"interpreter-rs\", null, [\"a\", \"b\"]]"
+        );
+        assert_eq!(
+            eval("env(\"PATH\")", Context::default()),
+            "permission denied: env needs env access"
+        );
+        assert_eq!(
+            eval("puts(1)", Context::new(Capabilities::none(), Vec::new())),
+            "permission denied: puts needs stdio access"
+        );
+        assert_eq!(
+            eval(
+                "args()",
+                Context::new(Capabilities::default().allow_args(), Vec::new())
+            ),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn test_args_denied() {
+        let context = Context::new(Capabilities::default(), vec!["secret".to_string()]);
+        assert_eq!(
+            eval("args()", context),
+            "permission denied: args needs args access"
+        );
+    }
+
+    #[test]
+    fn test_streams() {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let context = Context::default()
+            .with_stdin("first\r\nsecond".as_bytes())
+            .with_stdout(&mut out)
+            .with_stderr(&mut err);
+        let result = eval(
+            r#"let a = read_line(); let b = read_line();
+            print(a, 1); puts(); puts([b]); eputs("oops");
+            read_line()"#,
+            context,
+        );
+        assert_eq!(result, "null");
+        assert_eq!(String::from_utf8(out).unwrap(), "first 1\n[\"second\"]\n");
+        assert_eq!(String::from_utf8(err).unwrap(), "oops\n");
+    }
+}
@@ -0,0 +1,250 @@
+// json_parse と json_stringify。JSON の読み書きは serde_json に任せる
+use std::{collections::BTreeMap, rc::Rc};
+
+use serde_json::{Map, Number, Value};
+
+use super::{Arguments, Result};
+use crate::object::{HashKey, Object};
+
+// 数は整数だけを受け付ける。オブジェクトのキーは文字列になる
+pub(super) fn parse(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(1))?;
+    // serde_json のエラーには行と列が入る
+    let value: Value =
+        serde_json::from_str(args.string(0)?).map_err(|e| args.invalid(e.to_string()))?;
+    from_json(&args, value)
+}
+
+fn from_json(args: &Arguments, value: Value) -> Result<Object> {
+    Ok(match value {
+        Value::Null => Object::Null,
+        Value::Bool(v) => Object::Boolean(v),
+        Value::Number(n) => match n.as_i64() {
+            Some(v) => Object::Integer(v),
+            None => return Err(args.invalid(format!("{} is not a 64-bit integer", n))),
+        },
+        Value::String(s) => Object::String(s.into()),
+        Value::Array(elements) => Object::Array(Rc::new(
+            elements
+                .into_iter()
+                .map(|e| from_json(args, e))
+                .collect::<Result<_>>()?,
+        )),
+        Value::Object(pairs) => Object::Hash(Rc::new(
+            pairs
+                .into_iter()
+                .map(|(k, v)| Ok((HashKey::String(k.into()), from_json(args, v)?)))
+                .collect::<Result<BTreeMap<_, _>>>()?,
+        )),
+    })
+}
+
+// indent は字下げの空白の数。省略するか 0 なら 1 行で出力する
+// 配列とハッシュは作った後に変更できないので、値が循環することはない
+pub(super) fn stringify(args: Arguments) -> Result<Object> {
+    args.arity(1, Some(2))?;
+    let indent = if args.len() == 2 { args.integer(1)? } else { 0 };
+    if !(0..=16).contains(&indent) {
+        return Err(args.invalid(format!("indent must be between 0 and 16, got {}", indent)));
+    }
+    let value = to_json(&args, &args.values[0])?;
+    let out = if indent == 0 {
+        value.to_string()
+    } else {
+        let mut out = String::new();
+        write_indented(&mut out, &value, &" ".repeat(indent as usize), 0);
+        out
+    };
+    Ok(Object::String(out.into()))
+}
+
+// 空でない配列とオブジェクトだけ要素ごとに改行する
+fn write_indented(out: &mut String, value: &Value, indent: &str, depth: usize) {
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        out.push_str(&indent.repeat(depth));
+    };
+    match value {
+        Value::Array(elements) if !elements.is_empty() => {
+            out.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                write_indented(out, element, indent, depth + 1);
+            }
+            newline(out, depth);
+            out.push(']');
+        }
+        Value::Object(pairs) if !pairs.is_empty() => {
+            out.push('{');
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push_str(": ");
+                write_indented(out, value, indent, depth + 1);
+            }
+            newline(out, depth);
+            out.push('}');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+// 整数と真偽値のキーは文字列にする。文字列にして同じになるキーがあればエラー
+fn to_json(args: &Arguments, object: &Object) -> Result<Value> {
+    Ok(match object {
+        Object::Null => Value::Null,
+        Object::Boolean(v) => Value::Bool(*v),
+        Object::Integer(v) => Value::Number(Number::from(*v)),
+        Object::String(s) => Value::String(s.to_string()),
+        Object::Array(elements) => Value::Array(
+            elements
+                .iter()
+                .map(|e| to_json(args, e))
+                .collect::<Result<_>>()?,
+        ),
+        Object::Hash(pairs) => {
+            let mut map = Map::new();
+            for (key, value) in pairs.iter() {
+                let key = key.to_object().to_string();
+                if map.contains_key(&key) {
+                    return Err(args.invalid(format!("duplicate key {:?}", key)));
+                }
+                map.insert(key, to_json(args, value)?);
+            }
+            Value::Object(map)
+        }
+        Object::Function(_) | Object::Builtin(_) | Object::Module(_) | Object::Error(_) => {
+            return Err(args.invalid(format!(
+                "{} cannot be converted to JSON",
+                object.type_name()
+            )))
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{evaluator, object::Environment, parser};
+
+    fn eval(input: &str) -> String {
+        let program = parser::parse(input).unwrap();
+        evaluator::eval_program(&program, &Environment::new())
+            .map_or_else(|e| e.to_string(), |object| object.to_string())
+    }
+
+    // Monkey の文字列リテラルにする
+    fn quote(s: &str) -> String {
+        format!("{:?}", s)
+    }
+
+    #[test]
+    fn test_parse() {
+        for (json, expected) in [
+            (
+                r#"{"name": "x", "tags": ["a", 1, true, null], "n": {"m": -2}}"#,
+                r#"{"n": {"m": -2}, "name": "x", "tags": ["a", 1, true, null]}"#,
+            ),
+            (r#""a\nbé""#, "a\nbé"),
+            ("[]", "[]"),
+            (
+                "[1,\n  2,\n  ]",
+                "invalid argument to json_parse: trailing comma at line 3 column 3",
+            ),
+            (
+                r#"{"a" 1}"#,
+                "invalid argument to json_parse: expected `:` at line 1 column 6",
+            ),
+            (
+                "[1] x",
+                "invalid argument to json_parse: trailing characters at line 1 column 5",
+            ),
+            (
+                "1.5",
+                "invalid argument to json_parse: 1.5 is not a 64-bit integer",
+            ),
+            (
+                "18446744073709551615",
+                "invalid argument to json_parse: 18446744073709551615 is not a 64-bit integer",
+            ),
+        ] {
+            assert_eq!(
+                eval(&format!("json_parse({})", quote(json))),
+                expected,
+                "{}",
+                json
+            );
+        }
+        assert_eq!(
+            eval("json_parse(1)"),
+            "argument 1 to json_parse must be STRING, got INTEGER"
+        );
+    }
+
+    #[test]
+    fn test_stringify() {
+        for (input, expected) in [
+            (
+                r#"json_stringify({"b": [1, "x"], "a": if (false) { 1 }})"#,
+                r#"{"a":null,"b":[1,"x"]}"#,
+            ),
+            (r#"json_stringify("a\"b")"#, r#""a\"b""#),
+            (
+                r#"json_stringify({"a": [1, true], "b": {}}, 2)"#,
+                "{\n  \"a\": [\n    1,\n    true\n  ],\n  \"b\": {}\n}",
+            ),
+            (r#"json_stringify({1: 2, true: 3})"#, r#"{"1":2,"true":3}"#),
+            (
+                r#"json_stringify({1: 2, "1": 3})"#,
+                "invalid argument to json_stringify: duplicate key \"1\"",
+            ),
+            (
+                "json_stringify([fn(x) { x }])",
+                "invalid argument to json_stringify: FUNCTION cannot be converted to JSON",
+            ),
+            (
+                "json_stringify(math)",
+                "invalid argument to json_stringify: MODULE cannot be converted to JSON",
+            ),
+            (
+                "json_stringify(1, -1)",
+                "invalid argument to json_stringify: indent must be between 0 and 16, got -1",
+            ),
+        ] {
+            assert_eq!(eval(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // JSON → Monkey → JSON
+        for json in [
+            r#"{"a":[1,-2,{"b":null}],"c":"\n\"","d":true}"#,
+            "[]",
+            "9223372036854775807",
+        ] {
+            assert_eq!(
+                eval(&format!("json_stringify(json_parse({}))", quote(json))),
+                json
+            );
+        }
+        // Monkey → JSON → Monkey
+        for value in [
+            r#"{"k": [1, [2, [3]]], "s": "é\t", "z": {}}"#,
+            "[if (false) { 1 }, false, -9223372036854775807 - 1]",
+        ] {
+            assert_eq!(
+                eval(&format!(
+                    "let v = {}; json_parse(json_stringify(v, 4))",
+                    value
+                )),
+                eval(value)
+            );
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// 評価するプログラムが外の世界に触れるときの設定
+// ファイルや環境変数を使う組み込み関数は、ここで許可されているかを調べる
+use std::{
+    cell::{RefCell, RefMut},
+    fmt, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{object::Frame, token};
+
+// 許可の集合。Default は標準入出力だけを許す
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    read: Vec<PathBuf>,
+    write: Vec<PathBuf>,
+    env: bool,
+    args: bool,
+    stdio: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::none().allow_stdio()
+    }
+}
+
+impl Capabilities {
+    pub fn none() -> Self {
+        Self {
+            read: Vec::new(),
+            write: Vec::new(),
+            env: false,
+            args: false,
+            stdio: false,
+        }
+    }
+
+    // dir の下のファイルを読めるようにする
+    pub fn allow_read(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.read.push(dir.into());
+        self
+    }
+
+    // dir の下にファイルを書けるようにする
+    pub fn allow_write(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.write.push(dir.into());
+        self
+    }
+
+    pub fn allow_env(mut self) -> Self {
+        self.env = true;
+        self
+    }
+
+    // args() でスクリプトの引数を読めるようにする
+    pub fn allow_args(mut self) -> Self {
+        self.args = true;
+        self
+    }
+
+    pub fn allow_stdio(mut self) -> Self {
+        self.stdio = true;
+        self
+    }
+
+    pub fn can_read(&self, path: &Path) -> bool {
+        resolve(path).is_some_and(|path| is_under(&path, &self.read))
+    }
+
+    pub fn can_write(&self, path: &Path) -> bool {
+        resolve(path).is_some_and(|path| is_under(&path, &self.write))
+    }
+
+    pub fn can_use_env(&self) -> bool {
+        self.env
+    }
+
+    pub fn can_use_args(&self) -> bool {
+        self.args
+    }
+
+    pub fn can_use_stdio(&self) -> bool {
+        self.stdio
+    }
+}
+
+// まだないファイルは親のディレクトリを canonicalize してファイル名をつなぐ
+// リンク先がないシンボリックリンクは書き込むとリンク先に作られるので、リンク先をたどる
+fn resolve(path: &Path) -> Option<PathBuf> {
+    resolve_links(path, 0)
+}
+
+// リンクの循環で止まらないよう、たどる回数に上限を設ける
+fn resolve_links(path: &Path, depth: usize) -> Option<PathBuf> {
+    if let Ok(path) = fs::canonicalize(path) {
+        return Some(path);
+    }
+    if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink()) {
+        if depth >= 40 {
+            return None;
+        }
+        let target = fs::read_link(path).ok()?;
+        let target = match path.parent() {
+            Some(parent) if target.is_relative() => parent.join(target),
+            _ => target,
+        };
+        return resolve_links(&target, depth + 1);
+    }
+    let name = path.file_name()?;
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    fs::canonicalize(parent)
+        .ok()
+        .map(|parent| parent.join(name))
+}
+
+// シンボリックリンクや `..` で外に出られないよう、どちらも canonicalize してから比べる
+fn is_under(path: &Path, dirs: &[PathBuf]) -> bool {
+    dirs.iter()
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .any(|dir| path.starts_with(dir))
+}
+
+// 組み込み関数から見える評価の状態
+// 標準入出力は差し替えられるので、埋め込む側やテストは出力をバッファに受け取れる
+pub struct Context<'io> {
+    pub capabilities: Capabilities,
+    // args() で返すスクリプトの引数
+    pub args: Vec<String>,
+    // None ならプロセスの標準入力から読む。ほかの読み手と取り合わないよう先読みはしない
+    stdin: Option<RefCell<Box<dyn BufRead + 'io>>>,
+    stdout: RefCell<Box<dyn Write + 'io>>,
+    stderr: RefCell<Box<dyn Write + 'io>>,
+    // 呼び出し中の関数。内側の呼び出しが最後
+    frames: RefCell<Vec<Frame>>,
+    // frames がこの数に達したら、それ以上は呼び出さずにエラーにする
+    max_depth: usize,
+    // 組み込み関数が呼んだ関数で起きたエラーの場所。RuntimeError には載らないのでここで渡す
+    pending: RefCell<Location>,
+}
+
+// 既定の呼び出しの深さの上限。ループがないので再帰で回せる回数でもある
+pub const MAX_DEPTH: usize = 10_000;
+
+// 実行時エラーが起きた場所。どちらも最初に分かったところで埋める
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Location {
+    // エラーになった一番内側の式
+    pub span: Option<token::Span>,
+    // エラーが最初に関数の呼び出しを抜けるときに呼び出し中だった関数
+    pub trace: Option<Vec<Frame>>,
+}
+
+impl Default for Context<'_> {
+    fn default() -> Self {
+        Self::new(Capabilities::default(), Vec::new())
+    }
+}
+
+impl fmt::Debug for Context<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("capabilities", &self.capabilities)
+            .field("args", &self.args)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'io> Context<'io> {
+    pub fn new(capabilities: Capabilities, args: Vec<String>) -> Self {
+        Self {
+            capabilities,
+            args,
+            stdin: None,
+            stdout: RefCell::new(Box::new(io::stdout())),
+            stderr: RefCell::new(Box::new(io::stderr())),
+            frames: RefCell::new(Vec::new()),
+            max_depth: MAX_DEPTH,
+            pending: RefCell::new(Location::default()),
+        }
+    }
+
+    pub fn with_stdin(mut self, stdin: impl BufRead + 'io) -> Self {
+        self.stdin = Some(RefCell::new(Box::new(stdin)));
+        self
+    }
+
+    pub fn with_stdout(mut self, stdout: impl Write + 'io) -> Self {
+        self.stdout = RefCell::new(Box::new(stdout));
+        self
+    }
+
+    pub fn with_stderr(mut self, stderr: impl Write + 'io) -> Self {
+        self.stderr = RefCell::new(Box::new(stderr));
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // 改行も含めて buf に足し、読んだバイト数を返す
+    pub(crate) fn read_line(&self, buf: &mut String) -> io::Result<usize> {
+        match &self.stdin {
+            Some(stdin) => stdin.borrow_mut().read_line(buf),
+            None => io::stdin().read_line(buf),
+        }
+    }
+
+    pub(crate) fn stdout(&self) -> RefMut<'_, Box<dyn Write + 'io>> {
+        self.stdout.borrow_mut()
+    }
+
+    pub(crate) fn stderr(&self) -> RefMut<'_, Box<dyn Write + 'io>> {
+        self.stderr.borrow_mut()
+    }
+
+    pub(crate) fn push_frame(&self, frame: Frame) {
+        self.frames.borrow_mut().push(frame);
+    }
+
+    pub(crate) fn pop_frame(&self) {
+        self.frames.borrow_mut().pop();
+    }
+
+    // もう 1 段呼び出せるか
+    pub(crate) fn can_call(&self) -> bool {
+        self.frames.borrow().len() < self.max_depth
+    }
+
+    pub(crate) fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    // 今呼び出し中の関数の並び
+    pub(crate) fn trace(&self) -> Vec<Frame> {
+        self.frames.borrow().clone()
+    }
+
+    pub(crate) fn set_pending(&self, location: Location) {
+        *self.pending.borrow_mut() = location;
+    }
+
+    pub(crate) fn take_pending(&self) -> Location {
+        self.pending.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::Capabilities;
+
+    #[test]
+    fn test_capabilities() {
+        let dir =
+            std::env::temp_dir().join(format!("interpreter-rs-context-{}", std::process::id()));
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data/a.txt"), "a").unwrap();
+        fs::write(dir.join("secret.txt"), "s").unwrap();
+
+        let none = Capabilities::none();
+        assert!(!none.can_read(&dir.join("data/a.txt")));
+        assert!(!none.can_use_stdio());
+        assert!(Capabilities::default().can_use_stdio());
+
+        let c = Capabilities::none()
+            .allow_read(dir.join("data"))
+            .allow_write(dir.join("data"));
+        assert!(c.can_read(&dir.join("data/a.txt")));
+        assert!(!c.can_read(&dir.join("secret.txt")));
+        assert!(!c.can_read(&dir.join("data/../secret.txt")));
+        assert!(c.can_read(&dir.join("data/b.txt")));
+        assert!(c.can_write(&dir.join("data/b.txt")));
+        assert!(!c.can_write(&dir.join("b.txt")));
+        assert!(!c.can_write(&dir.join("data/..")));
+        assert!(!c.can_write(&dir.join("data/../b.txt")));
+        assert!(!c.can_use_env());
+        assert!(!c.can_use_args());
+        assert!(!Capabilities::default().can_use_args());
+        assert!(Capabilities::none().allow_args().can_use_args());
+
+        // リンク先がまだないシンボリックリンクはリンク先で調べる
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+            symlink(dir.join("pwned.txt"), dir.join("data/out")).unwrap();
+            symlink("c.txt", dir.join("data/in")).unwrap();
+            symlink("loop", dir.join("data/loop")).unwrap();
+            assert!(!c.can_write(&dir.join("data/out")));
+            assert!(!c.can_read(&dir.join("data/out")));
+            assert!(c.can_write(&dir.join("data/in")));
+            assert!(!c.can_write(&dir.join("data/loop")));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+// import したファイルを読み込んで評価する
+// 同じファイルは一度だけ評価し、二度目以降は同じモジュールを返す
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use super::{Context, Location, RuntimeError};
+use crate::{
+    ast::{self, statement},
+    object::{Env, Environment, Frame, Module, Object},
+    parser, token,
+};
+
+#[derive(Debug, Default)]
+pub struct Loader<'io> {
+    // canonicalize したパスをキーにする
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // 評価中のファイル。循環の検出に使う
+    loading: Vec<(PathBuf, String)>,
+    context: Context<'io>,
+    // 最後の eval_program が返したエラーの位置とトレース
+    span: Option<token::Span>,
+    trace: Vec<Frame>,
+}
+
+impl<'io> Loader<'io> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_context(context: Context<'io>) -> Self {
+        Self {
+            context,
+            ..Self::default()
+        }
+    }
+
+    pub fn context(&self) -> &Context<'io> {
+        &self.context
+    }
+
+    // 最後の eval_program がエラーを返したときに呼び出し中だった関数。内側の呼び出しが最後
+    // 関数の外で起きたエラーなら空
+    pub fn trace(&self) -> &[Frame] {
+        &self.trace
+    }
+
+    // 最後の eval_program が返したエラーになった式の位置。import したファイルの中なら、その中での位置
+    pub fn span(&self) -> Option<token::Span> {
+        self.span
+    }
+
+    // import したモジュールの中で先に埋めたものは上書きしない
+    pub(super) fn locate(&mut self, location: Location) {
+        if self.span.is_none() {
+            self.span = location.span;
+        }
+        if self.trace.is_empty() {
+            self.trace = location.trace.unwrap_or_default();
+        }
+    }
+
+    // 読み込んだモジュールを忘れる。context はそのまま
+    pub fn clear(&mut self) {
+        self.modules.clear();
+    }
+
+    // file は program を読んだファイル。import はそのディレクトリから探し、なければカレントディレクトリから探す
+    pub fn eval_program(
+        &mut self,
+        program: &ast::Program,
+        env: &Env,
+        file: Option<&Path>,
+    ) -> Result<Object, RuntimeError> {
+        self.span = None;
+        self.trace.clear();
+        let base = file.and_then(Path::parent).unwrap_or_else(|| Path::new(""));
+        let canonical = file.and_then(|file| fs::canonicalize(file).ok());
+        match canonical {
+            Some(canonical) => {
+                let name = file.unwrap().display().to_string();
+                self.loading.push((canonical, name));
+                let result = super::eval_module(program, env, self, base);
+                self.loading.pop();
+                result
+            }
+            None => super::eval_module(program, env, self, base),
+        }
+    }
+
+    pub(super) fn load(&mut self, path: &Path) -> Result<Rc<Module>, RuntimeError> {
+        let name = path.display().to_string();
+        // import も read_file と同じく、読むことを許可したファイルだけ読む
+        if !self.context.capabilities.can_read(path) {
+            return Err(RuntimeError::PermissionDenied {
+                function: "import",
+                capability: format!("read access to {}", name),
+            });
+        }
+        let failed = |reason: String| RuntimeError::ImportFailed {
+            path: name.clone(),
+            reason,
+        };
+        let canonical = fs::canonicalize(path).map_err(|e| failed(e.to_string()))?;
+        if let Some(i) = self.loading.iter().position(|(p, _)| *p == canonical) {
+            let mut cycle: Vec<&str> = self.loading[i..].iter().map(|(_, n)| n.as_str()).collect();
+            cycle.push(&name);
+            return Err(RuntimeError::ImportCycle {
+                cycle: cycle.join(" -> "),
+            });
+        }
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(module.clone());
+        }
+
+        let source = fs::read_to_string(&canonical).map_err(|e| failed(e.to_string()))?;
+        let program = parser::parse(&source).map_err(|errors| {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            failed(messages.join("; "))
+        })?;
+        let env = Environment::new();
+        let base = canonical
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf();
+        self.loading.push((canonical.clone(), name.clone()));
+        let result = super::eval_module(&program, &env, self, &base);
+        self.loading.pop();
+        result?;
+
+        let exports: BTreeMap<String, Object> = program
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                statement::Statement::ExportStatement(s) => Some(s.statement.name.name()),
+                _ => None,
+            })
+            .filter_map(|name| {
+                env.borrow()
+                    .get(name)
+                    .map(|value| (name.to_string(), value))
+            })
+            .collect();
+        let module = Rc::new(Module { name, exports });
+        self.modules.insert(canonical, module.clone());
+        Ok(module)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        rc::Rc,
+    };
+
+    use super::Loader;
+    use crate::{
+        evaluator::{Capabilities, Context},
+        object::Environment,
+        parser,
+    };
+
+    // テストごとに別のディレクトリにファイルを書く
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "interpreter-rs-loader-{}-{}",
+            test,
+            std::process::id()
+        ));
+        for (name, source) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    // dir の下を読めるようにする
+    fn loader(dir: &Path) -> Loader<'static> {
+        Loader::with_context(Context::new(
+            Capabilities::default().allow_read(dir),
+            Vec::new(),
+        ))
+    }
+
+    fn run(dir: &Path, main: &str) -> String {
+        let file = dir.join(main);
+        let program = parser::parse(&fs::read_to_string(&file).unwrap()).unwrap();
+        loader(dir)
+            .eval_program(&program, &Environment::new(), Some(&file))
+            .map_or_else(|e| e.to_string(), |object| object.to_string())
+    }
+
+    #[test]
+    fn test_import() {
+        let dir = write_files(
+            "import",
+            &[
+                (
+                    "main.mk",
+                    r#"import "lib/math.mk" as math; import "lib/../lib/math.mk" as again;
+                    math.double(math.base) + again.base"#,
+                ),
+                (
+                    "lib/math.mk",
+                    r#"import "util.mk" as util;
+                    let hidden = 10; export let base = util.one + hidden;
+                    export let double = fn(x) { x * 2 };"#,
+                ),
+                ("lib/util.mk", "export let one = 1;"),
+                ("private.mk", r#"import "lib/math.mk" as m; m.hidden"#),
+                ("missing.mk", r#"import "nothing.mk" as n; 1"#),
+                ("broken.mk", r#"import "lib/bad.mk" as b;"#),
+                ("lib/bad.mk", "let = 1;"),
+                ("not_module.mk", "let x = 1; x.y"),
+            ],
+        );
+        // util.mk は lib/math.mk からの相対パスで探す
+        assert_eq!(run(&dir, "main.mk"), "33");
+        assert_eq!(
+            run(&dir, "private.mk"),
+            format!(
+                "module {} has no export hidden",
+                dir.join("lib/math.mk").display()
+            )
+        );
+        assert!(run(&dir, "missing.mk").starts_with(&format!(
+            "cannot import {}: ",
+            dir.join("nothing.mk").display()
+        )));
+        assert_eq!(
+            run(&dir, "broken.mk"),
+            format!(
+                "cannot import {}: expected next token to be Ident, got = instead; no prefix parse function for = found",
+                dir.join("lib/bad.mk").display()
+            )
+        );
+        assert_eq!(
+            run(&dir, "not_module.mk"),
+            "member access not supported: INTEGER"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_requires_read() {
+        let dir = write_files(
+            "read",
+            &[
+                ("main.mk", r#"import "lib/a.mk" as a; a.x"#),
+                ("lib/a.mk", "export let x = 1;"),
+                ("lib/main.mk", r#"import "../private.mk" as p; p.y"#),
+                ("private.mk", "export let y = 2;"),
+            ],
+        );
+        let eval = |mut loader: Loader, main: &str| {
+            let file = dir.join(main);
+            let program = parser::parse(&fs::read_to_string(&file).unwrap()).unwrap();
+            loader
+                .eval_program(&program, &Environment::new(), Some(&file))
+                .map_or_else(|e| e.to_string(), |object| object.to_string())
+        };
+        assert_eq!(eval(loader(&dir), "main.mk"), "1");
+        // 読むことを許可していないファイルは import できない
+        assert_eq!(
+            eval(Loader::new(), "main.mk"),
+            format!(
+                "permission denied: import needs read access to {}",
+                dir.join("lib/a.mk").display()
+            )
+        );
+        // `..` で許可したディレクトリの外に出られない
+        assert_eq!(
+            eval(loader(&dir.join("lib")), "lib/main.mk"),
+            format!(
+                "permission denied: import needs read access to {}",
+                dir.join("lib/../private.mk").display()
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_and_cycle() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("a.mk", r#"import "b.mk" as b; export let x = 1;"#),
+                ("b.mk", r#"import "a.mk" as a; export let y = 2;"#),
+                ("c.mk", "export let z = 3;"),
+            ],
+        );
+        assert_eq!(
+            run(&dir, "a.mk"),
+            format!(
+                "import cycle: {} -> {} -> {}",
+                dir.join("a.mk").display(),
+                dir.join("b.mk").display(),
+                dir.join("a.mk").display()
+            )
+        );
+
+        // 同じファイルは同じモジュールになる
+        let mut loader = loader(&dir);
+        let first = loader.load(&dir.join("c.mk")).unwrap();
+        let second = loader.load(&dir.join(".").join("c.mk")).unwrap();
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(first.exports["z"].to_string(), "3");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_trace() {
+        let dir = write_files(
+            "trace",
+            &[
+                ("main.mk", "import \"lib.mk\" as lib;\nlib.check(0)"),
+                (
+                    "lib.mk",
+                    "export let check = fn(n) { if (n == 0) { fail() } n };\nlet fail = fn() { 1 / 0 };",
+                ),
+                ("bad.mk", "import \"broken.mk\" as b;"),
+                ("broken.mk", "let f = fn() { 1 + true }; let g = fn() { f() };\ng()"),
+            ],
+        );
+        let mut loader = loader(&dir);
+        let trace = |loader: &mut Loader, main: &str| {
+            let file = dir.join(main);
+            let program = parser::parse(&fs::read_to_string(&file).unwrap()).unwrap();
+            let _ = loader.eval_program(&program, &Environment::new(), Some(&file));
+            loader
+                .trace()
+                .iter()
+                .map(|frame| frame.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            trace(&mut loader, "main.mk"),
+            vec![
+                "<anonymous> (let check) at 2:1",
+                "<anonymous> (let fail) at 1:42",
+            ]
+        );
+        // import したファイルの中のエラーはそこでのトレースを残す
+        assert_eq!(
+            trace(&mut loader, "bad.mk"),
+            vec!["<anonymous> (let g) at 2:1", "<anonymous> (let f) at 1:43"]
+        );
+        let _ = loader.eval_program(&parser::parse("1 / 0").unwrap(), &Environment::new(), None);
+        assert!(loader.trace().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
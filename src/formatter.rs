@@ -0,0 +1,642 @@
+// ソースを決まった形に整形する
+// 括弧は必要なものだけ残し、コメントと文の間の空行（1 行まで）は保持する
+// 式の途中のコメントはその場所に書く。置ける場所がなく動いてしまうときは整形しない
+use thiserror::Error;
+
+use crate::{
+    ast::{expression, statement, Node},
+    lexer,
+    parser::{self, ParseError, Precedence},
+    token,
+};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    // インデントの空白数
+    pub indent: usize,
+    // 呼び出しの引数がこの幅を超えるときは 1 行に 1 つずつ並べる
+    pub max_width: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            indent: 4,
+            max_width: 80,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("cannot format source with {} parse error(s)", .0.len())]
+    Parse(Vec<ParseError>),
+    #[error("{position}: cannot keep this comment in place")]
+    MovedComment { position: token::Position },
+}
+
+impl FormatError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FormatError::Parse(_) => "E0101",
+            FormatError::MovedComment { .. } => "E0401",
+        }
+    }
+}
+
+impl From<Vec<ParseError>> for FormatError {
+    fn from(errors: Vec<ParseError>) -> Self {
+        FormatError::Parse(errors)
+    }
+}
+
+pub fn format(source: &str) -> Result<String, FormatError> {
+    format_with(source, &Options::default())
+}
+
+pub fn format_with(source: &str, options: &Options) -> Result<String, FormatError> {
+    let program = parser::parse(source)?;
+    let comments = lexer::Lexer::new(source.to_string())
+        .keep_comments(true)
+        .filter(|t| t.typ == token::TokenType::Comment)
+        .collect();
+    let mut p = Printer::new(options, comments);
+    p.statements(&program.statements, usize::MAX);
+    if !p.out.is_empty() {
+        p.out.push('\n');
+    }
+    // コメントの前後の token が変わっていれば、コメントの指すものが変わってしまう
+    let (before, after) = (anchors(source), anchors(&p.out));
+    let moved = before
+        .iter()
+        .enumerate()
+        .find(|(i, (_, count))| after.get(*i).map(|a| a.1) != Some(*count));
+    if let Some((_, (position, _))) = moved {
+        return Err(FormatError::MovedComment {
+            position: *position,
+        });
+    }
+    Ok(p.out)
+}
+
+// コメントごとに、その前にある token の数を数える
+// 括弧と `;` と `,` は整形で増えたり減ったりするので数えない
+fn anchors(source: &str) -> Vec<(token::Position, usize)> {
+    use token::TokenType::*;
+    let mut count = 0;
+    let mut anchors = Vec::new();
+    for t in lexer::Lexer::new(source.to_string()).keep_comments(true) {
+        match t.typ {
+            Comment => anchors.push((t.pos, count)),
+            Lparne | Rparne | Semicolon | Comma => {}
+            _ => count += 1,
+        }
+    }
+    anchors
+}
+
+struct Printer<'a> {
+    options: &'a Options,
+    out: String,
+    depth: usize,
+    // まだ出力していないコメント（ソース順）
+    comments: std::iter::Peekable<std::vec::IntoIter<token::Token>>,
+    // 最後に出力した要素の元の行。ブロックの先頭では None
+    last_line: Option<usize>,
+    // `;` を省いた直前の if 文の終わり
+    open_if: Option<usize>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(options: &'a Options, comments: Vec<token::Token>) -> Self {
+        Self {
+            options,
+            out: String::new(),
+            depth: 0,
+            comments: comments.into_iter().peekable(),
+            last_line: None,
+            open_if: None,
+        }
+    }
+
+    // コメントを出力しないときに使う。幅の計算用
+    fn flat(&self) -> Self {
+        Self::new(self.options, Vec::new())
+    }
+
+    fn column(&self) -> usize {
+        let line = self.out.rsplit('\n').next().unwrap_or("");
+        line.chars().count()
+    }
+
+    // 改行とインデントを入れ、元のソースで空行があれば 1 行だけ残す
+    fn line(&mut self, source_line: usize) {
+        if let Some(last) = self.last_line {
+            self.out.push('\n');
+            if source_line > last + 1 {
+                self.out.push('\n');
+            }
+        } else if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.indent();
+    }
+
+    fn indent(&mut self) {
+        let width = self.depth * self.options.indent;
+        self.out.extend(std::iter::repeat_n(' ', width));
+    }
+
+    fn comment(&mut self, comment: &token::Token) {
+        self.out
+            .push_str(std::str::from_utf8(&comment.literal[..]).unwrap());
+        self.last_line = Some(comment.end.line);
+    }
+
+    // offset より前にあるコメントをそれぞれ 1 行で出力する
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.next_if(|c| c.pos.offset < offset) {
+            self.line(comment.pos.line);
+            self.comment(&comment);
+        }
+    }
+
+    // 式の途中にある offset より前のコメントをその場所に書く
+    // 行コメントの後は改行し、コメントだけの行ならその字下げで、そうでなければ depth 段の字下げで続ける
+    fn inline_comments(&mut self, offset: usize, depth: usize) {
+        while let Some(comment) = self.comments.next_if(|c| c.pos.offset < offset) {
+            let line = self.out.rsplit('\n').next().unwrap_or("").to_string();
+            if !self.out.is_empty() && !self.out.ends_with([' ', '\n', '(', '[', '{']) {
+                self.out.push(' ');
+            }
+            self.out
+                .push_str(std::str::from_utf8(&comment.literal[..]).unwrap());
+            if comment.literal.starts_with(b"//") {
+                self.out.push('\n');
+                if line.trim().is_empty() {
+                    self.out.push_str(&line);
+                } else {
+                    self.out
+                        .extend(std::iter::repeat_n(' ', depth * self.options.indent));
+                }
+            }
+        }
+    }
+
+    // 直前にブロックコメントを書いていれば、続く要素との間を空ける
+    fn space_after_comment(&mut self) {
+        if self.out.ends_with("*/") {
+            self.out.push(' ');
+        }
+    }
+
+    // 直前が空白や改行でなければ空白を入れる
+    fn space(&mut self) {
+        if !self.out.ends_with([' ', '\n']) {
+            self.out.push(' ');
+        }
+    }
+
+    // 同じ行で始まり、次の要素より前にあるコメントは行末に残す
+    fn trailing_comment(&mut self, line: usize, before: usize) {
+        if let Some(comment) = self
+            .comments
+            .next_if(|c| c.pos.line == line && c.pos.offset < before)
+        {
+            self.out.push(' ');
+            self.comment(&comment);
+        }
+    }
+
+    // end は文の並びを閉じる位置。そこまでのコメントも出力する
+    fn statements(&mut self, statements: &[statement::Statement], end: usize) {
+        for (i, s) in statements.iter().enumerate() {
+            let next = statements
+                .get(i + 1)
+                .map_or(end, |next| next.span().start.offset);
+            self.statement(s, next);
+        }
+        self.open_if = None;
+        self.comments_before(end);
+    }
+
+    fn statement(&mut self, s: &statement::Statement, next: usize) {
+        let span = s.span();
+        self.comments_before(span.start.offset);
+        self.line(span.start.line);
+        let open_if = self.open_if.take();
+        let start = self.out.len();
+        match s {
+            statement::Statement::LetStatement(s) => self.let_statement(s),
+            statement::Statement::ReturnStatement(s) => {
+                self.out.push_str("return ");
+                self.expression(&s.return_value);
+                self.out.push(';');
+            }
+            statement::Statement::ExpressionStatement(s) => {
+                self.expression(&s.expression);
+                // ブロックで終わる if と try の後ろには付けない
+                if matches!(
+                    s.expression,
+                    expression::Expression::IfExpression(_)
+                        | expression::Expression::TryExpression(_)
+                ) {
+                    self.open_if = Some(self.out.len());
+                } else {
+                    self.out.push(';');
+                }
+            }
+            statement::Statement::ImportStatement(s) => {
+                self.out
+                    .push_str(&format!("import {} as {};", s.path, s.alias.name()));
+            }
+            statement::Statement::ExportStatement(s) => {
+                self.out.push_str("export ");
+                self.let_statement(&s.statement);
+            }
+            statement::Statement::ThrowStatement(s) => {
+                self.out.push_str("throw ");
+                self.expression(&s.value);
+                self.out.push(';');
+            }
+        }
+        // 続く文が `(` `[` `-` で始まると if に続く式として読まれるので、`;` を戻す
+        if let Some(at) = open_if {
+            if self.out[start..].starts_with(['(', '[', '-']) {
+                self.out.insert(at, ';');
+            }
+        }
+        self.last_line = Some(span.end.line);
+        self.trailing_comment(span.end.line, next);
+    }
+
+    fn let_statement(&mut self, s: &statement::LetStatement) {
+        self.out.push_str("let ");
+        self.out.push_str(s.name.name());
+        if let Some(annotation) = &s.annotation {
+            self.out.push_str(&format!(": {}", annotation));
+        }
+        self.out.push_str(" = ");
+        self.expression(&s.value);
+        self.out.push(';');
+    }
+
+    fn block(&mut self, block: &statement::BlockStatement) {
+        self.inline_comments(block.token.pos.offset, self.depth);
+        self.space_after_comment();
+        let has_comments = self
+            .comments
+            .peek()
+            .is_some_and(|c| c.pos.offset < block.rbrace.pos.offset);
+        if block.statements.is_empty() && !has_comments {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        self.depth += 1;
+        self.last_line = None;
+        let first = block
+            .statements
+            .first()
+            .map_or(block.rbrace.pos.offset, |s| s.span().start.offset);
+        self.trailing_comment(block.token.pos.line, first);
+        self.statements(&block.statements, block.rbrace.pos.offset);
+        self.depth -= 1;
+        self.out.push('\n');
+        self.indent();
+        self.out.push('}');
+    }
+
+    fn operand(&mut self, e: &expression::Expression, parenthesize: bool) {
+        if parenthesize {
+            self.out.push('(');
+            self.expression(e);
+            self.out.push(')');
+        } else {
+            self.expression(e);
+        }
+    }
+
+    fn expression(&mut self, e: &expression::Expression) {
+        self.inline_comments(e.span().start.offset, self.depth + 1);
+        self.space_after_comment();
+        match e {
+            expression::Expression::Identifier(e) => self.out.push_str(e.name()),
+            expression::Expression::IntegerLiteral(e) => self.out.push_str(&e.to_string()),
+            expression::Expression::StringLiteral(e) => self.out.push_str(&e.to_string()),
+            expression::Expression::Boolean(e) => self.out.push_str(&e.to_string()),
+            expression::Expression::PrefixExpression(e) => {
+                self.out.push_str(&e.operator);
+                self.operand(&e.right, precedence(&e.right) < Precedence::Prefix);
+            }
+            expression::Expression::InfixExpression(e) => {
+                let own = Precedence::of(&e.token.typ);
+                // 左結合なので、右側は同じ優先順位でも括弧が要る
+                self.operand(&e.left, precedence(&e.left) < own);
+                self.inline_comments(e.token.pos.offset, self.depth + 1);
+                self.space();
+                self.out.push_str(&format!("{} ", e.operator));
+                self.operand(&e.right, precedence(&e.right) <= own);
+            }
+            expression::Expression::IfExpression(e) => {
+                self.out.push_str("if (");
+                self.expression(&e.condition);
+                self.out.push_str(") ");
+                self.block(&e.consequence);
+                if let Some(alternative) = &e.alternative {
+                    // `}` と else の間のコメントは else の前に書く
+                    self.inline_comments(alternative.token.pos.offset, self.depth);
+                    self.space();
+                    self.out.push_str("else ");
+                    self.block(alternative);
+                }
+            }
+            expression::Expression::FunctionLiteral(e) => {
+                let parameters: Vec<String> = e.parameters.iter().map(|p| p.to_string()).collect();
+                self.out
+                    .push_str(&format!("fn({}) ", parameters.join(", ")));
+                if let Some(return_type) = &e.return_type {
+                    self.out.push_str(&format!("-> {} ", return_type));
+                }
+                self.block(&e.body);
+            }
+            expression::Expression::CallExpression(e) => {
+                self.operand(&e.function, precedence(&e.function) < Precedence::Call);
+                self.arguments(&e.arguments, e.rparen.pos.offset);
+            }
+            expression::Expression::ArrayLiteral(e) => {
+                self.out.push('[');
+                self.list(&e.elements);
+                self.inline_comments(e.rbracket.pos.offset, self.depth);
+                self.out.push(']');
+            }
+            expression::Expression::IndexExpression(e) => {
+                self.operand(&e.left, precedence(&e.left) < Precedence::Call);
+                self.out.push('[');
+                self.expression(&e.index);
+                self.inline_comments(e.rbracket.pos.offset, self.depth);
+                self.out.push(']');
+            }
+            expression::Expression::HashLiteral(e) => {
+                self.out.push('{');
+                for (i, (key, value)) in e.pairs.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(key);
+                    self.out.push_str(": ");
+                    self.expression(value);
+                }
+                self.inline_comments(e.rbrace.pos.offset, self.depth);
+                self.out.push('}');
+            }
+            expression::Expression::MemberExpression(e) => {
+                self.operand(&e.object, precedence(&e.object) < Precedence::Call);
+                self.inline_comments(e.token.pos.offset, self.depth + 1);
+                self.out.push('.');
+                self.out.push_str(e.member.name());
+            }
+            expression::Expression::TryExpression(e) => {
+                self.out.push_str("try ");
+                self.block(&e.block);
+                if let Some(catch) = &e.catch {
+                    self.inline_comments(catch.token.pos.offset, self.depth);
+                    self.space();
+                    self.out
+                        .push_str(&format!("catch ({}) ", catch.parameter.name()));
+                    self.block(&catch.body);
+                }
+                if let Some(finally) = &e.finally {
+                    self.space();
+                    self.out.push_str("finally ");
+                    self.block(finally);
+                }
+            }
+        }
+    }
+
+    fn list(&mut self, items: &[expression::Expression]) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expression(item);
+        }
+    }
+
+    // 1 行に収まらない引数は 1 行に 1 つずつ並べる。end は `)` の位置
+    fn arguments(&mut self, arguments: &[expression::Expression], end: usize) {
+        let mut flat = self.flat();
+        flat.list(arguments);
+        let fits = !flat.out.contains('\n')
+            && self.column() + flat.out.chars().count() + 2 <= self.options.max_width;
+        if fits || arguments.is_empty() {
+            self.out.push('(');
+            self.list(arguments);
+            self.inline_comments(end, self.depth);
+            self.out.push(')');
+            return;
+        }
+        self.out.push('(');
+        self.depth += 1;
+        for (i, argument) in arguments.iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            self.out.push('\n');
+            self.indent();
+            self.expression(argument);
+        }
+        self.inline_comments(end, self.depth);
+        self.depth -= 1;
+        // 行コメントの後ならもう改行している
+        let start = self.out.rfind('\n').map_or(0, |i| i + 1);
+        if self.out[start..].trim().is_empty() {
+            self.out.truncate(start);
+        } else {
+            self.out.push('\n');
+        }
+        self.indent();
+        self.out.push(')');
+    }
+}
+
+// 子として置いたときに括弧が要るかどうかの判定に使う
+fn precedence(e: &expression::Expression) -> Precedence {
+    match e {
+        expression::Expression::PrefixExpression(_) => Precedence::Prefix,
+        expression::Expression::InfixExpression(e) => Precedence::of(&e.token.typ),
+        expression::Expression::CallExpression(_) => Precedence::Call,
+        _ => Precedence::Index,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format, format_with, FormatError, Options};
+    use crate::parser;
+
+    fn assert_format(input: &str, expected: &str) {
+        let formatted = format(input).unwrap();
+        assert_eq!(formatted, expected);
+        // 整形し直しても変わらず、木も変わらない
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        assert_eq!(
+            parser::parse(&formatted).unwrap().to_string(),
+            parser::parse(input).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_if_statement_semicolon() {
+        // if 文の後ろの `;` は、省くと次の文が if に続く式として読まれるときだけ残す
+        assert_format(
+            "if (x) { 1 }; (f)(1); if (x) { 2 }; -1; if (x) { 3 }; [1]",
+            "if (x) {\n    1;\n}\nf(1);\nif (x) {\n    2;\n};\n-1;\nif (x) {\n    3;\n};\n[1];\n",
+        );
+        assert_format(
+            "let f = fn() { if (x) { 1 }; -2 };",
+            "let f = fn() {\n    if (x) {\n        1;\n    };\n    -2;\n};\n",
+        );
+        assert_format(
+            "if (x) { 1 } else { 2 }; (a + b) * c",
+            "if (x) {\n    1;\n} else {\n    2;\n};\n(a + b) * c;\n",
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_format("let x=1+2*3", "let x = 1 + 2 * 3;\n");
+        assert_format(
+            "let y = (1+2)*3; -(a+b); (-a)[0]; a-(b-c); (a-b)-c; (f)(x)(y);",
+            "let y = (1 + 2) * 3;\n-(a + b);\n(-a)[0];\na - (b - c);\na - b - c;\nf(x)(y);\n",
+        );
+        assert_format(
+            "let f=fn(a,b){if(a<b){return a}else{b}};f(1,[1,2],{\"k\":true})",
+            r#"let f = fn(a, b) {
+    if (a < b) {
+        return a;
+    } else {
+        b;
+    }
+};
+f(1, [1, 2], {"k": true});
+"#,
+        );
+        assert_format("if (x) {} else { }", "if (x) {} else {}\n");
+        assert_format(
+            "let f:fn(int,[str])->{str:int}=fn(a:int,b:[str])->{str:int}{{}}",
+            "let f: fn(int, [str]) -> {str: int} = fn(a: int, b: [str]) -> {str: int} {\n    {};\n};\n",
+        );
+        assert_format(
+            "let a = 1; let b = fn() { a }; // b",
+            "let a = 1;\nlet b = fn() {\n    a;\n}; // b\n",
+        );
+        assert_format(
+            "import \"lib.mk\" as lib;export let x=(-lib.a).b+lib.f(1)[0].c",
+            "import \"lib.mk\" as lib;\nexport let x = (-lib.a).b + lib.f(1)[0].c;\n",
+        );
+        assert_format(
+            "try{throw f(1)}catch(e){e}finally{g()};let v=try{1}finally{}",
+            "try {\n    throw f(1);\n} catch (e) {\n    e;\n} finally {\n    g();\n}\nlet v = try {\n    1;\n} finally {};\n",
+        );
+        assert_format("", "");
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_format(
+            r#"// head
+let a = 1; // trailing
+
+
+/* block
+   comment */
+let f = fn() { // open
+  // inner
+  a
+  // last
+};
+let g = fn() {
+    // only comment
+};
+// tail
+"#,
+            r#"// head
+let a = 1; // trailing
+
+/* block
+   comment */
+let f = fn() { // open
+    // inner
+    a;
+    // last
+};
+let g = fn() {
+    // only comment
+};
+// tail
+"#,
+        );
+    }
+
+    #[test]
+    fn test_comments_in_expressions() {
+        // 式の途中のコメントはその場所に残す
+        assert_format("f(1, // one\n 2);", "f(1, // one\n    2);\n");
+        assert_format("let x = 1 /* c */ + 2;", "let x = 1 /* c */ + 2;\n");
+        assert_format(
+            "if (x) { 1 } // t\nelse { 2 }",
+            "if (x) {\n    1;\n} // t\nelse {\n    2;\n}\n",
+        );
+        assert_format(
+            "let a = [1, /* two */ 2 /* end */]; h[/* i */ 0]; m /* m */ .b;",
+            "let a = [1, /* two */ 2 /* end */];\nh[/* i */ 0];\nm /* m */.b;\n",
+        );
+        assert_format(
+            "try { 1 } /* c */ catch (e) { 2 }; let f = fn(a) /* c */ { a };",
+            "try {\n    1;\n} /* c */ catch (e) {\n    2;\n}\nlet f = fn(a) /* c */ {\n    a;\n};\n",
+        );
+        let options = Options {
+            indent: 2,
+            max_width: 20,
+        };
+        assert_eq!(
+            format_with("add(first, // a\n second, third // c\n);", &options).unwrap(),
+            "add(\n  first,\n  // a\n  second,\n  third // c\n);\n"
+        );
+
+        // 置ける場所のないコメントは動かさずに整形をやめる
+        match format("let x /* c */ = 1;") {
+            Err(e @ FormatError::MovedComment { .. }) => {
+                assert_eq!(e.to_string(), "1:7: cannot keep this comment in place");
+                assert_eq!(e.code(), "E0401");
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_wrap_arguments() {
+        let options = Options {
+            indent: 2,
+            max_width: 20,
+        };
+        let formatted = format_with("f(1, 2); let x = add(first, second, third);", &options);
+        assert_eq!(
+            formatted.unwrap(),
+            "f(1, 2);\nlet x = add(\n  first,\n  second,\n  third\n);\n"
+        );
+
+        // 関数リテラルを含む引数は折り返す
+        assert_format(
+            "map(xs, fn(x) { x * 2 })",
+            "map(\n    xs,\n    fn(x) {\n        x * 2;\n    }\n);\n",
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(format("let = 1;").is_err());
+    }
+}
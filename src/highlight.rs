@@ -0,0 +1,393 @@
+// ソースの範囲ごとに種類を決めて色をつける
+// token だけで決められないもの（識別子が関数か引数か）は parse と名前解決の結果を使う
+use std::collections::HashSet;
+
+use serde_json::{json, Value};
+
+use crate::{
+    ast::arena::{Arena, NodeKind},
+    evaluator, lexer, parser, resolver, token,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Category {
+    Keyword,
+    // 名前解決できなかった識別子
+    Identifier,
+    Function,
+    Parameter,
+    Variable,
+    Number,
+    String,
+    Operator,
+    Punctuation,
+    Comment,
+}
+
+impl Category {
+    // HTML の class 名にも使う
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Keyword => "keyword",
+            Category::Identifier => "identifier",
+            Category::Function => "function",
+            Category::Parameter => "parameter",
+            Category::Variable => "variable",
+            Category::Number => "number",
+            Category::String => "string",
+            Category::Operator => "operator",
+            Category::Punctuation => "punctuation",
+            Category::Comment => "comment",
+        }
+    }
+
+    fn of(typ: &token::TokenType) -> Option<Self> {
+        use token::TokenType::*;
+        let category = match typ {
+            Function | Let | True | False | If | Else | Retrun | Import | Export | As | Throw
+            | Try | Catch | Finally => Category::Keyword,
+            Ident => Category::Identifier,
+            Int => Category::Number,
+            String => Category::String,
+            Comment => Category::Comment,
+            Assign | Plus | Minus | Bang | Asterisk | Slash | Lt | Gt | Arrow | Eq | NotEq => {
+                Category::Operator
+            }
+            Comma | Semicolon | Lparne | Rparne | Lbrace | Rbrace | Lbracket | Rbracket | Colon
+            | Dot => Category::Punctuation,
+            Illegal | Eof => return None,
+        };
+        Some(category)
+    }
+
+    // ANSI のエスケープシーケンスの色。識別子などは色をつけない
+    fn ansi(&self) -> Option<&'static str> {
+        match self {
+            Category::Keyword => Some("35"),
+            Category::Function => Some("34"),
+            Category::Parameter => Some("36"),
+            Category::Number => Some("33"),
+            Category::String => Some("32"),
+            Category::Comment => Some("90"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Highlight {
+    pub span: token::Span,
+    pub category: Category,
+}
+
+// token の種類だけで分類する。Illegal token は含めない
+pub fn classify(source: &str) -> Vec<Highlight> {
+    lexer::Lexer::new(source.to_string())
+        .keep_comments(true)
+        .filter_map(|t| {
+            Category::of(&t.typ).map(|category| Highlight {
+                span: t.span(),
+                category,
+            })
+        })
+        .collect()
+}
+
+// 識別子を関数、引数、変数に分ける。parse できなければ classify と同じ
+pub fn classify_with_ast(source: &str) -> Vec<Highlight> {
+    let mut highlights = classify(source);
+    let program = match parser::parse(source) {
+        Ok(program) => program,
+        Err(_) => return highlights,
+    };
+    let arena = Arena::from_program(&program);
+    let resolution = resolver::resolve(&arena, &evaluator::BUILTINS);
+    // 関数リテラルを束縛した let の名前
+    let functions: HashSet<_> = arena
+        .iter()
+        .filter_map(|(_, node)| match node.kind {
+            NodeKind::LetStatement { name, value, .. } => {
+                matches!(arena[value].kind, NodeKind::FunctionLiteral { .. }).then_some(name)
+            }
+            _ => None,
+        })
+        .collect();
+
+    for h in highlights
+        .iter_mut()
+        .filter(|h| h.category == Category::Identifier)
+    {
+        let declaration = match arena
+            .node_at(h.span.start.offset)
+            .and_then(|node| resolution.definition(node))
+        {
+            Some(id) => resolution.declaration(id),
+            None => continue,
+        };
+        h.category = match declaration.scope {
+            resolver::Scope::Parameter => Category::Parameter,
+            _ if declaration.node.is_some_and(|n| functions.contains(&n)) => Category::Function,
+            _ => Category::Variable,
+        };
+    }
+    highlights
+}
+
+// highlights はソース順に並んでいること
+fn render(
+    source: &str,
+    highlights: &[Highlight],
+    mut plain: impl FnMut(&mut String, &str),
+    mut styled: impl FnMut(&mut String, Category, &str),
+) -> String {
+    let mut out = String::new();
+    let mut at = 0;
+    for h in highlights {
+        let (start, end) = (h.span.start.offset, h.span.end.offset);
+        if start < at {
+            continue;
+        }
+        plain(&mut out, &source[at..start]);
+        styled(&mut out, h.category, &source[start..end]);
+        at = end;
+    }
+    plain(&mut out, &source[at..]);
+    out
+}
+
+pub fn to_ansi(source: &str, highlights: &[Highlight]) -> String {
+    render(
+        source,
+        highlights,
+        |out, text| out.push_str(text),
+        |out, category, text| match category.ansi() {
+            Some(color) => {
+                out.push_str(&format!("\x1b[{}m{}\x1b[0m", color, text));
+            }
+            None => out.push_str(text),
+        },
+    )
+}
+
+// 色は CSS で class ごとに指定する
+pub fn to_html(source: &str, highlights: &[Highlight]) -> String {
+    let body = render(source, highlights, escape_html, |out, category, text| {
+        out.push_str(&format!("<span class=\"{}\">", category.name()));
+        escape_html(out, text);
+        out.push_str("</span>");
+    });
+    format!("<pre class=\"monkey\">{}</pre>\n", body)
+}
+
+fn escape_html(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+// エディタ用の TextMate 文法。キーワードは lexer の表から作る
+pub fn textmate_grammar() -> Value {
+    let keywords = |types: &[token::TokenType]| {
+        let words: Vec<&str> = lexer::KEYWORDS
+            .iter()
+            .filter(|(_, typ)| types.contains(typ))
+            .map(|(keyword, _)| *keyword)
+            .collect();
+        format!("\\b(?:{})\\b", words.join("|"))
+    };
+    json!({
+        "name": "Monkey",
+        "scopeName": "source.monkey",
+        "fileTypes": ["monkey"],
+        "patterns": [
+            { "include": "#comments" },
+            { "include": "#strings" },
+            { "include": "#keywords" },
+            { "include": "#numbers" },
+            { "include": "#operators" },
+            { "include": "#punctuation" },
+        ],
+        "repository": {
+            "comments": {
+                "patterns": [
+                    { "name": "comment.line.double-slash.monkey", "match": "//.*$" },
+                    { "name": "comment.block.monkey", "begin": "/\\*", "end": "\\*/" },
+                ],
+            },
+            "strings": {
+                "name": "string.quoted.double.monkey",
+                "begin": "\"",
+                "end": "\"",
+                "patterns": [
+                    { "name": "constant.character.escape.monkey", "match": "\\\\[ntr\"\\\\]" },
+                ],
+            },
+            "keywords": {
+                "patterns": [
+                    {
+                        "name": "storage.type.monkey",
+                        "match": keywords(&[token::TokenType::Function, token::TokenType::Let]),
+                    },
+                    {
+                        "name": "constant.language.boolean.monkey",
+                        "match": keywords(&[token::TokenType::True, token::TokenType::False]),
+                    },
+                    {
+                        "name": "keyword.control.monkey",
+                        "match": keywords(&[
+                            token::TokenType::If,
+                            token::TokenType::Else,
+                            token::TokenType::Retrun,
+                            token::TokenType::Throw,
+                            token::TokenType::Try,
+                            token::TokenType::Catch,
+                            token::TokenType::Finally,
+                        ]),
+                    },
+                    {
+                        "name": "keyword.control.import.monkey",
+                        "match": keywords(&[
+                            token::TokenType::Import,
+                            token::TokenType::Export,
+                            token::TokenType::As,
+                        ]),
+                    },
+                ],
+            },
+            "numbers": { "name": "constant.numeric.integer.monkey", "match": "\\b[0-9]+\\b" },
+            "operators": {
+                "name": "keyword.operator.monkey",
+                "match": "==|!=|->|[=+\\-!*/<>]",
+            },
+            "punctuation": { "name": "punctuation.monkey", "match": "[,;:.(){}\\[\\]]" },
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{classify, classify_with_ast, textmate_grammar, to_ansi, to_html, Category};
+    use crate::lexer;
+
+    fn describe(source: &str, highlights: &[super::Highlight]) -> Vec<String> {
+        highlights
+            .iter()
+            .map(|h| {
+                let text = &source[h.span.start.offset..h.span.end.offset];
+                format!("{} {}", h.category.name(), text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let source = "let f = fn(a) { a + x }; // c\nf(\"s\", 1)";
+        assert_eq!(
+            describe(source, &classify(source)),
+            vec![
+                "keyword let",
+                "identifier f",
+                "operator =",
+                "keyword fn",
+                "punctuation (",
+                "identifier a",
+                "punctuation )",
+                "punctuation {",
+                "identifier a",
+                "operator +",
+                "identifier x",
+                "punctuation }",
+                "punctuation ;",
+                "comment // c",
+                "identifier f",
+                "punctuation (",
+                "string \"s\"",
+                "punctuation ,",
+                "number 1",
+                "punctuation )",
+            ]
+        );
+
+        let refined: Vec<String> = describe(source, &classify_with_ast(source))
+            .into_iter()
+            .filter(|s| !s.starts_with("punctuation"))
+            .collect();
+        assert_eq!(
+            &refined[..9],
+            &[
+                "keyword let",
+                "function f",
+                "operator =",
+                "keyword fn",
+                "parameter a",
+                "parameter a",
+                "operator +",
+                "identifier x",
+                "comment // c",
+            ]
+        );
+        assert_eq!(refined[9], "function f");
+
+        // parse できないときは token の種類だけで分ける
+        assert_eq!(
+            describe("let x = ", &classify_with_ast("let x = ")),
+            vec!["keyword let", "identifier x", "operator ="]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "let s = \"<a>\" + x;";
+        let highlights = classify_with_ast(source);
+        assert_eq!(
+            to_html(source, &highlights),
+            "<pre class=\"monkey\"><span class=\"keyword\">let</span> \
+<span class=\"variable\">s</span> <span class=\"operator\">=</span> \
+<span class=\"string\">&quot;&lt;a&gt;&quot;</span> <span class=\"operator\">+</span> \
+<span class=\"identifier\">x</span><span class=\"punctuation\">;</span></pre>\n"
+        );
+        assert_eq!(
+            to_ansi(source, &highlights),
+            "\x1b[35mlet\x1b[0m s = \x1b[32m\"<a>\"\x1b[0m + x;"
+        );
+        // Illegal token はそのまま出力する
+        assert_eq!(to_ansi("1 @", &classify("1 @")), "\x1b[33m1\x1b[0m @");
+        assert_eq!(Category::of(&crate::token::TokenType::Eof), None);
+    }
+
+    #[test]
+    fn test_textmate_grammar() {
+        let grammar = textmate_grammar();
+        assert_eq!(grammar["scopeName"], "source.monkey");
+        let patterns = grammar["repository"]["keywords"]["patterns"]
+            .as_array()
+            .unwrap();
+        let matches: Vec<&str> = patterns
+            .iter()
+            .map(|p| p["match"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            matches,
+            vec![
+                "\\b(?:fn|let)\\b",
+                "\\b(?:true|false)\\b",
+                "\\b(?:if|else|return|throw|try|catch|finally)\\b",
+                "\\b(?:import|export|as)\\b"
+            ]
+        );
+        // すべてのキーワードがどれかの規則に含まれる
+        for (keyword, _) in lexer::KEYWORDS.iter() {
+            assert!(
+                matches.iter().any(|m| m.contains(keyword)),
+                "{} is not highlighted",
+                keyword
+            );
+        }
+    }
+}
@@ -1,115 +1,461 @@
+use std::collections::VecDeque;
+
 use bytes::{Buf, Bytes};
+use thiserror::Error;
 
 use crate::token;
-pub(crate) struct Lexer {
+
+pub mod incremental;
+
+// キーワードと token の種類の対応。ここにないものは識別子になる
+pub const KEYWORDS: [(&str, token::TokenType); 14] = [
+    ("fn", token::TokenType::Function),
+    ("let", token::TokenType::Let),
+    ("true", token::TokenType::True),
+    ("false", token::TokenType::False),
+    ("if", token::TokenType::If),
+    ("else", token::TokenType::Else),
+    ("return", token::TokenType::Retrun),
+    ("import", token::TokenType::Import),
+    ("export", token::TokenType::Export),
+    ("as", token::TokenType::As),
+    ("throw", token::TokenType::Throw),
+    ("try", token::TokenType::Try),
+    ("catch", token::TokenType::Catch),
+    ("finally", token::TokenType::Finally),
+];
+
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+pub enum LexError {
+    #[error("{pos}: unexpected character {ch:?}")]
+    UnexpectedCharacter { ch: char, pos: token::Position },
+    #[error("{pos}: unterminated string {literal:?}")]
+    UnterminatedString {
+        literal: String,
+        pos: token::Position,
+    },
+    #[error("{pos}: invalid escape sequence {escape:?}")]
+    InvalidEscape {
+        escape: String,
+        pos: token::Position,
+    },
+    #[error("{pos}: malformed number {literal:?}")]
+    MalformedNumber {
+        literal: String,
+        pos: token::Position,
+    },
+    #[error("{pos}: unterminated block comment")]
+    UnterminatedComment { pos: token::Position },
+}
+
+impl LexError {
+    pub fn pos(&self) -> token::Position {
+        match self {
+            LexError::UnexpectedCharacter { pos, .. }
+            | LexError::UnterminatedString { pos, .. }
+            | LexError::InvalidEscape { pos, .. }
+            | LexError::MalformedNumber { pos, .. }
+            | LexError::UnterminatedComment { pos } => *pos,
+        }
+    }
+
+    // ドキュメントやテストで使うエラーコード。番号は変えない
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::UnexpectedCharacter { .. } => "E0001",
+            LexError::UnterminatedString { .. } => "E0002",
+            LexError::InvalidEscape { .. } => "E0003",
+            LexError::MalformedNumber { .. } => "E0004",
+            LexError::UnterminatedComment { .. } => "E0005",
+        }
+    }
+}
+
+// token の途中で入力が切れたときに、どの構文の内側にいるか
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LexMode {
+    Normal,
+    String,
+    BlockComment,
+}
+
+// 字句解析を再開するための状態。token の境界ごとに保存しておける
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LexState {
+    pub pos: token::Position,
+    pub mode: LexMode,
+}
+
+pub struct Lexer {
     data: Bytes,
+    pos: token::Position,
+    mode: LexMode,
+    // true なら Comment token も返す（formatter などで使う）
+    keep_comments: bool,
+    // peek で先読みした token
+    lookahead: VecDeque<token::Token>,
+    // Illegal token を返したときの詳細
+    errors: Vec<LexError>,
 }
 
 impl Lexer {
-    pub(crate) fn new<'a>(input: String) -> Self {
+    pub fn new(input: String) -> Self {
         let data = Bytes::from(input);
-        Self { data }
+        Self::resume(
+            data,
+            LexState {
+                pos: token::Position::default(),
+                mode: LexMode::Normal,
+            },
+        )
+    }
+
+    // input 全体のうち state.pos.offset 以降を state.mode から字句解析する
+    pub fn resume(input: Bytes, state: LexState) -> Self {
+        Self::resume_from(input.slice(state.pos.offset..), state)
+    }
+
+    // rest は input のうち state.pos.offset 以降の部分。途中までしか渡さなければそこを終端として扱う
+    pub fn resume_from(rest: Bytes, state: LexState) -> Self {
+        Self {
+            data: rest,
+            pos: state.pos,
+            mode: state.mode,
+            keep_comments: false,
+            lookahead: VecDeque::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn keep_comments(mut self, keep: bool) -> Self {
+        self.keep_comments = keep;
+        self
+    }
+
+    // 現在の状態。peek した token も読み済みとして扱う
+    pub fn state(&self) -> LexState {
+        LexState {
+            pos: self.pos,
+            mode: self.mode,
+        }
     }
 
-    // fn read_char(&mut self) {
-    //     self.data.get_u8()
-    // }
-    pub(crate) fn next_token(&mut self) -> token::Token {
-        self.consume_white_space();
+    // これまでに発生したエラーを返す。Illegal token 1 つにつき 1 つ積まれる
+    pub fn errors(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    // n 個先の token を返す。消費はしない（peek(0) は次に next_token で返る token）
+    // 入力の終端以降は Eof を返し続ける
+    pub fn peek(&mut self, n: usize) -> &token::Token {
+        while self.lookahead.len() <= n {
+            let token = self.read_token();
+            self.lookahead.push_back(token);
+        }
+        &self.lookahead[n]
+    }
+
+    pub fn next_token(&mut self) -> token::Token {
+        match self.lookahead.pop_front() {
+            Some(token) => token,
+            None => self.read_token(),
+        }
+    }
+
+    fn read_token(&mut self) -> token::Token {
+        loop {
+            let token = self.scan_token();
+            if token.typ != token::TokenType::Comment || self.keep_comments {
+                return token;
+            }
+        }
+    }
+
+    fn scan_token(&mut self) -> token::Token {
+        if self.mode == LexMode::Normal {
+            self.consume_white_space();
+        }
+        let start = self.pos;
         // FIXME is_emptyの判定まわりをもう少し最適化したい
         if self.data.is_empty() {
-            return token::Token::new(token::TokenType::Eof, Bytes::new());
+            return self.token(token::TokenType::Eof, Bytes::new(), start);
+        }
+        match self.mode {
+            LexMode::Normal => {}
+            LexMode::String => return self.read_string(start, false),
+            LexMode::BlockComment => return self.read_block_comment(start, false),
         }
 
-        let mut b = self.data.clone();
-        // 保持するためのdata
-        let literal = b.split_to(1);
         // １文字消費しつつ、 `u8` を取得
         // これをもとに判定する
         let mut peek = self.data.clone();
-        let token = match peek.get_u8() {
+        let (token_type, len) = match peek.get_u8() {
             b'=' => {
                 if !peek.is_empty() && peek.get_u8() == b'=' {
-                    return token::Token::new(token::TokenType::Eq, self.data.split_to(2));
+                    (token::TokenType::Eq, 2)
+                } else {
+                    (token::TokenType::Assign, 1)
+                }
+            }
+            b';' => (token::TokenType::Semicolon, 1),
+            b'(' => (token::TokenType::Lparne, 1),
+            b')' => (token::TokenType::Rparne, 1),
+            b',' => (token::TokenType::Comma, 1),
+            b'+' => (token::TokenType::Plus, 1),
+            b'-' => {
+                if !peek.is_empty() && peek.get_u8() == b'>' {
+                    (token::TokenType::Arrow, 2)
+                } else {
+                    (token::TokenType::Minus, 1)
                 }
-                token::Token::new(token::TokenType::Assign, literal)
             }
-            b';' => token::Token::new(token::TokenType::Semicolon, literal),
-            b'(' => token::Token::new(token::TokenType::Lparne, literal),
-            b')' => token::Token::new(token::TokenType::Rparne, literal),
-            b',' => token::Token::new(token::TokenType::Comma, literal),
-            b'+' => token::Token::new(token::TokenType::Plus, literal),
-            b'-' => token::Token::new(token::TokenType::Minus, literal),
             b'!' => {
                 if !peek.is_empty() && peek.get_u8() == b'=' {
-                    return token::Token::new(token::TokenType::NotEq, self.data.split_to(2));
+                    (token::TokenType::NotEq, 2)
+                } else {
+                    (token::TokenType::Bang, 1)
                 }
-                token::Token::new(token::TokenType::Bang, literal)
             }
-            b'/' => token::Token::new(token::TokenType::Slash, literal),
-            b'*' => token::Token::new(token::TokenType::Asterisk, literal),
-            b'<' => token::Token::new(token::TokenType::Lt, literal),
-            b'>' => token::Token::new(token::TokenType::Gt, literal),
-            b'{' => token::Token::new(token::TokenType::Lbrace, literal),
-            b'}' => token::Token::new(token::TokenType::Rbrace, literal),
+            b'/' => match peek.first() {
+                Some(b'/') => {
+                    let len = self.count_while(|s| s != b'\n');
+                    (token::TokenType::Comment, len)
+                }
+                Some(b'*') => return self.read_block_comment(start, true),
+                _ => (token::TokenType::Slash, 1),
+            },
+            b'*' => (token::TokenType::Asterisk, 1),
+            b'<' => (token::TokenType::Lt, 1),
+            b'>' => (token::TokenType::Gt, 1),
+            b'{' => (token::TokenType::Lbrace, 1),
+            b'}' => (token::TokenType::Rbrace, 1),
+            b'[' => (token::TokenType::Lbracket, 1),
+            b']' => (token::TokenType::Rbracket, 1),
+            b':' => (token::TokenType::Colon, 1),
+            b'.' => (token::TokenType::Dot, 1),
+            b'"' => return self.read_string(start, true),
             s if Lexer::is_letter(s) => {
-                // is_letterを満たさない最初の位置を取得
-                let mut by = self.data.split(|s| !Lexer::is_letter(*s));
-                let x = by.next().unwrap().len();
-                // 取得したis_letterを満たさない位置でsplitし、literalとする
-                let literal = self.data.split_to(x);
+                // is_letterを満たさない最初の位置で split し、literalとする
+                let literal = self.advance(self.count_while(Lexer::is_letter));
 
                 // identifierかどうか
                 let token_type = Lexer::literal_to_token_type(&literal[..]);
-
-                // FIXME returnしているのを直す
-                return token::Token::new(token_type, literal);
+                return self.token(token_type, literal, start);
             }
-            s if Lexer::is_digit(s) => {
-                // is_letterを満たさない最初の位置を取得
-                let mut by = self.data.split(|s| !Lexer::is_digit(*s));
-                let x = by.next().unwrap().len();
-                // 取得したis_letterを満たさない位置でsplitし、literalとする
-                let literal = self.data.split_to(x);
-                // FIXME returnしているのを直す
-                return token::Token::new(token::TokenType::Int, literal);
+            s if Lexer::is_digit(s) => return self.read_number(start),
+            _ => {
+                let literal = self.advance(self.char_len());
+                let ch = String::from_utf8_lossy(&literal[..])
+                    .chars()
+                    .next()
+                    .unwrap_or(char::REPLACEMENT_CHARACTER);
+                self.errors
+                    .push(LexError::UnexpectedCharacter { ch, pos: start });
+                return self.token(token::TokenType::Illegal, literal, start);
             }
-            _ => token::Token::new(token::TokenType::Illegal, Bytes::new()),
         };
-        // １文字分すすめる
-        self.data.get_u8();
-        token
+        let literal = self.advance(len);
+        self.token(token_type, literal, start)
+    }
+
+    fn read_number(&mut self, start: token::Position) -> token::Token {
+        let digits = self.count_while(Lexer::is_digit);
+        // 数字の直後に文字が続くもの（`12ab` など）はまとめてエラーにする
+        let rest = self.data[digits..]
+            .iter()
+            .take_while(|s| Lexer::is_letter(**s) || Lexer::is_digit(**s))
+            .count();
+        let literal = self.advance(digits + rest);
+        if rest == 0 {
+            return self.token(token::TokenType::Int, literal, start);
+        }
+        self.errors.push(LexError::MalformedNumber {
+            literal: String::from_utf8_lossy(&literal[..]).into_owned(),
+            pos: start,
+        });
+        self.token(token::TokenType::Illegal, literal, start)
+    }
+
+    // `"` から対応する `"` までを読む。literal には `"` を含めない
+    // エスケープがなければ入力の slice をそのまま使う
+    // quoted が false のときは文字列の途中から再開している
+    fn read_string(&mut self, start: token::Position, quoted: bool) -> token::Token {
+        let head = if quoted { 1 } else { 0 };
+        let mut unescaped: Option<Vec<u8>> = None;
+        let mut invalid_escape = None;
+        let mut i = head;
+        loop {
+            match self.data.get(i) {
+                None => {
+                    // 入力が途中で切れている場合に備えて、文字列の内側にいることを残す
+                    self.mode = LexMode::String;
+                    let literal = self.advance(self.data.len());
+                    self.errors.push(LexError::UnterminatedString {
+                        literal: String::from_utf8_lossy(&literal[..]).into_owned(),
+                        pos: start,
+                    });
+                    return self.token(token::TokenType::Illegal, literal, start);
+                }
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let buf = unescaped.get_or_insert_with(|| self.data[head..i].to_vec());
+                    match self.data.get(i + 1) {
+                        Some(b'n') => buf.push(b'\n'),
+                        Some(b't') => buf.push(b'\t'),
+                        Some(b'r') => buf.push(b'\r'),
+                        Some(b'"') => buf.push(b'"'),
+                        Some(b'\\') => buf.push(b'\\'),
+                        // 終端は次のループで UnterminatedString になる
+                        None => {
+                            i += 1;
+                            continue;
+                        }
+                        Some(_) => {
+                            if invalid_escape.is_none() {
+                                invalid_escape = Some(i);
+                            }
+                        }
+                    }
+                    i += 2;
+                }
+                Some(b) => {
+                    if let Some(buf) = unescaped.as_mut() {
+                        buf.push(*b);
+                    }
+                    i += 1;
+                }
+            }
+        }
+
+        self.mode = LexMode::Normal;
+        if let Some(at) = invalid_escape {
+            let escape_len = 1 + utf8_char_len(self.data[at + 1]);
+            let escape = String::from_utf8_lossy(&self.data[at..at + escape_len]).into_owned();
+            let literal = self.data.slice(0..i + 1);
+            // エスケープの位置を得るため、手前までを先に消費する
+            self.advance(at);
+            let pos = self.pos;
+            self.advance(i + 1 - at);
+            self.errors.push(LexError::InvalidEscape { escape, pos });
+            return self.token(token::TokenType::Illegal, literal, start);
+        }
+
+        let raw = self.advance(i + 1);
+        let literal = match unescaped {
+            Some(buf) => Bytes::from(buf),
+            None => raw.slice(head..i),
+        };
+        self.token(token::TokenType::String, literal, start)
+    }
+
+    // `/*` から `*/` までを読む。入れ子にはしない
+    fn read_block_comment(&mut self, start: token::Position, opened: bool) -> token::Token {
+        let head = if opened { 2 } else { 0 };
+        match self.data[head..].windows(2).position(|w| w == b"*/") {
+            Some(at) => {
+                self.mode = LexMode::Normal;
+                let literal = self.advance(head + at + 2);
+                self.token(token::TokenType::Comment, literal, start)
+            }
+            None => {
+                self.mode = LexMode::BlockComment;
+                let literal = self.advance(self.data.len());
+                self.errors
+                    .push(LexError::UnterminatedComment { pos: start });
+                self.token(token::TokenType::Illegal, literal, start)
+            }
+        }
+    }
+
+    // start から現在位置までの token を作る
+    fn token(&self, typ: token::TokenType, literal: Bytes, start: token::Position) -> token::Token {
+        token::Token::with_span(typ, literal, token::Span::new(start, self.pos))
+    }
+
+    // 先頭から pred を満たすバイト数
+    fn count_while(&self, pred: fn(u8) -> bool) -> usize {
+        self.data.iter().take_while(|s| pred(**s)).count()
+    }
+
+    // 先頭の UTF-8 文字のバイト数
+    fn char_len(&self) -> usize {
+        utf8_char_len(self.data[0]).min(self.data.len())
     }
+
+    // n バイト消費して返す。位置もあわせて進める
+    fn advance(&mut self, n: usize) -> Bytes {
+        let consumed = self.data.split_to(n);
+        for b in consumed.iter() {
+            if *b == b'\n' {
+                self.pos.line += 1;
+                self.pos.column = 1;
+            } else if *b & 0xc0 != 0x80 {
+                // UTF-8 の継続バイトは column に数えない
+                self.pos.column += 1;
+            }
+        }
+        self.pos.offset += n;
+        consumed
+    }
+
     const fn is_letter(s: u8) -> bool {
         matches!(s, b'a'..=b'z' | b'A'..=b'Z' | b'_')
     }
     const fn is_digit(s: u8) -> bool {
-        matches!(s, b'0'..=b'9')
+        s.is_ascii_digit()
     }
     const fn is_whitespace(s: u8) -> bool {
         matches!(s, b' ' | b'\t' | b'\n' | b'\r')
     }
-    const fn literal_to_token_type(literal: &[u8]) -> token::TokenType {
-        match literal {
-            b"fn" => token::TokenType::Function,
-            b"let" => token::TokenType::Let,
-            b"true" => token::TokenType::True,
-            b"false" => token::TokenType::False,
-            b"if" => token::TokenType::If,
-            b"else" => token::TokenType::Else,
-            b"return" => token::TokenType::Retrun,
-            _ => token::TokenType::Ident,
-        }
+    fn literal_to_token_type(literal: &[u8]) -> token::TokenType {
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| keyword.as_bytes() == literal)
+            .map_or(token::TokenType::Ident, |(_, typ)| typ.clone())
     }
     fn consume_white_space(&mut self) {
-        // 消費するスペース等を満たさない最初の位置を取得
-        let mut by = self.data.split(|s| !Lexer::is_whitespace(*s));
-        let x = by.next().unwrap().len();
-        // 消費
-        let _ = self.data.split_to(x);
+        // 消費するスペース等を満たさない最初の位置まで消費
+        let _ = self.advance(self.count_while(Lexer::is_whitespace));
+    }
+}
+
+// UTF-8 の先頭バイトから文字のバイト数を求める
+const fn utf8_char_len(b: u8) -> usize {
+    match b {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xff => 4,
+        _ => 1,
+    }
+}
+
+// Eof に到達したら None を返す
+impl Iterator for Lexer {
+    type Item = token::Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token();
+        if token.typ == token::TokenType::Eof {
+            None
+        } else {
+            Some(token)
+        }
     }
 }
 
+// input をすべて token に分割する。Illegal token があった時点でエラーにする
+pub fn tokenize(input: &str) -> Result<Vec<token::Token>, LexError> {
+    let mut l = Lexer::new(input.to_string());
+    let mut tokens = Vec::new();
+    while let Some(t) = l.next() {
+        if t.typ == token::TokenType::Illegal {
+            return Err(l.errors().remove(0));
+        }
+        tokens.push(t);
+    }
+    Ok(tokens)
+}
+
 #[cfg(test)]
 mod test {
     use bytes::Bytes;
@@ -126,7 +472,7 @@ mod test {
             x + y;
         };
         let result = add(five, ten);
-        !-/*5;
+        !-/ *5;
         5 < 10 > 5;
         if (5 < 10) {
             return true;
@@ -242,6 +588,220 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_iterator() {
+        let input = "let x = 5;".to_string();
+        let types: Vec<TokenType> = Lexer::new(input).map(|t| t.typ).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Let,
+                TokenType::Ident,
+                TokenType::Assign,
+                TokenType::Int,
+                TokenType::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut l = Lexer::new("a == b".to_string());
+        assert_eq!(l.peek(1).typ, TokenType::Eq);
+        assert_eq!(l.peek(0).typ, TokenType::Ident);
+        assert_eq!(l.peek(5).typ, TokenType::Eof);
+        assert_eq!(&l.next_token().literal[..], b"a");
+        assert_eq!(l.next_token().typ, TokenType::Eq);
+        assert_eq!(&l.next_token().literal[..], b"b");
+        assert_eq!(l.next_token().typ, TokenType::Eof);
+    }
+
+    #[test]
+    fn test_position() {
+        let input = "let x = 5;\n  x + 10;".to_string();
+        let positions: Vec<(usize, usize, usize)> = Lexer::new(input)
+            .map(|t| (t.pos.offset, t.pos.line, t.pos.column))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (0, 1, 1),
+                (4, 1, 5),
+                (6, 1, 7),
+                (8, 1, 9),
+                (9, 1, 10),
+                (13, 2, 3),
+                (15, 2, 5),
+                (17, 2, 7),
+                (19, 2, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens = super::tokenize("add(1, 2)").unwrap();
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(&tokens[0].literal[..], b"add");
+
+        let types: Vec<TokenType> = super::tokenize("-> - >")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.typ)
+            .collect();
+        assert_eq!(
+            types,
+            vec![TokenType::Arrow, TokenType::Minus, TokenType::Gt,]
+        );
+
+        let err = super::tokenize("let x = 1;\nlet y = 2 @ 3;").unwrap_err();
+        assert_eq!(
+            err,
+            super::LexError::UnexpectedCharacter {
+                ch: '@',
+                pos: crate::token::Position {
+                    offset: 21,
+                    line: 2,
+                    column: 11,
+                },
+            }
+        );
+        assert_eq!(err.to_string(), "2:11: unexpected character '@'");
+    }
+
+    #[test]
+    fn test_string() {
+        let tokens = super::tokenize(r#""foobar" "foo bar" "a\"b\n" """#).unwrap();
+        let literals: Vec<&[u8]> = tokens.iter().map(|t| &t.literal[..]).collect();
+        assert!(tokens.iter().all(|t| t.typ == TokenType::String));
+        assert_eq!(
+            literals,
+            vec![&b"foobar"[..], &b"foo bar"[..], &b"a\"b\n"[..], &b""[..]]
+        );
+    }
+
+    #[test]
+    fn test_lex_errors() {
+        use super::LexError;
+        use crate::token::Position;
+
+        for (input, expected) in [
+            (
+                "let s = \"abc",
+                LexError::UnterminatedString {
+                    literal: "\"abc".to_string(),
+                    pos: Position {
+                        offset: 8,
+                        line: 1,
+                        column: 9,
+                    },
+                },
+            ),
+            (
+                "\"a\\qb\" + 1",
+                LexError::InvalidEscape {
+                    escape: "\\q".to_string(),
+                    pos: Position {
+                        offset: 2,
+                        line: 1,
+                        column: 3,
+                    },
+                },
+            ),
+            (
+                "x +\n 123abc",
+                LexError::MalformedNumber {
+                    literal: "123abc".to_string(),
+                    pos: Position {
+                        offset: 5,
+                        line: 2,
+                        column: 2,
+                    },
+                },
+            ),
+            (
+                "a = é;",
+                LexError::UnexpectedCharacter {
+                    ch: 'é',
+                    pos: Position {
+                        offset: 4,
+                        line: 1,
+                        column: 5,
+                    },
+                },
+            ),
+        ] {
+            assert_eq!(super::tokenize(input).unwrap_err(), expected, "{}", input);
+        }
+
+        // エラー後も字句解析は続けられる
+        let mut l = Lexer::new("\"a\\qb\" 1".to_string());
+        assert_eq!(l.next_token().typ, TokenType::Illegal);
+        let t = l.next_token();
+        assert_eq!((t.typ, &t.literal[..]), (TokenType::Int, &b"1"[..]));
+        assert_eq!(l.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_comment() {
+        let input = "a // line\n/* block\n */ b /* x */".to_string();
+        let types: Vec<TokenType> = Lexer::new(input.clone()).map(|t| t.typ).collect();
+        assert_eq!(types, vec![TokenType::Ident, TokenType::Ident]);
+
+        let literals: Vec<Bytes> = Lexer::new(input)
+            .keep_comments(true)
+            .map(|t| t.literal)
+            .collect();
+        assert_eq!(
+            literals,
+            vec!["a", "// line", "/* block\n */", "b", "/* x */"]
+        );
+    }
+
+    #[test]
+    fn test_resume() {
+        use super::{LexMode, LexState};
+        use crate::token::Position;
+
+        // 文字列の途中で切れた入力は、続きから再開できる
+        let mut l = Lexer::new("x = \"ab".to_string());
+        l.by_ref().for_each(drop);
+        assert_eq!(l.state().mode, LexMode::String);
+
+        let input = Bytes::from("x = \"ab\ncd\" /* c\nd */ y");
+        let mut l = Lexer::resume(
+            input.clone(),
+            LexState {
+                pos: Position {
+                    offset: 8,
+                    line: 2,
+                    column: 1,
+                },
+                mode: LexMode::String,
+            },
+        );
+        let t = l.next_token();
+        assert_eq!((t.typ, &t.literal[..]), (TokenType::String, &b"cd"[..]));
+        assert_eq!(l.state().mode, LexMode::Normal);
+
+        let mut l = Lexer::resume(
+            input,
+            LexState {
+                pos: Position {
+                    offset: 17,
+                    line: 3,
+                    column: 1,
+                },
+                mode: LexMode::BlockComment,
+            },
+        )
+        .keep_comments(true);
+        let t = l.next_token();
+        assert_eq!((t.typ, &t.literal[..]), (TokenType::Comment, &b"d */"[..]));
+        let t = l.next_token();
+        assert_eq!((t.typ, t.pos.line, t.pos.column), (TokenType::Ident, 3, 6));
+    }
+
     #[test]
     fn explain() {
         let input = r"ab sb ab".to_string();
@@ -0,0 +1,433 @@
+use std::ops::Range;
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use super::{LexState, Lexer};
+use crate::token;
+
+// 元のテキストの range（バイト offset）を text で置き換える編集
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+// 適用できない編集
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+pub enum EditError {
+    #[error("edit range {start}..{end} is out of bounds for length {len}")]
+    OutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("edit offset {offset} is not on a character boundary")]
+    NotCharBoundary { offset: usize },
+}
+
+// 編集によって変化した token の範囲
+// 編集前の tokens[removed] が、編集後の tokens[inserted] に置き換わった
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TokenChange {
+    pub removed: Range<usize>,
+    pub inserted: Range<usize>,
+}
+
+// 編集された箇所だけを再度字句解析する lexer
+// comment も token として保持する（Eof は含めない）
+pub struct IncrementalLexer {
+    source: String,
+    tokens: Vec<token::Token>,
+    // states[i] は tokens[i - 1] を読み終えた直後の状態。states[0] は先頭
+    // そのため states.len() == tokens.len() + 1
+    states: Vec<LexState>,
+}
+
+// 編集のたびに source 全体を写さないよう、lexer にはまず編集の後ろをこのバイト数だけ渡す
+const WINDOW: usize = 256;
+
+// scan で読んだ token と、編集前の token と同期した位置
+struct Scan {
+    tokens: Vec<token::Token>,
+    states: Vec<LexState>,
+    sync: Option<(usize, LexState)>,
+}
+
+impl IncrementalLexer {
+    pub fn new(source: String) -> Self {
+        let mut l = Self {
+            source: String::new(),
+            tokens: Vec::new(),
+            states: vec![LexState {
+                pos: token::Position::default(),
+                mode: super::LexMode::Normal,
+            }],
+        };
+        // 空の source の先頭への挿入なので失敗しない
+        l.edit(TextEdit {
+            range: 0..0,
+            text: source,
+        })
+        .unwrap();
+        l
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tokens(&self) -> &[token::Token] {
+        &self.tokens
+    }
+
+    // range が source の外や文字の途中を指していたら何も変えずにエラーを返す
+    pub fn edit(&mut self, edit: TextEdit) -> Result<TokenChange, EditError> {
+        let TextEdit { range, text } = edit;
+        let len = self.source.len();
+        if range.start > range.end || range.end > len {
+            return Err(EditError::OutOfBounds {
+                start: range.start,
+                end: range.end,
+                len,
+            });
+        }
+        if let Some(&offset) = [range.start, range.end]
+            .iter()
+            .find(|&&offset| !self.source.is_char_boundary(offset))
+        {
+            return Err(EditError::NotCharBoundary { offset });
+        }
+        let edit_end = range.start + text.len();
+        let delta = text.len() as isize - range.len() as isize;
+        self.source.replace_range(range.clone(), &text);
+
+        // 編集位置に接している token から再開する（`=` の直後に `=` を足した場合など）
+        let first = self.states[1..]
+            .iter()
+            .position(|s| s.pos.offset >= range.start)
+            .unwrap_or(self.tokens.len());
+
+        // 編集の後ろは少しだけ lexer に渡し、同じ状態に戻る前に足りなくなったら広げる
+        let begin = self.states[first].pos.offset;
+        let mut window = (edit_end + WINDOW).min(self.source.len());
+        let Scan {
+            tokens,
+            mut states,
+            sync,
+        } = loop {
+            match self.scan(first, window, edit_end, delta) {
+                Some(scan) => break scan,
+                None => window = (window + (window - begin)).min(self.source.len()),
+            }
+        };
+
+        let removed_end = match sync {
+            Some((old, state)) => {
+                let anchor = self.states[old].pos;
+                for t in &mut self.tokens[old..] {
+                    t.pos = shift(t.pos, anchor, state.pos);
+                    t.end = shift(t.end, anchor, state.pos);
+                }
+                for s in &mut self.states[old..] {
+                    s.pos = shift(s.pos, anchor, state.pos);
+                }
+                // 最後の状態は states[old] と一致するので入れ替えない
+                states.pop();
+                old
+            }
+            None => {
+                self.states.truncate(self.tokens.len());
+                self.tokens.len()
+            }
+        };
+
+        // 先頭側で変化していない token は範囲から除く
+        let same = tokens
+            .iter()
+            .zip(&self.tokens[first..removed_end])
+            .take_while(|(a, b)| a == b && a.span() == b.span())
+            .count();
+        let inserted = tokens.len();
+        self.tokens.splice(first..removed_end, tokens);
+        self.states.splice(first..removed_end, states);
+        Ok(TokenChange {
+            removed: first + same..removed_end,
+            inserted: first + same..first + inserted,
+        })
+    }
+
+    // states[first] から source[..window] までを字句解析する
+    // 窓の端に接する token は窓の外まで続くかもしれないので、そこまで来たら None を返す
+    fn scan(&self, first: usize, window: usize, edit_end: usize, delta: isize) -> Option<Scan> {
+        let start = self.states[first];
+        let rest = Bytes::copy_from_slice(&self.source.as_bytes()[start.pos.offset..window]);
+        let complete = window == self.source.len();
+        let mut l = Lexer::resume_from(rest, start).keep_comments(true);
+        let mut tokens = Vec::new();
+        let mut states = vec![start];
+        let mut old = first;
+        loop {
+            let state = l.state();
+            // 編集範囲より後ろで、編集前と同じ状態に戻ったら以降の token は変わらない
+            if state.pos.offset >= edit_end {
+                while old < self.tokens.len()
+                    && (self.states[old].pos.offset as isize + delta) < state.pos.offset as isize
+                {
+                    old += 1;
+                }
+                if old < self.states.len()
+                    && (self.states[old].pos.offset as isize + delta) == state.pos.offset as isize
+                    && self.states[old].mode == state.mode
+                    && old > first
+                {
+                    return Some(Scan {
+                        tokens,
+                        states,
+                        sync: Some((old, state)),
+                    });
+                }
+            }
+            let t = l.next_token();
+            if !complete && t.end.offset >= window {
+                return None;
+            }
+            if t.typ == token::TokenType::Eof {
+                return Some(Scan {
+                    tokens,
+                    states,
+                    sync: None,
+                });
+            }
+            tokens.push(t);
+            states.push(l.state());
+        }
+    }
+}
+
+// 同じ行の token は column も、以降の行は line だけずらす
+fn shift(pos: token::Position, old: token::Position, new: token::Position) -> token::Position {
+    token::Position {
+        offset: (pos.offset as isize + new.offset as isize - old.offset as isize) as usize,
+        line: (pos.line as isize + new.line as isize - old.line as isize) as usize,
+        column: if pos.line == old.line {
+            (pos.column as isize + new.column as isize - old.column as isize) as usize
+        } else {
+            pos.column
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Range;
+
+    use super::{EditError, IncrementalLexer, TextEdit, TokenChange};
+    use crate::lexer::Lexer;
+    use crate::token::TokenType;
+
+    // 全体を字句解析しなおした結果と一致するか
+    fn assert_same_as_full(l: &IncrementalLexer) {
+        let expected: Vec<_> = Lexer::new(l.source().to_string())
+            .keep_comments(true)
+            .collect();
+        assert_eq!(l.tokens().len(), expected.len(), "{:?}", l.source());
+        for (got, want) in l.tokens().iter().zip(&expected) {
+            assert_eq!(
+                (&got.typ, &got.literal, got.span()),
+                (&want.typ, &want.literal, want.span()),
+                "{:?}",
+                l.source()
+            );
+        }
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut l = IncrementalLexer::new("let x = 5;\nlet y = x + 10;\n".to_string());
+        assert_eq!(l.tokens().len(), 12);
+
+        // `x` を `xyz` にする
+        let change = l
+            .edit(TextEdit {
+                range: 4..5,
+                text: "xyz".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            change,
+            TokenChange {
+                removed: 1..2,
+                inserted: 1..2
+            }
+        );
+        assert_same_as_full(&l);
+
+        // `=` の直後に `=` を足すと `==` になる
+        let change = l
+            .edit(TextEdit {
+                range: 9..9,
+                text: "=".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            change,
+            TokenChange {
+                removed: 2..3,
+                inserted: 2..3
+            }
+        );
+        assert_eq!(l.tokens()[2].typ, TokenType::Eq);
+        assert_same_as_full(&l);
+    }
+
+    #[test]
+    fn test_edit_comment_and_string() {
+        let mut l = IncrementalLexer::new("a; b; c;".to_string());
+        // block comment を開くと、閉じていないので以降すべてが変わる
+        let change = l
+            .edit(TextEdit {
+                range: 2..2,
+                text: "/*".to_string(),
+            })
+            .unwrap();
+        assert_eq!(change.removed, 2..6);
+        assert_eq!(l.tokens()[2].typ, TokenType::Illegal);
+        assert_same_as_full(&l);
+
+        // 閉じると元の token に戻る
+        l.edit(TextEdit {
+            range: 7..7,
+            text: "*/".to_string(),
+        })
+        .unwrap();
+        assert_eq!(l.tokens()[2].typ, TokenType::Comment);
+        assert_same_as_full(&l);
+
+        let mut l = IncrementalLexer::new("x + \"ab\" + y".to_string());
+        let change = l
+            .edit(TextEdit {
+                range: 6..6,
+                text: "\n c".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            change,
+            TokenChange {
+                removed: 2..3,
+                inserted: 2..3
+            }
+        );
+        assert_same_as_full(&l);
+    }
+
+    #[test]
+    fn test_random_edits() {
+        let texts = [
+            "", " ", "\n", "a", "1", "=", "!", "/", "*", "\"", "/*", "*/", "//", "fn", "let", "{",
+            "}", ";", "x1",
+        ];
+        let source = "let add = fn(a, b) { a + b; };\n/* c */ add(1, \"s\"); // end\n";
+        // 乱数は再現できるように線形合同法で作る
+        let mut seed: u64 = 42;
+        let mut next = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        // 長い source では lexer に渡す範囲を広げながら読む
+        for source in [source.to_string(), source.repeat(20)] {
+            let mut l = IncrementalLexer::new(source);
+            for _ in 0..500 {
+                let len = l.source().len();
+                let start = next(len + 1);
+                let end = (start + next(4)).min(len);
+                let text = texts[next(texts.len())].to_string();
+                l.edit(TextEdit {
+                    range: start..end,
+                    text,
+                })
+                .unwrap();
+                assert_same_as_full(&l);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_edit() {
+        let mut l = IncrementalLexer::new("let s = \"あ\";".to_string());
+        let tests = vec![
+            (
+                14..15,
+                EditError::OutOfBounds {
+                    start: 14,
+                    end: 15,
+                    len: 14,
+                },
+            ),
+            (
+                Range { start: 3, end: 2 },
+                EditError::OutOfBounds {
+                    start: 3,
+                    end: 2,
+                    len: 14,
+                },
+            ),
+            (10..11, EditError::NotCharBoundary { offset: 10 }),
+            (8..11, EditError::NotCharBoundary { offset: 11 }),
+        ];
+        for (range, expected) in tests {
+            let e = l
+                .edit(TextEdit {
+                    range: range.clone(),
+                    text: "x".to_string(),
+                })
+                .unwrap_err();
+            assert_eq!(e, expected, "{:?}", range);
+        }
+        // 失敗した編集は何も変えない
+        assert_eq!(l.source(), "let s = \"あ\";");
+        assert_same_as_full(&l);
+    }
+
+    #[test]
+    fn test_edit_beyond_window() {
+        // 編集の影響が lexer に最初に渡す範囲より先まで続く場合
+        let body = "a; ".repeat(400);
+        let mut l = IncrementalLexer::new(format!("{}b;", body));
+        let change = l
+            .edit(TextEdit {
+                range: 0..0,
+                text: "/*".to_string(),
+            })
+            .unwrap();
+        assert_eq!(change.removed, 0..802);
+        assert_same_as_full(&l);
+        l.edit(TextEdit {
+            range: 0..2,
+            text: String::new(),
+        })
+        .unwrap();
+        assert_same_as_full(&l);
+
+        // 窓の端をまたぐ長い識別子
+        let name = "x".repeat(1000);
+        let mut l = IncrementalLexer::new(format!("{} + 1", name));
+        let change = l
+            .edit(TextEdit {
+                range: 0..0,
+                text: "y".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            change,
+            TokenChange {
+                removed: 0..1,
+                inserted: 0..1
+            }
+        );
+        assert_eq!(l.tokens()[0].literal.len(), 1001);
+        assert_same_as_full(&l);
+    }
+}
@@ -1,8 +1,19 @@
-mod ast;
-mod lexer;
-mod parser;
+pub mod ast;
+pub mod cli;
+pub mod completion;
+pub mod evaluator;
+pub mod formatter;
+pub mod highlight;
+pub mod lexer;
+pub mod lint;
+pub mod lsp;
+pub mod object;
+pub mod optimizer;
+pub mod parser;
 pub mod repl;
-mod token;
+pub mod resolver;
+pub mod token;
+pub mod types;
 
 #[cfg(test)]
 mod tests {
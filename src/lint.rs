@@ -0,0 +1,556 @@
+// parse 済みの AST を調べて、バグの可能性がある書き方を報告する
+// スコープは関数ごと（ブロックはスコープを作らない）で、評価器と同じにしている
+// 後から宣言する名前への参照も使用に数えるよう、使われたかどうかは名前解決の結果も見る
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    ast::{
+        self,
+        arena::Arena,
+        expression, statement,
+        visit::{self, Visitor},
+        Node,
+    },
+    evaluator, resolver, token,
+};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Rule {
+    UnusedLet,
+    Shadowing,
+    UnreachableCode,
+    SelfComparison,
+    ConstantCondition,
+    WrongArity,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedLet,
+        Rule::Shadowing,
+        Rule::UnreachableCode,
+        Rule::SelfComparison,
+        Rule::ConstantCondition,
+        Rule::WrongArity,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedLet => "unused-let",
+            Rule::Shadowing => "shadowing",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::SelfComparison => "self-comparison",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::WrongArity => "wrong-arity",
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown lint rule {0:?}")]
+pub struct UnknownRule(String);
+
+impl FromStr for Rule {
+    type Err = UnknownRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .iter()
+            .copied()
+            .find(|rule| rule.name() == s)
+            .ok_or_else(|| UnknownRule(s.to_string()))
+    }
+}
+
+// 有効にするルールの集合。Default はすべて有効
+#[derive(Debug, Clone)]
+pub struct Config {
+    enabled: HashSet<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: Rule::ALL.iter().copied().collect(),
+        }
+    }
+}
+
+impl Config {
+    pub fn none() -> Self {
+        Self {
+            enabled: HashSet::new(),
+        }
+    }
+
+    pub fn enable(mut self, rule: Rule) -> Self {
+        self.enabled.insert(rule);
+        self
+    }
+
+    pub fn disable(mut self, rule: Rule) -> Self {
+        self.enabled.remove(&rule);
+        self
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.enabled.contains(&rule)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub message: String,
+    pub span: token::Span,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.span.start, self.rule, self.message)
+    }
+}
+
+// 結果はソース上の位置順に並べる
+pub fn lint(program: &ast::Program, config: &Config) -> Vec<Diagnostic> {
+    let resolution = resolver::resolve(&Arena::from_program(program), &evaluator::BUILTINS);
+    let used = resolution
+        .used()
+        .into_iter()
+        .filter_map(|id| resolution.declaration(id).span)
+        .collect();
+    let mut linter = Linter {
+        config,
+        used,
+        constant_conditions: HashSet::new(),
+        scopes: vec![Vec::new()],
+        diagnostics: Vec::new(),
+    };
+    linter.visit_program(program);
+    linter.pop_scope();
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|d| (d.span.start.offset, d.span.end.offset));
+    diagnostics
+}
+
+pub fn to_json(diagnostics: &[Diagnostic]) -> Value {
+    Value::Array(
+        diagnostics
+            .iter()
+            .map(|d| {
+                json!({
+                    "rule": d.rule.name(),
+                    "message": d.message,
+                    "span": ast::json::span(d.span),
+                })
+            })
+            .collect(),
+    )
+}
+
+struct Binding<'ast> {
+    name: &'ast str,
+    span: token::Span,
+    parameter: bool,
+    used: bool,
+    // 関数リテラルを束縛したときの引数の数
+    arity: Option<usize>,
+}
+
+struct Linter<'a, 'ast> {
+    config: &'a Config,
+    // 名前解決で参照が見つかった宣言の位置
+    used: HashSet<token::Span>,
+    // constant-condition を報告した条件。同じ式を self-comparison でも報告しない
+    constant_conditions: HashSet<token::Span>,
+    scopes: Vec<Vec<Binding<'ast>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a, 'ast> Linter<'a, 'ast> {
+    fn report(&mut self, rule: Rule, span: token::Span, message: String) {
+        if self.config.is_enabled(rule) {
+            self.diagnostics.push(Diagnostic {
+                rule,
+                message,
+                span,
+            });
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Binding<'ast>> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|b| b.name == name)
+    }
+
+    fn declare(
+        &mut self,
+        identifier: &'ast expression::Identifier,
+        parameter: bool,
+        arity: Option<usize>,
+    ) {
+        let name = identifier.name();
+        if self.lookup(name).is_some() {
+            self.report(
+                Rule::Shadowing,
+                identifier.span(),
+                format!("`{}` shadows an earlier binding", name),
+            );
+        }
+        self.scopes.last_mut().unwrap().push(Binding {
+            name,
+            span: identifier.span(),
+            parameter,
+            used: false,
+            arity,
+        });
+    }
+
+    // `_` で始まる名前は使わなくてもよい
+    fn pop_scope(&mut self) {
+        for b in self.scopes.pop().unwrap() {
+            let used = b.used || self.used.contains(&b.span);
+            if !used && !b.parameter && !b.name.starts_with('_') {
+                self.report(
+                    Rule::UnusedLet,
+                    b.span,
+                    format!("`{}` is never used", b.name),
+                );
+            }
+        }
+    }
+
+    // return と throw の後ろの文はまとめて 1 つとして報告する。名前の使用は数えるので走査は続ける
+    fn statements(&mut self, statements: &'ast [statement::Statement]) {
+        let returned = statements.iter().position(|s| {
+            matches!(
+                s,
+                statement::Statement::ReturnStatement(_) | statement::Statement::ThrowStatement(_)
+            )
+        });
+        if let Some(i) = returned.filter(|i| i + 1 < statements.len()) {
+            let span = statements[i + 1]
+                .span()
+                .to(statements.last().unwrap().span());
+            self.report(
+                Rule::UnreachableCode,
+                span,
+                format!("unreachable code after {}", statements[i].token_literal()),
+            );
+        }
+        for s in statements {
+            self.visit_statement(s);
+        }
+    }
+}
+
+impl<'a, 'ast> Visitor<'ast> for Linter<'a, 'ast> {
+    fn visit_program(&mut self, program: &'ast ast::Program) {
+        self.statements(&program.statements);
+    }
+
+    fn visit_block_statement(&mut self, block: &'ast statement::BlockStatement) {
+        self.statements(&block.statements);
+    }
+
+    // 関数は再帰呼び出しできるよう先に名前を束縛する
+    fn visit_let_statement(&mut self, statement: &'ast statement::LetStatement) {
+        if let expression::Expression::FunctionLiteral(function) = &statement.value {
+            self.declare(&statement.name, false, Some(function.parameters.len()));
+            self.visit_function_literal(function);
+        } else {
+            self.visit_expression(&statement.value);
+            self.declare(&statement.name, false, None);
+        }
+    }
+
+    fn visit_import_statement(&mut self, statement: &'ast statement::ImportStatement) {
+        self.declare(&statement.alias, false, None);
+    }
+
+    // export した名前はほかのファイルから使われるので使用済みにする
+    fn visit_export_statement(&mut self, statement: &'ast statement::ExportStatement) {
+        self.visit_let_statement(&statement.statement);
+        self.scopes.last_mut().unwrap().last_mut().unwrap().used = true;
+    }
+
+    fn visit_identifier(&mut self, identifier: &'ast expression::Identifier) {
+        if let Some(b) = self.lookup(identifier.name()) {
+            b.used = true;
+        }
+    }
+
+    fn visit_function_literal(&mut self, function: &'ast expression::FunctionLiteral) {
+        self.scopes.push(Vec::new());
+        for parameter in &function.parameters {
+            self.declare(&parameter.name, true, None);
+        }
+        self.visit_block_statement(&function.body);
+        self.pop_scope();
+    }
+
+    // catch の引数は本体だけのスコープとして扱い、続けて書いた catch (e) を shadowing にしない
+    // 使わなくても引数と同じく報告しない
+    fn visit_catch_clause(&mut self, catch: &'ast expression::CatchClause) {
+        self.scopes.push(Vec::new());
+        self.declare(&catch.parameter, true, None);
+        self.visit_block_statement(&catch.body);
+        self.pop_scope();
+    }
+
+    fn visit_infix_expression(&mut self, expression: &'ast expression::InfixExpression) {
+        if matches!(expression.operator.as_str(), "==" | "!=" | "<" | ">")
+            && is_pure(&expression.left)
+            && expression.left == expression.right
+            && !self.constant_conditions.contains(&expression.span())
+        {
+            self.report(
+                Rule::SelfComparison,
+                expression.span(),
+                format!("comparing `{}` with itself", expression.left),
+            );
+        }
+        visit::walk_infix_expression(self, expression);
+    }
+
+    fn visit_if_expression(&mut self, expression: &'ast expression::IfExpression) {
+        if is_constant(&expression.condition) {
+            if self.config.is_enabled(Rule::ConstantCondition) {
+                self.constant_conditions.insert(expression.condition.span());
+            }
+            self.report(
+                Rule::ConstantCondition,
+                expression.condition.span(),
+                format!("condition `{}` is constant", expression.condition),
+            );
+        }
+        visit::walk_if_expression(self, expression);
+    }
+
+    fn visit_call_expression(&mut self, expression: &'ast expression::CallExpression) {
+        let arity = match &*expression.function {
+            expression::Expression::Identifier(identifier) => self
+                .lookup(identifier.name())
+                .and_then(|b| b.arity.map(|arity| (b.name, arity))),
+            expression::Expression::FunctionLiteral(function) => {
+                Some(("function literal", function.parameters.len()))
+            }
+            _ => None,
+        };
+        if let Some((name, arity)) = arity {
+            if arity != expression.arguments.len() {
+                self.report(
+                    Rule::WrongArity,
+                    expression.span(),
+                    format!(
+                        "`{}` takes {} argument(s) but {} were given",
+                        name,
+                        arity,
+                        expression.arguments.len()
+                    ),
+                );
+            }
+        }
+        visit::walk_call_expression(self, expression);
+    }
+}
+
+// 呼び出しを含まず、評価するたびに同じ値になる式
+fn is_pure(e: &expression::Expression) -> bool {
+    match e {
+        expression::Expression::Identifier(_)
+        | expression::Expression::IntegerLiteral(_)
+        | expression::Expression::StringLiteral(_)
+        | expression::Expression::Boolean(_) => true,
+        expression::Expression::PrefixExpression(e) => is_pure(&e.right),
+        expression::Expression::InfixExpression(e) => is_pure(&e.left) && is_pure(&e.right),
+        expression::Expression::IndexExpression(e) => is_pure(&e.left) && is_pure(&e.index),
+        _ => false,
+    }
+}
+
+fn is_constant(e: &expression::Expression) -> bool {
+    match e {
+        expression::Expression::IntegerLiteral(_)
+        | expression::Expression::StringLiteral(_)
+        | expression::Expression::Boolean(_) => true,
+        expression::Expression::PrefixExpression(e) => is_constant(&e.right),
+        expression::Expression::InfixExpression(e) => is_constant(&e.left) && is_constant(&e.right),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lint, to_json, Config, Rule};
+    use crate::parser;
+
+    fn check(input: &str, config: &Config) -> Vec<String> {
+        let program = parser::parse(input).unwrap();
+        lint(&program, config)
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        let tests = [
+            ("let x = 1; let _y = 2; x;", vec![]),
+            (
+                "let x = 1; let f = fn(x) { let y = x; 0 }; f(1);",
+                vec![
+                    "1:5: unused-let: `x` is never used",
+                    "1:23: shadowing: `x` shadows an earlier binding",
+                    "1:32: unused-let: `y` is never used",
+                ],
+            ),
+            (
+                "let x = 1; let x = x + 1; x;",
+                vec!["1:16: shadowing: `x` shadows an earlier binding"],
+            ),
+            (
+                "let f = fn() { return 1; 2; 3 }; f();",
+                vec!["1:26: unreachable-code: unreachable code after return"],
+            ),
+            (
+                "let x = 1; x == x; x[0] != x[0]; f(x) == f(x); x < 1;",
+                vec![
+                    "1:12: self-comparison: comparing `x` with itself",
+                    "1:20: self-comparison: comparing `(x[0])` with itself",
+                ],
+            ),
+            (
+                "let x = 1; if (true) { x } else { 1 }; if (1 < 2) { 0 }; if (!x) { 0 };",
+                vec![
+                    "1:16: constant-condition: condition `true` is constant",
+                    "1:44: constant-condition: condition `(1 < 2)` is constant",
+                ],
+            ),
+            (
+                "let add = fn(a, b) { a + b }; add(1); add(1, 2); fn(x) { x }(); let g = add; g(1);",
+                vec![
+                    "1:31: wrong-arity: `add` takes 2 argument(s) but 1 were given",
+                    "1:50: wrong-arity: `function literal` takes 1 argument(s) but 0 were given",
+                ],
+            ),
+            // export した名前は使われていなくてもよい
+            (
+                "import \"m.mk\" as m; import \"n.mk\" as n; export let x = m.f; let y = 1;",
+                vec![
+                    "1:38: unused-let: `n` is never used",
+                    "1:65: unused-let: `y` is never used",
+                ],
+            ),
+            // 続けて書いた catch の引数は shadowing にしない
+            (
+                "let f = fn() { throw 1; 2 }; try { f() } catch (e) { 0 }; try { f() } catch (e) { e };",
+                vec!["1:25: unreachable-code: unreachable code after throw"],
+            ),
+            // 再帰呼び出しは使っていることになる
+            (
+                "let loop = fn(n) { if (n == 0) { 0 } else { loop(n - 1) } }; loop(3);",
+                vec![],
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(check(input, &Config::default()), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_forward_references() {
+        // 関数の中から後で宣言する名前を使っても、使っていることになる
+        for input in [
+            "let f = fn() { g() }; let g = fn() { 1 }; f();",
+            "let outer = fn() { let f = fn() { h }; let h = 1; f() }; outer();",
+        ] {
+            assert_eq!(
+                check(input, &Config::default()),
+                Vec::<String>::new(),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            check(
+                "let f = fn() { g() }; let g = fn() { 1 };",
+                &Config::default()
+            ),
+            vec!["1:5: unused-let: `f` is never used"]
+        );
+    }
+
+    #[test]
+    fn test_constant_self_comparison() {
+        // 定数同士の比較は constant-condition だけを報告する
+        let input = "let x = 1; if (1 == 1) { x }; if (x == x) { x };";
+        assert_eq!(
+            check(input, &Config::default()),
+            vec![
+                "1:16: constant-condition: condition `(1 == 1)` is constant",
+                "1:35: self-comparison: comparing `x` with itself",
+            ]
+        );
+        // constant-condition を無効にしていれば self-comparison を報告する
+        assert_eq!(
+            check(input, &Config::default().disable(Rule::ConstantCondition)),
+            vec![
+                "1:16: self-comparison: comparing `1` with itself",
+                "1:35: self-comparison: comparing `x` with itself",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let input = "let x = 1; let x = 2; if (true) { 0 };";
+        assert_eq!(check(input, &Config::default()).len(), 4);
+        assert_eq!(
+            check(input, &Config::default().disable(Rule::UnusedLet)),
+            vec![
+                "1:16: shadowing: `x` shadows an earlier binding",
+                "1:27: constant-condition: condition `true` is constant",
+            ]
+        );
+        assert_eq!(
+            check(input, &Config::none().enable(Rule::ConstantCondition)),
+            vec!["1:27: constant-condition: condition `true` is constant"]
+        );
+        assert_eq!("wrong-arity".parse::<Rule>().unwrap(), Rule::WrongArity);
+        assert!("nope".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let program = parser::parse("let x = 1;").unwrap();
+        let json = to_json(&lint(&program, &Config::default()));
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "rule": "unused-let",
+                "message": "`x` is never used",
+                "span": {
+                    "start": {"offset": 4, "line": 1, "column": 5},
+                    "end": {"offset": 5, "line": 1, "column": 6},
+                },
+            }])
+        );
+    }
+}
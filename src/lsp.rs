@@ -0,0 +1,831 @@
+// Language Server Protocol を標準入出力で話す
+// 文書は変更のたびに全文を受け取り（TextDocumentSyncKind.Full）、そのつど parse し直す
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    ast::{
+        self,
+        arena::Arena,
+        expression, statement,
+        visit::{self, Visitor},
+        Node,
+    },
+    completion, evaluator, formatter, highlight, lexer, parser, resolver, token, types,
+};
+
+pub mod transport;
+
+// semantic tokens の種類。添字が token の種類の番号になる
+const TOKEN_TYPES: [&str; 8] = [
+    "keyword",
+    "variable",
+    "function",
+    "parameter",
+    "number",
+    "string",
+    "operator",
+    "comment",
+];
+
+// JSON-RPC のエラーコード
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// 入力が終わるか exit を受け取るまで処理し、終了コードを返す
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<i32> {
+    let mut server = Server::default();
+    while let Some(message) = transport::read_message(&mut input)? {
+        let replies = match message {
+            Ok(message) => server.handle(&message),
+            // どのリクエストかわからないので id は null にして、次のメッセージを待つ
+            Err(e) => vec![json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": PARSE_ERROR, "message": e.to_string() },
+            })],
+        };
+        for reply in replies {
+            transport::write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            return Ok(if server.shutdown { 0 } else { 1 });
+        }
+    }
+    Ok(1)
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, String>,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    // 返信と通知を返す
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // クライアントからの応答は使わない
+            None => return Vec::new(),
+        };
+        let params = &message["params"];
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.notification(method, params),
+        };
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.with_position(params, hover),
+            "textDocument/definition" => self.with_position(params, definition),
+            "textDocument/completion" => self.with_position(params, complete),
+            "textDocument/documentSymbol" => self
+                .document(params)
+                .map(|(_, text)| document_symbols(text)),
+            "textDocument/semanticTokens/full" => self
+                .document(params)
+                .map(|(_, text)| json!({ "data": semantic_tokens(text) })),
+            "textDocument/formatting" => self.document(params).map(|(_, text)| {
+                let indent = params["options"]["tabSize"].as_u64().unwrap_or(4) as usize;
+                formatting(text, indent)
+            }),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        };
+        let reply = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        vec![reply]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "exit" => {
+                self.exited = true;
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                vec![publish_diagnostics(uri, text)]
+            }
+            // 全文同期なので最後の変更が文書全体になる
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                match text {
+                    Some(text) => {
+                        self.documents.insert(uri.to_string(), text.to_string());
+                        vec![publish_diagnostics(uri, text)]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![notify(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn document<'a>(&'a self, params: &'a Value) -> Result<(&'a str, &'a str), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        self.documents
+            .get(uri)
+            .map(|text| (uri, text.as_str()))
+            .ok_or_else(|| (INVALID_PARAMS, format!("unknown document {}", uri)))
+    }
+
+    fn with_position(
+        &self,
+        params: &Value,
+        f: fn(&str, &str, usize) -> Value,
+    ) -> Result<Value, (i64, String)> {
+        let (uri, text) = self.document(params)?;
+        let position = &params["position"];
+        let offset = to_offset(
+            text,
+            position["line"].as_u64().unwrap_or(0) as usize,
+            position["character"].as_u64().unwrap_or(0) as usize,
+        );
+        Ok(f(uri, text, offset))
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": 1,
+            "hoverProvider": true,
+            "definitionProvider": true,
+            "documentSymbolProvider": true,
+            "documentFormattingProvider": true,
+            "completionProvider": { "triggerCharacters": ["\""] },
+            "semanticTokensProvider": {
+                "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                "full": true,
+            },
+        },
+        "serverInfo": { "name": "interpreter-rs" },
+    })
+}
+
+fn notify(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+// parse できなければ構文エラーを、できれば未定義の識別子と型の誤りを報告する
+// メッセージ先頭の位置は range と重複するので取り除く
+fn publish_diagnostics(uri: &str, text: &str) -> Value {
+    let errors: Vec<(token::Span, String, &str)> = match parser::parse_with_spans(text) {
+        Err(errors) => errors
+            .into_iter()
+            .map(|(e, span)| (span, e.to_string(), e.code()))
+            .collect(),
+        Ok(program) => {
+            let arena = Arena::from_program(&program);
+            let resolution = resolver::resolve(&arena, &evaluator::BUILTINS);
+            let typing = types::check(&arena);
+            let mut errors: Vec<(token::Span, String, &str)> = resolution
+                .errors()
+                .iter()
+                .map(|e| (e.span(), e.to_string(), e.code()))
+                .chain(
+                    typing
+                        .errors()
+                        .iter()
+                        .map(|e| (e.span(), e.to_string(), e.code())),
+                )
+                .collect();
+            errors.sort_by_key(|(span, _, _)| span.start.offset);
+            errors
+        }
+    };
+    let diagnostics: Vec<Value> = errors
+        .into_iter()
+        .map(|(span, message, code)| {
+            let prefix = format!("{}: ", span.start);
+            let message = message.strip_prefix(&prefix).unwrap_or(&message);
+            json!({
+                "range": range(text, span),
+                "severity": 1,
+                "source": "interpreter-rs",
+                "code": code,
+                "message": message,
+            })
+        })
+        .collect();
+    notify(
+        "textDocument/publishDiagnostics",
+        json!({ "uri": uri, "diagnostics": diagnostics }),
+    )
+}
+
+// offset を含む token。token の直後にカーソルがあるときもその token とみなす
+fn token_at(text: &str, offset: usize) -> Option<token::Token> {
+    let mut before = None;
+    for t in lexer::Lexer::new(text.to_string()).keep_comments(true) {
+        if t.pos.offset <= offset && offset < t.end.offset {
+            return Some(t);
+        }
+        if t.end.offset == offset {
+            before = Some(t);
+        }
+    }
+    before
+}
+
+fn hover(_uri: &str, text: &str, offset: usize) -> Value {
+    let token = match token_at(text, offset) {
+        Some(token) => token,
+        None => return Value::Null,
+    };
+    let literal = String::from_utf8_lossy(&token.literal[..]).into_owned();
+    let mut contents = format!("`{}` {}", literal, token.typ);
+    if token.typ == token::TokenType::Ident {
+        if let Ok(program) = parser::parse(text) {
+            let arena = Arena::from_program(&program);
+            let resolution = resolver::resolve(&arena, &evaluator::BUILTINS);
+            let typing = types::check(&arena);
+            let definition = arena
+                .node_at(token.pos.offset)
+                .and_then(|node| resolution.definition(node));
+            if let Some(id) = definition {
+                let declaration = resolution.declaration(id);
+                let typ = declaration
+                    .node
+                    .and_then(|node| typing.type_of(node))
+                    .map_or_else(|| "any".to_string(), |t| t.to_string());
+                contents = format!(
+                    "```monkey\n{}: {}\n```\n{:?}",
+                    literal, typ, declaration.scope
+                );
+            }
+        }
+    }
+    json!({
+        "contents": { "kind": "markdown", "value": contents },
+        "range": range(text, token.span()),
+    })
+}
+
+// let で束縛した名前と関数の引数へ飛ぶ。組み込み関数は位置がないので null
+fn definition(uri: &str, text: &str, offset: usize) -> Value {
+    let declaration = token_at(text, offset)
+        .filter(|t| t.typ == token::TokenType::Ident)
+        .and_then(|token| {
+            let program = parser::parse(text).ok()?;
+            let arena = Arena::from_program(&program);
+            let resolution = resolver::resolve(&arena, &evaluator::BUILTINS);
+            let id = resolution.definition(arena.node_at(token.pos.offset)?)?;
+            resolution.declaration(id).span
+        });
+    match declaration {
+        Some(span) => json!({ "uri": uri, "range": range(text, span) }),
+        None => Value::Null,
+    }
+}
+
+// 入力中の行は parse できないことが多いので、そのときはその行より前だけを使う
+fn complete(_uri: &str, text: &str, offset: usize) -> Value {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let source = parser::parse(text)
+        .or_else(|_| parser::parse(&text[..line_start]))
+        .map(|program| Document::new(&program))
+        .unwrap_or_default();
+    let completions = completion::complete(text, offset, &source);
+    let edit_range = offset_range(text, completions.start, offset);
+    let items: Vec<Value> = completions
+        .candidates
+        .into_iter()
+        .map(|c| {
+            // CompletionItemKind
+            let kind = match c.kind {
+                completion::Kind::Function | completion::Kind::Builtin => 3,
+                completion::Kind::Module => 9,
+                completion::Kind::Variable => 6,
+                completion::Kind::Key => 10,
+                completion::Kind::Keyword => 14,
+                completion::Kind::Command => 1,
+            };
+            json!({
+                "label": c.label,
+                "kind": kind,
+                "textEdit": { "range": edit_range, "newText": c.label },
+            })
+        })
+        .collect();
+    Value::Array(items)
+}
+
+// 文書で宣言している名前と、let で束縛したハッシュリテラルの文字列のキー
+#[derive(Default)]
+struct Document {
+    names: Vec<(String, completion::Kind)>,
+    keys: HashMap<String, Vec<String>>,
+    // 関数リテラルを束縛した名前
+    functions: Vec<String>,
+}
+
+impl Document {
+    fn new(program: &ast::Program) -> Self {
+        let mut document = Document::default();
+        document.visit_program(program);
+        document.names = resolver::resolve(&Arena::from_program(program), &evaluator::BUILTINS)
+            .declarations()
+            .map(|(_, d)| {
+                let kind = match d.scope {
+                    resolver::Scope::Builtin => completion::builtin_kind(&d.name),
+                    _ if document.functions.contains(&d.name) => completion::Kind::Function,
+                    _ => completion::Kind::Variable,
+                };
+                (d.name.clone(), kind)
+            })
+            .collect();
+        document
+    }
+}
+
+impl<'ast> Visitor<'ast> for Document {
+    fn visit_let_statement(&mut self, statement: &'ast statement::LetStatement) {
+        let name = statement.name.name().to_string();
+        match &statement.value {
+            expression::Expression::FunctionLiteral(_) => {
+                self.functions.push(name);
+            }
+            expression::Expression::HashLiteral(hash) => {
+                let keys = hash
+                    .pairs
+                    .iter()
+                    .filter_map(|(key, _)| match key {
+                        expression::Expression::StringLiteral(s) => Some(s.value.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                self.keys.insert(name, keys);
+            }
+            _ => {}
+        }
+        visit::walk_let_statement(self, statement);
+    }
+}
+
+impl completion::Source for Document {
+    fn names(&self) -> Vec<(String, completion::Kind)> {
+        self.names.clone()
+    }
+
+    fn keys(&self, name: &str) -> Vec<String> {
+        self.keys.get(name).cloned().unwrap_or_default()
+    }
+}
+
+// let ごとにシンボルを作り、その中の let を子にする
+fn document_symbols(text: &str) -> Value {
+    let program = match parser::parse(text) {
+        Ok(program) => program,
+        Err(_) => return Value::Null,
+    };
+    let arena = Arena::from_program(&program);
+    let mut symbols = Symbols {
+        text,
+        typing: types::check(&arena),
+        arena: &arena,
+        frames: vec![Vec::new()],
+    };
+    symbols.visit_program(&program);
+    Value::Array(symbols.frames.pop().unwrap())
+}
+
+struct Symbols<'a> {
+    text: &'a str,
+    arena: &'a Arena,
+    typing: types::Typing,
+    frames: Vec<Vec<Value>>,
+}
+
+impl<'ast> Visitor<'ast> for Symbols<'_> {
+    fn visit_let_statement(&mut self, statement: &'ast statement::LetStatement) {
+        self.frames.push(Vec::new());
+        visit::walk_let_statement(self, statement);
+        let children = self.frames.pop().unwrap();
+
+        // SymbolKind.Function = 12, SymbolKind.Variable = 13
+        let kind = match statement.value {
+            expression::Expression::FunctionLiteral(_) => 12,
+            _ => 13,
+        };
+        let name = &statement.name;
+        let mut symbol = json!({
+            "name": name.name(),
+            "kind": kind,
+            "range": range(self.text, statement.span()),
+            "selectionRange": range(self.text, name.span()),
+            "children": children,
+        });
+        let node = self.arena.node_at(name.span().start.offset);
+        if let Some(typ) = node.and_then(|node| self.typing.type_of(node)) {
+            symbol["detail"] = json!(typ.to_string());
+        }
+        self.frames.last_mut().unwrap().push(symbol);
+    }
+}
+
+// 区切り記号には対応する種類がないので送らない
+fn token_type(category: highlight::Category) -> Option<usize> {
+    let index = match category {
+        highlight::Category::Keyword => 0,
+        highlight::Category::Identifier | highlight::Category::Variable => 1,
+        highlight::Category::Function => 2,
+        highlight::Category::Parameter => 3,
+        highlight::Category::Number => 4,
+        highlight::Category::String => 5,
+        highlight::Category::Operator => 6,
+        highlight::Category::Comment => 7,
+        highlight::Category::Punctuation => return None,
+    };
+    Some(index)
+}
+
+// 前の token からの相対位置で [行, 列, 長さ, 種類, 修飾] を並べる
+// 複数行にまたがる token は最初の行の分だけ送る
+fn semantic_tokens(text: &str) -> Vec<u64> {
+    let mut data = Vec::new();
+    let (mut last_line, mut last_character) = (0, 0);
+    for h in highlight::classify_with_ast(text) {
+        let typ = match token_type(h.category) {
+            Some(typ) => typ,
+            None => continue,
+        };
+        let (start, end) = (h.span.start.offset, h.span.end.offset);
+        let (line, character) = to_position(text, start);
+        let end = text[start..end].find('\n').map_or(end, |i| start + i);
+        let length = utf16_len(&text[start..end]);
+        let delta = if line == last_line {
+            character - last_character
+        } else {
+            character
+        };
+        data.extend(vec![
+            (line - last_line) as u64,
+            delta as u64,
+            length as u64,
+            typ as u64,
+            0,
+        ]);
+        last_line = line;
+        last_character = character;
+    }
+    data
+}
+
+// 文書全体を置き換える編集をひとつ返す。変わらなければ空、parse できなければ null
+fn formatting(text: &str, indent: usize) -> Value {
+    let options = formatter::Options {
+        indent,
+        ..formatter::Options::default()
+    };
+    match formatter::format_with(text, &options) {
+        Ok(formatted) if formatted == text => json!([]),
+        Ok(formatted) => {
+            let end = to_position(text, text.len());
+            json!([{
+                "range": {
+                    "start": { "line": 0, "character": 0 },
+                    "end": { "line": end.0, "character": end.1 },
+                },
+                "newText": formatted,
+            }])
+        }
+        Err(_) => Value::Null,
+    }
+}
+
+// LSP の位置は 0 始まりの行と、行頭からの UTF-16 の長さで表す
+fn to_position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count(),
+        utf16_len(&before[line_start..]),
+    )
+}
+
+fn to_offset(text: &str, line: usize, character: usize) -> usize {
+    let line_start = match line {
+        0 => 0,
+        _ => match text.match_indices('\n').nth(line - 1) {
+            Some((i, _)) => i + 1,
+            None => return text.len(),
+        },
+    };
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+fn range(text: &str, span: token::Span) -> Value {
+    offset_range(text, span.start.offset, span.end.offset)
+}
+
+fn offset_range(text: &str, start: usize, end: usize) -> Value {
+    let start = to_position(text, start);
+    let end = to_position(text, end);
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::{serve, transport};
+
+    const URI: &str = "file:///a.monkey";
+
+    // メッセージを順に送り、サーバーが書いたメッセージと終了コードを返す
+    fn drive(messages: &[Value]) -> (Vec<Value>, i32) {
+        let mut input = Vec::new();
+        for message in messages {
+            transport::write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        let code = serve(&input[..], &mut output).unwrap();
+        let mut r = &output[..];
+        let mut replies = Vec::new();
+        while let Some(reply) = transport::read_message(&mut r).unwrap() {
+            replies.push(reply.unwrap());
+        }
+        (replies, code)
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn open(text: &str) -> Value {
+        notification(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "monkey", "version": 1, "text": text } }),
+        )
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } })
+    }
+
+    #[test]
+    fn test_lifecycle_and_diagnostics() {
+        let (replies, code) = drive(&[
+            request(1, "initialize", json!({ "capabilities": {} })),
+            notification("initialized", json!({})),
+            open("let x = ;"),
+            notification(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": URI, "version": 2 },
+                    "contentChanges": [{ "text": "let x: int = true;\ny" }],
+                }),
+            ),
+            request(2, "textDocument/unknown", json!({})),
+            request(3, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(replies.len(), 5);
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(
+            replies[0]["result"]["capabilities"]["semanticTokensProvider"]["legend"]["tokenTypes"]
+                [0],
+            "keyword"
+        );
+
+        assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = &replies[1]["params"]["diagnostics"];
+        assert_eq!(
+            diagnostics[0]["message"],
+            "no prefix parse function for ; found"
+        );
+        assert_eq!(diagnostics[0]["code"], "E0102");
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({ "start": { "line": 0, "character": 8 }, "end": { "line": 0, "character": 9 } })
+        );
+
+        let diagnostics = &replies[2]["params"]["diagnostics"];
+        let messages: Vec<&str> = diagnostics
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d["message"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "type mismatch: expected int, found bool",
+                "undefined identifier \"y\""
+            ]
+        );
+        assert_eq!(diagnostics[0]["code"], "E0301");
+        assert_eq!(diagnostics[1]["code"], "E0204");
+        assert_eq!(
+            diagnostics[1]["range"]["start"],
+            json!({ "line": 1, "character": 0 })
+        );
+
+        assert_eq!(replies[3]["error"]["code"], -32601);
+        assert_eq!(
+            replies[4],
+            json!({ "jsonrpc": "2.0", "id": 3, "result": null })
+        );
+
+        // shutdown せずに exit したら 1
+        let (_, code) = drive(&[notification("exit", Value::Null)]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    fn test_malformed_message() {
+        // 読めない本文には Parse error を返し、次のリクエストにも答える
+        let mut input = b"Content-Length: 8\r\n\r\n{\"id\": 1".to_vec();
+        transport::write_message(&mut input, &request(2, "shutdown", Value::Null)).unwrap();
+        transport::write_message(&mut input, &notification("exit", Value::Null)).unwrap();
+        let mut output = Vec::new();
+        assert_eq!(serve(&input[..], &mut output).unwrap(), 0);
+        let mut r = &output[..];
+        let reply = transport::read_message(&mut r).unwrap().unwrap().unwrap();
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["error"]["code"], -32700);
+        assert_eq!(
+            transport::read_message(&mut r).unwrap().unwrap().unwrap(),
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+        );
+
+        // ヘッダーが壊れていたら続けられないので終わる
+        let input = b"Content-Type: x\r\n\r\n{}";
+        assert!(serve(&input[..], &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_language_features() {
+        let text = "let add = fn(a: int, b) {\n  let s = a + b;\n  s\n};\nadd(1, \"あ\");";
+        let (replies, _) = drive(&[
+            open(text),
+            request(1, "textDocument/hover", at(2, 2)),
+            request(2, "textDocument/definition", at(4, 1)),
+            request(3, "textDocument/definition", at(1, 10)),
+            request(
+                4,
+                "textDocument/documentSymbol",
+                json!({ "textDocument": { "uri": URI } }),
+            ),
+            request(
+                5,
+                "textDocument/semanticTokens/full",
+                json!({ "textDocument": { "uri": URI } }),
+            ),
+            request(6, "textDocument/hover", at(0, 4)),
+            request(7, "textDocument/hover", at(0, 3)),
+        ]);
+
+        assert_eq!(
+            replies[1]["result"]["contents"]["value"],
+            "```monkey\ns: int\n```\nLocal"
+        );
+        assert_eq!(
+            replies[2]["result"]["range"],
+            json!({ "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 7 } })
+        );
+        assert_eq!(
+            replies[3]["result"]["range"]["start"],
+            json!({ "line": 0, "character": 13 })
+        );
+
+        let symbols = &replies[4]["result"];
+        assert_eq!(symbols[0]["name"], "add");
+        assert_eq!(symbols[0]["kind"], 12);
+        assert_eq!(symbols[0]["detail"], "fn(int, any) -> int");
+        assert_eq!(symbols[0]["children"][0]["name"], "s");
+        assert_eq!(symbols[0]["children"][0]["kind"], 13);
+
+        // 先頭の `let add =` と最終行の `"あ"`。add は関数
+        let data: Vec<u64> = replies[5]["result"]["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_u64().unwrap())
+            .collect();
+        assert_eq!(&data[..15], &[0, 0, 3, 0, 0, 0, 4, 3, 2, 0, 0, 4, 1, 6, 0]);
+        assert_eq!(&data[data.len() - 5..], &[0, 3, 3, 5, 0]);
+        // `fn(a` の a は引数
+        assert_eq!(&data[20..25], &[0, 3, 1, 3, 0]);
+
+        assert_eq!(
+            replies[6]["result"]["contents"]["value"],
+            "```monkey\nadd: fn(int, any) -> int\n```\nGlobal"
+        );
+        assert_eq!(replies[7]["result"]["contents"]["value"], "`let` Let");
+    }
+
+    #[test]
+    fn test_completion() {
+        let text = "let config = {\"name\": 1, \"nest\": 2};\nlet cat = fn(x) { x };\nconfig[\"n";
+        let (replies, _) = drive(&[open(text), request(1, "textDocument/completion", at(2, 9))]);
+        let labels = |reply: &Value| -> Vec<(String, u64)> {
+            reply["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| {
+                    (
+                        item["label"].as_str().unwrap().to_string(),
+                        item["kind"].as_u64().unwrap(),
+                    )
+                })
+                .collect()
+        };
+        // 最終行は parse できないので、それより前の let からキーを引く
+        assert_eq!(
+            labels(&replies[1]),
+            vec![("name".to_string(), 10), ("nest".to_string(), 10)]
+        );
+        assert_eq!(
+            replies[1]["result"][0]["textEdit"]["range"],
+            json!({ "start": { "line": 2, "character": 8 }, "end": { "line": 2, "character": 9 } })
+        );
+
+        let (replies, _) = drive(&[
+            open("let cat = 1;\nlet cow = fn(x) { x };\nc"),
+            request(1, "textDocument/completion", at(2, 1)),
+            request(2, "textDocument/completion", at(1, 19)),
+        ]);
+        assert_eq!(
+            labels(&replies[1]),
+            vec![
+                ("cat".to_string(), 6),
+                ("catch".to_string(), 14),
+                ("cow".to_string(), 3)
+            ]
+        );
+        // `fn(x) { x` の x は引数
+        assert_eq!(labels(&replies[2]), vec![("x".to_string(), 6)]);
+    }
+
+    #[test]
+    fn test_formatting() {
+        let params = json!({ "textDocument": { "uri": URI }, "options": { "tabSize": 2, "insertSpaces": true } });
+        let (replies, _) = drive(&[
+            open("let f=fn(x){x}\n"),
+            request(1, "textDocument/formatting", params.clone()),
+        ]);
+        assert_eq!(
+            replies[1]["result"],
+            json!([{
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 0 } },
+                "newText": "let f = fn(x) {\n  x;\n};\n",
+            }])
+        );
+
+        let (replies, _) = drive(&[
+            open("let = 1"),
+            request(1, "textDocument/formatting", params),
+        ]);
+        assert_eq!(replies[1]["result"], Value::Null);
+    }
+}
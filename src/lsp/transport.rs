@@ -0,0 +1,79 @@
+// JSON-RPC のメッセージを `Content-Length` ヘッダーつきで読み書きする
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+// 入力が終わっていたら None
+// 本文が JSON として読めなくても、長さの分は読み終えているので次のメッセージから続けられる。そのときは内側の Err を返す
+pub fn read_message<R: BufRead>(r: &mut R) -> io::Result<Option<serde_json::Result<Value>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    r.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)))
+}
+
+pub fn write_message<W: Write>(w: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{read_message, write_message};
+
+    #[test]
+    fn test_round_trip() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &json!({"id": 1, "method": "ああ"})).unwrap();
+        write_message(&mut buf, &json!(null)).unwrap();
+        assert!(buf.starts_with(b"Content-Length: 26\r\n\r\n{"));
+
+        let mut r = &buf[..];
+        assert_eq!(
+            read_message(&mut r).unwrap().unwrap().unwrap(),
+            json!({"id": 1, "method": "ああ"})
+        );
+        assert_eq!(read_message(&mut r).unwrap().unwrap().unwrap(), json!(null));
+        assert!(read_message(&mut r).unwrap().is_none());
+
+        let mut r = &b"Content-Type: x\r\n\r\n{}"[..];
+        assert!(read_message(&mut r).is_err());
+    }
+
+    #[test]
+    fn test_malformed_body() {
+        // 読めない本文の後のメッセージも読める
+        let mut buf = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        write_message(&mut buf, &json!({"id": 2})).unwrap();
+        let mut r = &buf[..];
+        assert!(read_message(&mut r).unwrap().unwrap().is_err());
+        assert_eq!(
+            read_message(&mut r).unwrap().unwrap().unwrap(),
+            json!({"id": 2})
+        );
+        assert!(read_message(&mut r).unwrap().is_none());
+    }
+}
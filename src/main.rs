@@ -1,4 +1,11 @@
 fn main() {
-    use interpreter_rs::repl;
-    repl::start(std::io::stdin(), std::io::stdout())
+    use interpreter_rs::cli;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = cli::run(
+        &args,
+        std::io::stdin(),
+        std::io::stdout(),
+        std::io::stderr(),
+    );
+    std::process::exit(code)
 }
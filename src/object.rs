@@ -0,0 +1,221 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{expression, statement},
+    evaluator::{builtins::Arguments, RuntimeError},
+    token,
+};
+
+pub mod environment;
+
+pub use environment::{Env, Environment};
+
+// 評価結果の値。配列やハッシュは clone しても中身を共有する
+#[derive(Debug, Clone)]
+pub enum Object {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+    Null,
+    Array(Rc<Vec<Object>>),
+    Hash(Rc<BTreeMap<HashKey, Object>>),
+    Function(Rc<Function>),
+    Builtin(&'static Builtin),
+    Module(Rc<Module>),
+    Error(Rc<ErrorValue>),
+}
+
+impl Object {
+    // エラーメッセージで使う型の名前
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Function(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Module(_) => "MODULE",
+            Object::Error(_) => "ERROR",
+        }
+    }
+
+    // false と null 以外はすべて真
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Boolean(false) | Object::Null)
+    }
+
+    // ハッシュのキーにできるのは整数、真偽値、文字列だけ
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(v) => Some(HashKey::Integer(*v)),
+            Object::Boolean(v) => Some(HashKey::Boolean(*v)),
+            Object::String(v) => Some(HashKey::String(v.clone())),
+            _ => None,
+        }
+    }
+}
+
+// 関数とモジュール、エラーは同じ実体かどうか、それ以外は値で比べる
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Integer(a), Object::Integer(b)) => a == b,
+            (Object::Boolean(a), Object::Boolean(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Null, Object::Null) => true,
+            (Object::Array(a), Object::Array(b)) => a == b,
+            (Object::Hash(a), Object::Hash(b)) => a == b,
+            (Object::Function(a), Object::Function(b)) => Rc::ptr_eq(a, b),
+            (Object::Builtin(a), Object::Builtin(b)) => std::ptr::eq(*a, *b),
+            (Object::Module(a), Object::Module(b)) => Rc::ptr_eq(a, b),
+            (Object::Error(a), Object::Error(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+// 文字列はそのまま出力し、配列やハッシュの要素の文字列だけ引用符で囲む
+impl Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Integer(v) => write!(f, "{}", v),
+            Object::Boolean(v) => write!(f, "{}", v),
+            Object::String(v) => f.write_str(v),
+            Object::Null => f.write_str("null"),
+            Object::Array(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write_nested(f, element)?;
+                }
+                f.write_str("]")
+            }
+            Object::Hash(pairs) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write_nested(f, &key.to_object())?;
+                    f.write_str(": ")?;
+                    write_nested(f, value)?;
+                }
+                f.write_str("}")
+            }
+            Object::Function(function) => function.fmt(f),
+            Object::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name),
+            Object::Module(module) => write!(f, "<module {}>", module.name),
+            Object::Error(error) => error.fmt(f),
+        }
+    }
+}
+
+fn write_nested(f: &mut fmt::Formatter<'_>, object: &Object) -> fmt::Result {
+    match object {
+        Object::String(v) => write!(f, "{:?}", v),
+        _ => object.fmt(f),
+    }
+}
+
+// ハッシュのキー。BTreeMap で持つので出力の順番は決まっている
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+}
+
+impl HashKey {
+    pub fn to_object(&self) -> Object {
+        match self {
+            HashKey::Integer(v) => Object::Integer(*v),
+            HashKey::Boolean(v) => Object::Boolean(*v),
+            HashKey::String(v) => Object::String(v.clone()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Function {
+    // `let f = fn...` で束縛した名前。トレースに出す
+    pub name: Option<String>,
+    pub parameters: Vec<expression::Parameter>,
+    pub body: statement::BlockStatement,
+    // 関数を作ったときの環境（クロージャ）
+    pub env: Env,
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("fn(")?;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            parameter.fmt(f)?;
+        }
+        write!(f, ") {}", self.body)
+    }
+}
+
+// 組み込み関数。引数の数と型は関数の中で調べる
+#[derive(Debug)]
+pub struct Builtin {
+    // math.abs のようにモジュール名をつけた名前
+    pub name: &'static str,
+    pub function: fn(Arguments) -> Result<Object, RuntimeError>,
+}
+
+// import したファイルか組み込みのモジュール。export した名前だけを持つ
+#[derive(Debug)]
+pub struct Module {
+    // import に書いたパスを、import したファイルのディレクトリから解決したもの。組み込みならモジュール名
+    pub name: String,
+    pub exports: BTreeMap<String, Object>,
+}
+
+// error() で作ったか、catch で受け取った実行時エラーの値
+#[derive(Debug)]
+pub struct ErrorValue {
+    // error() の既定は "Error"。実行時エラーなら "TypeError" などの種類
+    pub kind: String,
+    pub message: String,
+    // エラーが起きたときに呼び出し中だった関数。内側の呼び出しが最後
+    pub trace: Vec<Frame>,
+}
+
+impl Display for ErrorValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+// 呼び出し中の関数 1 つ分。span は呼び出した式の位置
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    // 組み込み関数なら名前、それ以外は <anonymous>
+    pub function: String,
+    // 関数を let で束縛していればその名前
+    pub binding: Option<String>,
+    pub span: token::Span,
+}
+
+// `<anonymous> (let f) at 2:5` の形
+impl Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.function)?;
+        if let Some(binding) = &self.binding {
+            write!(f, " (let {})", binding)?;
+        }
+        write!(f, " at {}", self.span.start)
+    }
+}
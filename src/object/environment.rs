@@ -0,0 +1,55 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::Object;
+
+pub type Env = Rc<RefCell<Environment>>;
+
+// 変数の束縛。関数呼び出しごとに外側の環境を持つ新しい環境を作る
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    pub fn enclosed(outer: &Env) -> Env {
+        Rc::new(RefCell::new(Self {
+            store: HashMap::new(),
+            outer: Some(outer.clone()),
+        }))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(object) => Some(object.clone()),
+            None => self
+                .outer
+                .as_ref()
+                .and_then(|outer| outer.borrow().get(name)),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, object: Object) {
+        self.store.insert(name.to_string(), object);
+    }
+
+    // 束縛をすべて消す。同じ環境を参照しているものからも見えなくなる
+    pub fn clear(&mut self) {
+        self.store.clear();
+    }
+
+    // この環境で束縛している名前（外側は含まない）を名前順に返す
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings: Vec<(String, Object)> = self
+            .store
+            .iter()
+            .map(|(name, object)| (name.clone(), object.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+}
@@ -0,0 +1,594 @@
+// 評価の前に AST を書き換えて、結果を変えずに簡単にする
+//   1: 定数の畳み込み、条件が定数の if の分岐の削除、return より後の文の削除
+//   2: 1 に加えて、エラーにならない小さな関数の呼び出しをインライン展開する
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        self, expression, statement,
+        visit::{self, Visitor, VisitorMut},
+        Node,
+    },
+    evaluator,
+    object::Object,
+    token,
+};
+
+pub const MAX_LEVEL: u8 = 2;
+
+// インライン展開する関数の本体の大きさ（式の数）の上限
+const INLINE_LIMIT: usize = 10;
+
+pub fn optimize(program: &mut ast::Program, level: u8) {
+    if level == 0 {
+        return;
+    }
+    Folder.visit_program_mut(program);
+    if level >= 2 {
+        inline(program);
+        Folder.visit_program_mut(program);
+    }
+}
+
+// 整数、真偽値、文字列のリテラルならその値
+fn constant(expression: &expression::Expression) -> Option<Object> {
+    match expression {
+        expression::Expression::IntegerLiteral(literal) => Some(Object::Integer(literal.value)),
+        expression::Expression::StringLiteral(literal) => {
+            Some(Object::String(literal.value.as_str().into()))
+        }
+        expression::Expression::Boolean(boolean) => Some(Object::Boolean(boolean.value)),
+        _ => None,
+    }
+}
+
+// 評価してもエラーにならない式。引数で置き換えた識別子は評価済みの値を指している
+fn cannot_fail(expression: &expression::Expression) -> bool {
+    match expression {
+        expression::Expression::Identifier(_)
+        | expression::Expression::IntegerLiteral(_)
+        | expression::Expression::StringLiteral(_)
+        | expression::Expression::Boolean(_) => true,
+        expression::Expression::PrefixExpression(e) => e.operator == "!" && cannot_fail(&e.right),
+        expression::Expression::ArrayLiteral(array) => array.elements.iter().all(cannot_fail),
+        expression::Expression::HashLiteral(hash) => hash
+            .pairs
+            .iter()
+            .all(|(k, v)| constant(k).is_some() && cannot_fail(v)),
+        _ => false,
+    }
+}
+
+fn to_literal(object: Object, span: token::Span) -> Option<expression::Expression> {
+    let expression = match object {
+        Object::Integer(value) => {
+            let literal = value.to_string().into();
+            expression::Expression::IntegerLiteral(expression::IntegerLiteral {
+                token: token::Token::with_span(token::TokenType::Int, literal, span),
+                value,
+            })
+        }
+        Object::Boolean(value) => boolean(value, span),
+        Object::String(value) => {
+            let literal = value.to_string().into();
+            expression::Expression::StringLiteral(expression::StringLiteral {
+                token: token::Token::with_span(token::TokenType::String, literal, span),
+                value: value.to_string(),
+            })
+        }
+        _ => return None,
+    };
+    Some(expression)
+}
+
+fn boolean(value: bool, span: token::Span) -> expression::Expression {
+    let typ = if value {
+        token::TokenType::True
+    } else {
+        token::TokenType::False
+    };
+    expression::Expression::Boolean(expression::Boolean {
+        token: token::Token::with_span(typ, value.to_string().into(), span),
+        value,
+    })
+}
+
+struct Folder;
+
+impl Folder {
+    // 評価器と同じ計算をして、エラーになる式はそのまま残す
+    fn fold(expression: &expression::Expression) -> Option<expression::Expression> {
+        let span = expression.span();
+        let object = match expression {
+            expression::Expression::PrefixExpression(e) => {
+                evaluator::eval_prefix(&e.operator, constant(&e.right)?).ok()?
+            }
+            expression::Expression::InfixExpression(e) => {
+                evaluator::eval_infix(&e.operator, constant(&e.left)?, constant(&e.right)?).ok()?
+            }
+            expression::Expression::IfExpression(e) => {
+                let statements = match &e.consequence.statements[..] {
+                    [statement::Statement::ExpressionStatement(s)] if e.alternative.is_none() => s,
+                    _ => return None,
+                };
+                // 条件が定数で分岐が式ひとつだけなら、その式に置き換える
+                if constant(&e.condition)?.is_truthy() {
+                    return Some(statements.expression.clone());
+                }
+                return None;
+            }
+            _ => return None,
+        };
+        to_literal(object, span)
+    }
+
+    // 分岐が定数で決まるなら、通る方だけを `if (true) { ... }` の形で残す
+    fn prune(expression: &mut expression::IfExpression) {
+        let truthy = match constant(&expression.condition) {
+            Some(condition) => condition.is_truthy(),
+            None => return,
+        };
+        if truthy {
+            expression.alternative = None;
+        } else if let Some(alternative) = expression.alternative.take() {
+            *expression.condition = boolean(true, expression.condition.span());
+            expression.consequence = alternative;
+        } else {
+            expression.consequence.statements.clear();
+        }
+    }
+
+    // ブロックはスコープを作らないので、条件が定数の if 文は中身を展開できる
+    // ただし最後の文はブロックの値になるので、空になるときは残す
+    fn flatten(statements: &mut Vec<statement::Statement>) {
+        let len = statements.len();
+        let mut result = Vec::with_capacity(len);
+        for (i, statement) in std::mem::take(statements).into_iter().enumerate() {
+            let is_last = i + 1 == len;
+            match statement {
+                statement::Statement::ExpressionStatement(s) => match s.expression {
+                    expression::Expression::IfExpression(e)
+                        if e.alternative.is_none() && constant(&e.condition).is_some() =>
+                    {
+                        if !e.consequence.statements.is_empty() {
+                            result.extend(e.consequence.statements);
+                        } else if is_last {
+                            result.push(statement::Statement::ExpressionStatement(
+                                statement::ExpressionStatement {
+                                    expression: expression::Expression::IfExpression(e),
+                                },
+                            ));
+                        }
+                    }
+                    expression => result.push(statement::Statement::ExpressionStatement(
+                        statement::ExpressionStatement { expression },
+                    )),
+                },
+                statement => result.push(statement),
+            }
+        }
+
+        // return と throw より後は実行されない
+        if let Some(i) = result.iter().position(|s| {
+            matches!(
+                s,
+                statement::Statement::ReturnStatement(_) | statement::Statement::ThrowStatement(_)
+            )
+        }) {
+            result.truncate(i + 1);
+        }
+        *statements = result;
+    }
+}
+
+impl VisitorMut for Folder {
+    fn visit_program_mut(&mut self, program: &mut ast::Program) {
+        visit::walk_program_mut(self, program);
+        Self::flatten(&mut program.statements);
+    }
+
+    fn visit_block_statement_mut(&mut self, block: &mut statement::BlockStatement) {
+        visit::walk_block_statement_mut(self, block);
+        Self::flatten(&mut block.statements);
+    }
+
+    fn visit_if_expression_mut(&mut self, expression: &mut expression::IfExpression) {
+        visit::walk_if_expression_mut(self, expression);
+        Self::prune(expression);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut expression::Expression) {
+        visit::walk_expression_mut(self, expression);
+        if let Some(folded) = Self::fold(expression) {
+            *expression = folded;
+        }
+    }
+}
+
+// インライン展開できる関数。本体は引数だけを使う、呼び出しを含まない小さな式
+struct Candidate {
+    parameters: Vec<String>,
+    body: expression::Expression,
+}
+
+impl Candidate {
+    fn new(function: &expression::FunctionLiteral) -> Option<Self> {
+        let body = match &function.body.statements[..] {
+            [statement::Statement::ExpressionStatement(s)] => &s.expression,
+            [statement::Statement::ReturnStatement(s)] => &s.return_value,
+            _ => return None,
+        };
+        let parameters: Vec<String> = function
+            .parameters
+            .iter()
+            .map(|p| p.name.name().to_string())
+            .collect();
+        let mut size = 0;
+        if Self::is_simple(body, &parameters, &mut size) && size <= INLINE_LIMIT {
+            Some(Self {
+                parameters,
+                body: body.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn is_simple(
+        expression: &expression::Expression,
+        parameters: &[String],
+        size: &mut usize,
+    ) -> bool {
+        *size += 1;
+        match expression {
+            expression::Expression::Identifier(identifier) => {
+                parameters.iter().any(|p| p == identifier.name())
+            }
+            expression::Expression::IntegerLiteral(_)
+            | expression::Expression::StringLiteral(_)
+            | expression::Expression::Boolean(_) => true,
+            expression::Expression::PrefixExpression(e) => {
+                Self::is_simple(&e.right, parameters, size)
+            }
+            expression::Expression::InfixExpression(e) => {
+                Self::is_simple(&e.left, parameters, size)
+                    && Self::is_simple(&e.right, parameters, size)
+            }
+            expression::Expression::IndexExpression(e) => {
+                Self::is_simple(&e.left, parameters, size)
+                    && Self::is_simple(&e.index, parameters, size)
+            }
+            expression::Expression::ArrayLiteral(array) => array
+                .elements
+                .iter()
+                .all(|e| Self::is_simple(e, parameters, size)),
+            expression::Expression::HashLiteral(hash) => hash.pairs.iter().all(|(k, v)| {
+                Self::is_simple(k, parameters, size) && Self::is_simple(v, parameters, size)
+            }),
+            _ => false,
+        }
+    }
+}
+
+// 宣言されている名前（let と引数と import の別名と catch の引数）ごとの数
+#[derive(Default)]
+struct Declarations(HashMap<String, usize>);
+
+impl<'ast> Visitor<'ast> for Declarations {
+    fn visit_let_statement(&mut self, statement: &'ast statement::LetStatement) {
+        *self.0.entry(statement.name.name().to_string()).or_default() += 1;
+        visit::walk_let_statement(self, statement);
+    }
+
+    fn visit_import_statement(&mut self, statement: &'ast statement::ImportStatement) {
+        *self
+            .0
+            .entry(statement.alias.name().to_string())
+            .or_default() += 1;
+    }
+
+    fn visit_function_literal(&mut self, function: &'ast expression::FunctionLiteral) {
+        for parameter in &function.parameters {
+            *self.0.entry(parameter.name.name().to_string()).or_default() += 1;
+        }
+        visit::walk_function_literal(self, function);
+    }
+
+    fn visit_catch_clause(&mut self, catch: &'ast expression::CatchClause) {
+        *self
+            .0
+            .entry(catch.parameter.name().to_string())
+            .or_default() += 1;
+        visit::walk_catch_clause(self, catch);
+    }
+}
+
+// トップレベルで一度だけ宣言された関数を、宣言より後の文の中で展開する
+fn inline(program: &mut ast::Program) {
+    let mut declarations = Declarations::default();
+    declarations.visit_program(program);
+
+    let mut inliner = Inliner {
+        candidates: HashMap::new(),
+        defined: HashSet::new(),
+        parameters: Vec::new(),
+    };
+    for statement in &mut program.statements {
+        inliner.visit_statement_mut(statement);
+        if let statement::Statement::LetStatement(s) = statement {
+            let name = s.name.name().to_string();
+            if let expression::Expression::FunctionLiteral(function) = &s.value {
+                if declarations.0.get(&name) == Some(&1) {
+                    if let Some(candidate) = Candidate::new(function) {
+                        inliner.candidates.insert(name.clone(), candidate);
+                    }
+                }
+            }
+            inliner.defined.insert(name);
+        }
+    }
+}
+
+struct Inliner {
+    candidates: HashMap<String, Candidate>,
+    // 実行済みのトップレベルの let で束縛された名前
+    defined: HashSet<String>,
+    // 囲んでいる関数の引数
+    parameters: Vec<Vec<String>>,
+}
+
+impl Inliner {
+    // 引数は評価してもエラーにならず、何度評価しても同じものだけにする
+    fn is_pure(&self, argument: &expression::Expression) -> bool {
+        match argument {
+            expression::Expression::Identifier(identifier) => {
+                let name = identifier.name();
+                self.defined.contains(name) || self.parameters.iter().flatten().any(|p| p == name)
+            }
+            _ => constant(argument).is_some(),
+        }
+    }
+
+    fn expand(&self, call: &expression::CallExpression) -> Option<expression::Expression> {
+        let name = match call.function.as_ref() {
+            expression::Expression::Identifier(identifier) => identifier.name(),
+            _ => return None,
+        };
+        let candidate = self.candidates.get(name)?;
+        if candidate.parameters.len() != call.arguments.len()
+            || !call.arguments.iter().all(|a| self.is_pure(a))
+        {
+            return None;
+        }
+        let mut body = candidate.body.clone();
+        Substitution {
+            arguments: candidate
+                .parameters
+                .iter()
+                .map(String::as_str)
+                .zip(&call.arguments)
+                .collect(),
+        }
+        .visit_expression_mut(&mut body);
+        // エラーになりうる式を展開すると、エラーの位置とトレースから関数の呼び出しが消える
+        Folder.visit_expression_mut(&mut body);
+        if !cannot_fail(&body) {
+            return None;
+        }
+        Some(body)
+    }
+}
+
+impl VisitorMut for Inliner {
+    fn visit_function_literal_mut(&mut self, function: &mut expression::FunctionLiteral) {
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|p| p.name.name().to_string())
+            .collect();
+        self.parameters.push(parameters);
+        visit::walk_function_literal_mut(self, function);
+        self.parameters.pop();
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut expression::Expression) {
+        visit::walk_expression_mut(self, expression);
+        if let expression::Expression::CallExpression(call) = expression {
+            if let Some(expanded) = self.expand(call) {
+                *expression = expanded;
+            }
+        }
+    }
+}
+
+// 本体の引数を実引数の式で置き換える
+struct Substitution<'a> {
+    arguments: HashMap<&'a str, &'a expression::Expression>,
+}
+
+impl VisitorMut for Substitution<'_> {
+    fn visit_expression_mut(&mut self, expression: &mut expression::Expression) {
+        if let expression::Expression::Identifier(identifier) = expression {
+            if let Some(argument) = self.arguments.get(identifier.name()) {
+                *expression = (*argument).clone();
+            }
+            return;
+        }
+        visit::walk_expression_mut(self, expression);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{optimize, MAX_LEVEL};
+    use crate::{evaluator, object::Environment, parser};
+
+    fn optimized(input: &str, level: u8) -> String {
+        let mut program = parser::parse(input).unwrap();
+        optimize(&mut program, level);
+        program.to_string()
+    }
+
+    #[test]
+    fn test_optimize() {
+        let tests = vec![
+            ("1 + 2 * 3; -(4 - 5); !true", 1, "7;\n1;\nfalse;"),
+            ("\"a\" + \"b\" == \"ab\"", 1, "true;"),
+            // エラーになる式は実行時まで残す
+            ("1 / 0; 1 + true", 1, "(1 / 0);\n(1 + true);"),
+            ("x * (2 + 3)", 1, "(x * 5);"),
+            ("if (1 < 2) { a } else { b }", 1, "a;"),
+            (
+                "if (false) { a } else { let b = 1; b }",
+                1,
+                "let b = 1;\nb;",
+            ),
+            ("if (false) { a }", 1, "if (false) { };"),
+            ("if (false) { a }; 1", 1, "1;"),
+            (
+                "if (x) { 1 + 1 } else { 2 }",
+                1,
+                "if (x) { 2; } else { 2; };",
+            ),
+            (
+                "fn() { a; return 1; b; }; return 2; c",
+                1,
+                "fn() { a; return 1; };\nreturn 2;",
+            ),
+            (
+                "try { throw 1 + 1; a } catch (e) { e }",
+                1,
+                "try { throw 2; } catch (e) { e; };",
+            ),
+            (
+                "let f = fn(a) { a * 2 }; f(3) + f(x)",
+                1,
+                "let f = fn(a) { (a * 2); };\n(f(3) + f(x));",
+            ),
+            (
+                "let f = fn(a) { a * 2 }; f(3) + f(x)",
+                2,
+                "let f = fn(a) { (a * 2); };\n(6 + f(x));",
+            ),
+            (
+                "let x = 1; let f = fn(a, b) { [a, !b] }; fn(y) { f(x, y) }",
+                2,
+                "let x = 1;\nlet f = fn(a, b) { [a, (!b)]; };\nfn(y) { [x, (!y)]; };",
+            ),
+            // 定数に畳み込めず、エラーになりうる本体は展開しない
+            (
+                "let f = fn(a) { a * 2 }; fn(y) { f(y) }; f(\"s\")",
+                2,
+                "let f = fn(a) { (a * 2); };\nfn(y) { f(y); };\nf(\"s\");",
+            ),
+            // 再帰する関数、宣言より前の呼び出し、同じ名前が他にもある関数は展開しない
+            (
+                "let f = fn(n) { f(n) }; f(1)",
+                2,
+                "let f = fn(n) { f(n); };\nf(1);",
+            ),
+            (
+                "g(1); let g = fn(a) { a }; g(2)",
+                2,
+                "g(1);\nlet g = fn(a) { a; };\n2;",
+            ),
+            (
+                "let h = fn(a) { a }; fn(h) { h }; h(1)",
+                2,
+                "let h = fn(a) { a; };\nfn(h) { h; };\nh(1);",
+            ),
+        ];
+        for (input, level, expected) in tests {
+            assert_eq!(optimized(input, level), expected, "input: {}", input);
+        }
+    }
+
+    // どの最適化レベルでも評価結果が変わらない
+    #[test]
+    fn test_semantics_preserved() {
+        let corpus = vec![
+            "1 + 2 * 3 - 4 / 2",
+            "9223372036854775807 + 1",
+            "-(-9223372036854775807 - 1)",
+            "\"foo\" + \"bar\" == \"foobar\"",
+            "!!\"\"; !0",
+            "if (1 > 2) { 10 } else { 20 }",
+            "if (false) { 10 }",
+            "let x = 5; if (true) { let x = x * 2; } x",
+            "let f = fn() { if (true) { return 1; } return 2; }; f()",
+            "let f = fn() { if (false) { return 1; } }; f()",
+            "let f = fn(x) { return x; x + true }; f(3)",
+            "return 1; undefined",
+            "let double = fn(x) { x * 2 }; double(double(21))",
+            "let add = fn(a, b) { a + b }; add(\"a\", \"b\") + add(\"c\", \"d\")",
+            "let first = fn(a, b) { a }; let g = fn(x) { first(x, missing) }; g(1)",
+            "let first = fn(a, b) { a }; first(1, missing)",
+            "let get = fn(h, k) { h[k] }; let h = {\"a\": 1}; get(h, \"a\") + get(h, \"a\")",
+            "let bad = fn(a) { a + true }; if (false) { bad(1) } else { 0 }",
+            "let bad = fn(a) { a + true }; bad(1)",
+            "let f = fn(a) { a }; f(1, 2)",
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10)",
+            "let apply = fn(g, v) { g(v) }; let inc = fn(v) { v + 1 }; apply(inc, 41)",
+            "let a = [1, 2 + 3, 4 * 5]; a[1 + 1] + a[0]",
+            "let x = if (\"s\") { 1 } else { 2 }; let y = if (0) { 3 }; [x, y]",
+            "{1 + 1: \"two\", true == true: 3 > 2}",
+            "1 / 0",
+            "let z = 0; 1 / z",
+            "let f = fn(a) { [a][0] * 2 }; f(f(f(1)))",
+            "let f = fn(a) { a / 0 }; try { f(1) } catch (e) { [e.kind, e.message] }",
+            "let f = fn(a) { if (a) { throw a * 2; } a }; try { f(1) } catch (e) { e }",
+            "let f = fn(a) { a * 2 }; let r = try { throw fn(a) { a + 100 } } catch (f) { f(1) }; r",
+            "let f = fn(a) { a / 0 }; try { f(1) } catch (e) { e.trace }",
+            "let f = fn(a) { [a][1] }; let g = fn(b) { f(b) }; g(1)",
+        ];
+        for input in corpus {
+            let expected = eval(input, 0);
+            for level in 1..=MAX_LEVEL {
+                assert_eq!(eval(input, level), expected, "level {}: {}", level, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_catch_parameter() {
+        // catch の引数と同じ名前の関数は、catch の中の呼び出しがどちらか決められないので展開しない
+        let input = "let f = fn(a) { !a }; try { throw 1 } catch (f) { f(1) }; f(true)";
+        assert_eq!(
+            optimized(input, 2),
+            "let f = fn(a) { (!a); };\ntry { throw 1; } catch (f) { f(1); };\nf(true);"
+        );
+    }
+
+    #[test]
+    fn test_fallible_call_keeps_trace() {
+        // エラーになりうる呼び出しは展開しないので、トレースに関数が残る
+        let input = "let f = fn(a) { a / 0 };\nlet g = fn(b) { f(b) };\ng(1)";
+        let mut program = parser::parse(input).unwrap();
+        optimize(&mut program, 2);
+        let mut loader = evaluator::Loader::new();
+        let e = loader
+            .eval_program(&program, &Environment::new(), None)
+            .unwrap_err();
+        assert_eq!(e.to_string(), "division by zero");
+        assert_eq!(loader.span().unwrap().to_string(), "1:17-1:22");
+        let trace: Vec<String> = loader.trace().iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            trace,
+            vec!["<anonymous> (let g) at 3:1", "<anonymous> (let f) at 2:17"]
+        );
+    }
+
+    // エラーのときは位置とトレースも比べる
+    fn eval(input: &str, level: u8) -> String {
+        let mut program = parser::parse(input).unwrap();
+        optimize(&mut program, level);
+        let mut loader = evaluator::Loader::new();
+        match loader.eval_program(&program, &Environment::new(), None) {
+            Ok(object) => object.to_string(),
+            Err(e) => {
+                let trace: Vec<String> = loader.trace().iter().map(|f| f.to_string()).collect();
+                format!("error: {} at {:?} in {:?}", e, loader.span(), trace)
+            }
+        }
+    }
+}
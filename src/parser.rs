@@ -1,61 +1,161 @@
 use crate::{
-    ast::{self, statement},
+    ast::{self, annotation, expression, statement},
     lexer, token,
 };
-use anyhow::Result;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-enum ParseError {
+pub enum ParseError {
+    #[error(transparent)]
+    Lex(#[from] lexer::LexError),
     #[error("expected next token to be {want}, got {got} instead")]
     UnexpectedPeekToken {
         want: token::TokenType,
         got: token::TokenType,
     },
+    #[error("no prefix parse function for {typ} found")]
+    NoPrefixParseFunction { typ: token::TokenType },
+    #[error("could not parse {literal:?} as integer")]
+    InvalidIntegerLiteral { literal: String },
+    #[error("{literal:?} is not a type")]
+    InvalidTypeAnnotation { literal: String },
+    #[error("{keyword} is only allowed at the top level")]
+    NotAtTopLevel { keyword: String },
+    // `}` がないまま入力が終わった
+    #[error("unterminated block")]
+    UnterminatedBlock,
 }
 
-struct Parser {
+impl ParseError {
+    // ドキュメントやテストで使うエラーコード。番号は変えない
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::Lex(e) => e.code(),
+            ParseError::UnexpectedPeekToken { .. } => "E0101",
+            ParseError::NoPrefixParseFunction { .. } => "E0102",
+            ParseError::InvalidIntegerLiteral { .. } => "E0103",
+            ParseError::InvalidTypeAnnotation { .. } => "E0104",
+            ParseError::NotAtTopLevel { .. } => "E0105",
+            ParseError::UnterminatedBlock => "E0106",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub(crate) enum Precedence {
+    Lowest,
+    Equals,      // ==
+    LessGreater, // > or <
+    Sum,         // +
+    Product,     // *
+    Prefix,      // -X or !X
+    Call,        // myFunction(X)
+    Index,       // array[index]
+}
+
+impl Precedence {
+    pub(crate) fn of(typ: &token::TokenType) -> Self {
+        match typ {
+            token::TokenType::Eq | token::TokenType::NotEq => Precedence::Equals,
+            token::TokenType::Lt | token::TokenType::Gt => Precedence::LessGreater,
+            token::TokenType::Plus | token::TokenType::Minus => Precedence::Sum,
+            token::TokenType::Slash | token::TokenType::Asterisk => Precedence::Product,
+            token::TokenType::Lparne => Precedence::Call,
+            token::TokenType::Lbracket | token::TokenType::Dot => Precedence::Index,
+            _ => Precedence::Lowest,
+        }
+    }
+}
+
+pub struct Parser {
     l: lexer::Lexer,
     cur_token: Option<token::Token>,
     peek_token: Option<token::Token>,
     // TODO エラーをResultで統一したほうがいい気がしている
     errors: Vec<ParseError>,
+    // errors と同じ順番で、エラーが起きた位置
+    spans: Vec<token::Span>,
+    // 読んでいるブロックの深さ。import と export はトップレベルにしか書けない
+    depth: usize,
 }
 
 impl Parser {
-    fn new(l: lexer::Lexer) -> Self {
+    pub fn new(l: lexer::Lexer) -> Self {
         let mut p = Self {
             l,
             cur_token: None,
             peek_token: None,
             errors: Vec::new(),
+            spans: Vec::new(),
+            depth: 0,
         };
         p.next_token();
         p.next_token();
         p
     }
 
-    fn errors(&mut self) -> Vec<ParseError> {
+    pub fn errors(&mut self) -> Vec<ParseError> {
         // TODO 一旦コピーせず、持っているものを返す。
+        self.spans.clear();
         std::mem::take(&mut self.errors)
     }
 
+    // エラーとその位置の組を返す
+    pub fn errors_with_spans(&mut self) -> Vec<(ParseError, token::Span)> {
+        let spans = std::mem::take(&mut self.spans);
+        self.errors().into_iter().zip(spans).collect()
+    }
+
+    fn error(&mut self, e: ParseError, span: token::Span) {
+        self.errors.push(e);
+        self.spans.push(span);
+    }
+
     fn next_token(&mut self) {
-        self.cur_token = std::mem::replace(&mut self.peek_token, Some(self.l.next_token()));
+        let token = self.l.next_token();
+        if token.typ == token::TokenType::Illegal {
+            for e in self.l.errors() {
+                let pos = e.pos();
+                self.error(ParseError::from(e), token::Span::new(pos, token.end));
+            }
+        }
+        self.cur_token = self.peek_token.replace(token);
+    }
+
+    // Bytes の clone は参照カウントを増やすだけなので、token はそのまま clone して使う
+    fn cur_token(&self) -> token::Token {
+        self.cur_token.clone().unwrap()
     }
 
     fn cur_token_is(&self, target: &token::TokenType) -> bool {
-        matches!(&self.cur_token, Some(token::Token { typ, literal: _ }) if typ == target)
+        matches!(&self.cur_token, Some(token::Token { typ, .. }) if typ == target)
     }
     fn peek_token_is(&self, target: &token::TokenType) -> bool {
-        matches!(&self.peek_token, Some(token::Token { typ, literal: _ }) if typ == target)
+        matches!(&self.peek_token, Some(token::Token { typ, .. }) if typ == target)
+    }
+
+    fn peek_precedence(&self) -> Precedence {
+        self.peek_token
+            .as_ref()
+            .map_or(Precedence::Lowest, |t| Precedence::of(&t.typ))
+    }
+
+    fn cur_precedence(&self) -> Precedence {
+        self.cur_token
+            .as_ref()
+            .map_or(Precedence::Lowest, |t| Precedence::of(&t.typ))
     }
 
     fn peek_error(&mut self, token: &token::TokenType) {
-        self.errors.push(ParseError::UnexpectedPeekToken {
-            want: token.clone(),
-            got: self.peek_token.as_ref().unwrap().typ.clone(),
-        })
+        let peek = self.peek_token.as_ref().unwrap();
+        let (got, span) = (peek.typ.clone(), peek.span());
+        self.error(
+            ParseError::UnexpectedPeekToken {
+                want: token.clone(),
+                got,
+            },
+            span,
+        )
     }
 
     // expect_peek check peek token. this method call next_token if own token's type match target type
@@ -70,57 +170,604 @@ impl Parser {
     }
 
     fn parse_let_statemet(&mut self) -> Option<statement::Statement> {
-        let token = std::mem::replace(&mut self.cur_token, None).unwrap();
+        let token = self.cur_token();
         if !self.expect_peek(&token::TokenType::Ident) {
             return None;
         }
-        let identifier_token = std::mem::replace(&mut self.cur_token, None).unwrap();
-        let name = ast::expression::Identifier::new(identifier_token);
+        let name = expression::Identifier::new(self.cur_token());
+        let annotation = self
+            .parse_optional_annotation(&token::TokenType::Colon)?
+            .map(Box::new);
 
         if !self.expect_peek(&token::TokenType::Assign) {
             return None;
         }
-        // TODO 一旦セミコロンまで読み飛ばす
-        while !self.cur_token_is(&token::TokenType::Semicolon) {
-            self.next_token()
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_token_is(&token::TokenType::Semicolon) {
+            self.next_token();
         }
         Some(statement::Statement::LetStatement(
             statement::LetStatement {
                 name,
                 token,
-                value: None,
+                annotation,
+                value,
             },
         ))
     }
     fn parse_return_statemet(&mut self) -> Option<statement::Statement> {
-        let token = std::mem::replace(&mut self.cur_token, None).unwrap();
+        let token = self.cur_token();
 
         self.next_token();
-
-        // TODO 一旦セミコロンまで読み飛ばす
-        while !self.cur_token_is(&token::TokenType::Semicolon) {
-            self.next_token()
+        let return_value = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_token_is(&token::TokenType::Semicolon) {
+            self.next_token();
         }
         Some(statement::Statement::ReturnStatement(
             statement::ReturnStatement {
                 token,
-                return_value: None,
+                return_value,
             },
         ))
     }
 
+    fn parse_throw_statement(&mut self) -> Option<statement::Statement> {
+        let token = self.cur_token();
+
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_token_is(&token::TokenType::Semicolon) {
+            self.next_token();
+        }
+        Some(statement::Statement::ThrowStatement(
+            statement::ThrowStatement { token, value },
+        ))
+    }
+
+    fn parse_import_statement(&mut self) -> Option<statement::Statement> {
+        let token = self.cur_token();
+        if !self.expect_peek(&token::TokenType::String) {
+            return None;
+        }
+        let path = self.cur_token();
+        let path = expression::StringLiteral {
+            value: String::from_utf8_lossy(&path.literal[..]).into_owned(),
+            token: path,
+        };
+        if !self.expect_peek(&token::TokenType::As) || !self.expect_peek(&token::TokenType::Ident) {
+            return None;
+        }
+        let alias = expression::Identifier::new(self.cur_token());
+        if self.peek_token_is(&token::TokenType::Semicolon) {
+            self.next_token();
+        }
+        Some(statement::Statement::ImportStatement(
+            statement::ImportStatement { token, path, alias },
+        ))
+    }
+
+    fn parse_export_statement(&mut self) -> Option<statement::Statement> {
+        let token = self.cur_token();
+        if !self.expect_peek(&token::TokenType::Let) {
+            return None;
+        }
+        match self.parse_let_statemet()? {
+            statement::Statement::LetStatement(statement) => {
+                Some(statement::Statement::ExportStatement(
+                    statement::ExportStatement { token, statement },
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_expression_statement(&mut self) -> Option<statement::Statement> {
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_token_is(&token::TokenType::Semicolon) {
+            self.next_token();
+        }
+        Some(statement::Statement::ExpressionStatement(
+            statement::ExpressionStatement { expression },
+        ))
+    }
+
     fn parse_statement(&mut self) -> Option<statement::Statement> {
         match self.cur_token {
             Some(token::Token {
                 typ: token::TokenType::Let,
-                literal: _,
+                ..
             }) => self.parse_let_statemet(),
             Some(token::Token {
                 typ: token::TokenType::Retrun,
-                literal: _,
+                ..
             }) => self.parse_return_statemet(),
-            _ => None,
+            Some(token::Token {
+                typ: token::TokenType::Throw,
+                ..
+            }) => self.parse_throw_statement(),
+            Some(
+                ref token @ token::Token {
+                    typ: token::TokenType::Import | token::TokenType::Export,
+                    ..
+                },
+            ) if self.depth > 0 => {
+                let keyword = String::from_utf8_lossy(&token.literal[..]).into_owned();
+                let span = token.span();
+                self.error(ParseError::NotAtTopLevel { keyword }, span);
+                None
+            }
+            Some(token::Token {
+                typ: token::TokenType::Import,
+                ..
+            }) => self.parse_import_statement(),
+            Some(token::Token {
+                typ: token::TokenType::Export,
+                ..
+            }) => self.parse_export_statement(),
+            _ => self.parse_expression_statement(),
+        }
+    }
+
+    fn parse_block_statement(&mut self) -> statement::BlockStatement {
+        let token = self.cur_token();
+        let mut statements = Vec::new();
+        self.next_token();
+        self.depth += 1;
+        while !self.cur_token_is(&token::TokenType::Rbrace)
+            && !self.cur_token_is(&token::TokenType::Eof)
+        {
+            if let Some(stmt) = self.parse_statement() {
+                statements.push(stmt);
+            }
+            self.next_token();
+        }
+        self.depth -= 1;
+        if self.cur_token_is(&token::TokenType::Eof) {
+            self.error(ParseError::UnterminatedBlock, token.span());
+        }
+        statement::BlockStatement {
+            token,
+            statements,
+            rbrace: self.cur_token(),
+        }
+    }
+
+    // Pratt parser
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<expression::Expression> {
+        let mut left = self.parse_prefix()?;
+        while !self.peek_token_is(&token::TokenType::Semicolon)
+            && precedence < self.peek_precedence()
+        {
+            self.next_token();
+            left = match self.cur_token().typ {
+                token::TokenType::Lparne => self.parse_call_expression(left)?,
+                token::TokenType::Lbracket => self.parse_index_expression(left)?,
+                token::TokenType::Dot => self.parse_member_expression(left)?,
+                _ => self.parse_infix_expression(left)?,
+            };
+        }
+        Some(left)
+    }
+
+    fn parse_prefix(&mut self) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        let expression = match token.typ {
+            token::TokenType::Ident => {
+                expression::Expression::Identifier(expression::Identifier::new(token))
+            }
+            token::TokenType::Int => {
+                let literal = String::from_utf8_lossy(&token.literal[..]).into_owned();
+                match literal.parse() {
+                    Ok(value) => {
+                        expression::Expression::IntegerLiteral(expression::IntegerLiteral {
+                            token,
+                            value,
+                        })
+                    }
+                    Err(_) => {
+                        self.error(ParseError::InvalidIntegerLiteral { literal }, token.span());
+                        return None;
+                    }
+                }
+            }
+            token::TokenType::String => {
+                let value = String::from_utf8_lossy(&token.literal[..]).into_owned();
+                expression::Expression::StringLiteral(expression::StringLiteral { token, value })
+            }
+            token::TokenType::True | token::TokenType::False => {
+                let value = token.typ == token::TokenType::True;
+                expression::Expression::Boolean(expression::Boolean { token, value })
+            }
+            token::TokenType::Bang | token::TokenType::Minus => {
+                let operator = String::from_utf8_lossy(&token.literal[..]).into_owned();
+                self.next_token();
+                let right = self.parse_expression(Precedence::Prefix)?;
+                expression::Expression::PrefixExpression(expression::PrefixExpression {
+                    token,
+                    operator,
+                    right: Box::new(right),
+                })
+            }
+            token::TokenType::Lparne => {
+                self.next_token();
+                let expression = self.parse_expression(Precedence::Lowest)?;
+                if !self.expect_peek(&token::TokenType::Rparne) {
+                    return None;
+                }
+                expression
+            }
+            token::TokenType::If => self.parse_if_expression()?,
+            token::TokenType::Try => self.parse_try_expression()?,
+            token::TokenType::Function => self.parse_function_literal()?,
+            token::TokenType::Lbracket => {
+                let elements = self.parse_expression_list(&token::TokenType::Rbracket)?;
+                expression::Expression::ArrayLiteral(expression::ArrayLiteral {
+                    token,
+                    elements,
+                    rbracket: self.cur_token(),
+                })
+            }
+            token::TokenType::Lbrace => self.parse_hash_literal()?,
+            // 字句エラーは next_token で報告済みなので、ここでは報告せずに 1 つの式として読み飛ばす
+            // エラーがあれば木は使われないので、中身は token をそのまま持つ識別子にしておく
+            token::TokenType::Illegal => {
+                expression::Expression::Identifier(expression::Identifier::new(token))
+            }
+            typ => {
+                let span = self.cur_token().span();
+                self.error(ParseError::NoPrefixParseFunction { typ }, span);
+                return None;
+            }
+        };
+        Some(expression)
+    }
+
+    fn parse_infix_expression(
+        &mut self,
+        left: expression::Expression,
+    ) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        let operator = String::from_utf8_lossy(&token.literal[..]).into_owned();
+        let precedence = self.cur_precedence();
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+        Some(expression::Expression::InfixExpression(
+            expression::InfixExpression {
+                token,
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            },
+        ))
+    }
+
+    fn parse_if_expression(&mut self) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        if !self.expect_peek(&token::TokenType::Lparne) {
+            return None;
+        }
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(&token::TokenType::Rparne) {
+            return None;
+        }
+        if !self.expect_peek(&token::TokenType::Lbrace) {
+            return None;
+        }
+        let consequence = self.parse_block_statement();
+
+        let mut alternative = None;
+        if self.peek_token_is(&token::TokenType::Else) {
+            self.next_token();
+            if !self.expect_peek(&token::TokenType::Lbrace) {
+                return None;
+            }
+            alternative = Some(self.parse_block_statement());
+        }
+        Some(expression::Expression::IfExpression(
+            expression::IfExpression {
+                token,
+                condition: Box::new(condition),
+                consequence,
+                alternative,
+            },
+        ))
+    }
+
+    fn parse_try_expression(&mut self) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        if !self.expect_peek(&token::TokenType::Lbrace) {
+            return None;
+        }
+        let block = self.parse_block_statement();
+
+        let mut catch = None;
+        if self.peek_token_is(&token::TokenType::Catch) {
+            self.next_token();
+            let token = self.cur_token();
+            if !self.expect_peek(&token::TokenType::Lparne)
+                || !self.expect_peek(&token::TokenType::Ident)
+            {
+                return None;
+            }
+            let parameter = expression::Identifier::new(self.cur_token());
+            if !self.expect_peek(&token::TokenType::Rparne)
+                || !self.expect_peek(&token::TokenType::Lbrace)
+            {
+                return None;
+            }
+            let body = self.parse_block_statement();
+            catch = Some(Box::new(expression::CatchClause {
+                token,
+                parameter,
+                body,
+            }));
+        }
+
+        let mut finally = None;
+        if self.peek_token_is(&token::TokenType::Finally) {
+            self.next_token();
+            if !self.expect_peek(&token::TokenType::Lbrace) {
+                return None;
+            }
+            finally = Some(self.parse_block_statement());
+        } else if catch.is_none() {
+            // catch も finally もない try は書けない
+            self.peek_error(&token::TokenType::Catch);
+            return None;
+        }
+        Some(expression::Expression::TryExpression(
+            expression::TryExpression {
+                token,
+                block,
+                catch,
+                finally,
+            },
+        ))
+    }
+
+    fn parse_function_literal(&mut self) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        if !self.expect_peek(&token::TokenType::Lparne) {
+            return None;
+        }
+        let parameters = self.parse_function_parameters()?;
+        let return_type = self.parse_optional_annotation(&token::TokenType::Arrow)?;
+        if !self.expect_peek(&token::TokenType::Lbrace) {
+            return None;
+        }
+        let body = self.parse_block_statement();
+        Some(expression::Expression::FunctionLiteral(
+            expression::FunctionLiteral {
+                token,
+                parameters,
+                return_type,
+                body,
+            },
+        ))
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<Vec<expression::Parameter>> {
+        let mut parameters = Vec::new();
+        if self.peek_token_is(&token::TokenType::Rparne) {
+            self.next_token();
+            return Some(parameters);
+        }
+        loop {
+            if !self.expect_peek(&token::TokenType::Ident) {
+                return None;
+            }
+            let name = expression::Identifier::new(self.cur_token());
+            let annotation = self.parse_optional_annotation(&token::TokenType::Colon)?;
+            parameters.push(expression::Parameter { name, annotation });
+            if !self.peek_token_is(&token::TokenType::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+        if !self.expect_peek(&token::TokenType::Rparne) {
+            return None;
+        }
+        Some(parameters)
+    }
+
+    // 次の token が separator なら、その後ろの型注釈を読む
+    // 失敗したときは None、注釈がなければ Some(None) を返す
+    fn parse_optional_annotation(
+        &mut self,
+        separator: &token::TokenType,
+    ) -> Option<Option<annotation::TypeAnnotation>> {
+        if !self.peek_token_is(separator) {
+            return Some(None);
         }
+        self.next_token();
+        self.next_token();
+        self.parse_type_annotation().map(Some)
+    }
+
+    // cur_token から型注釈を読む
+    fn parse_type_annotation(&mut self) -> Option<annotation::TypeAnnotation> {
+        let token = self.cur_token();
+        match token.typ {
+            token::TokenType::Ident
+                if annotation::NAMES
+                    .iter()
+                    .any(|name| name.as_bytes() == &token.literal[..]) =>
+            {
+                Some(annotation::TypeAnnotation::Named(token))
+            }
+            token::TokenType::Lbracket => {
+                self.next_token();
+                let element = self.parse_type_annotation()?;
+                if !self.expect_peek(&token::TokenType::Rbracket) {
+                    return None;
+                }
+                Some(annotation::TypeAnnotation::Array {
+                    token,
+                    element: Box::new(element),
+                    rbracket: self.cur_token(),
+                })
+            }
+            token::TokenType::Lbrace => {
+                self.next_token();
+                let key = self.parse_type_annotation()?;
+                if !self.expect_peek(&token::TokenType::Colon) {
+                    return None;
+                }
+                self.next_token();
+                let value = self.parse_type_annotation()?;
+                if !self.expect_peek(&token::TokenType::Rbrace) {
+                    return None;
+                }
+                Some(annotation::TypeAnnotation::Hash {
+                    token,
+                    key: Box::new(key),
+                    value: Box::new(value),
+                    rbrace: self.cur_token(),
+                })
+            }
+            token::TokenType::Function => {
+                if !self.expect_peek(&token::TokenType::Lparne) {
+                    return None;
+                }
+                let mut parameters = Vec::new();
+                if self.peek_token_is(&token::TokenType::Rparne) {
+                    self.next_token();
+                } else {
+                    loop {
+                        self.next_token();
+                        parameters.push(self.parse_type_annotation()?);
+                        if !self.peek_token_is(&token::TokenType::Comma) {
+                            break;
+                        }
+                        self.next_token();
+                    }
+                    if !self.expect_peek(&token::TokenType::Rparne) {
+                        return None;
+                    }
+                }
+                if !self.expect_peek(&token::TokenType::Arrow) {
+                    return None;
+                }
+                self.next_token();
+                let return_type = self.parse_type_annotation()?;
+                Some(annotation::TypeAnnotation::Function {
+                    token,
+                    parameters,
+                    return_type: Box::new(return_type),
+                })
+            }
+            _ => {
+                let literal = String::from_utf8_lossy(&token.literal[..]).into_owned();
+                self.error(ParseError::InvalidTypeAnnotation { literal }, token.span());
+                None
+            }
+        }
+    }
+
+    fn parse_call_expression(
+        &mut self,
+        function: expression::Expression,
+    ) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        let arguments = self.parse_expression_list(&token::TokenType::Rparne)?;
+        Some(expression::Expression::CallExpression(
+            expression::CallExpression {
+                token,
+                function: Box::new(function),
+                arguments,
+                rparen: self.cur_token(),
+            },
+        ))
+    }
+
+    fn parse_index_expression(
+        &mut self,
+        left: expression::Expression,
+    ) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        self.next_token();
+        let index = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(&token::TokenType::Rbracket) {
+            return None;
+        }
+        Some(expression::Expression::IndexExpression(
+            expression::IndexExpression {
+                token,
+                left: Box::new(left),
+                index: Box::new(index),
+                rbracket: self.cur_token(),
+            },
+        ))
+    }
+
+    fn parse_member_expression(
+        &mut self,
+        object: expression::Expression,
+    ) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        if !self.expect_peek(&token::TokenType::Ident) {
+            return None;
+        }
+        Some(expression::Expression::MemberExpression(
+            expression::MemberExpression {
+                token,
+                object: Box::new(object),
+                member: expression::Identifier::new(self.cur_token()),
+            },
+        ))
+    }
+
+    // `,` 区切りで end まで読む。呼び出し時の cur_token は開き括弧
+    fn parse_expression_list(
+        &mut self,
+        end: &token::TokenType,
+    ) -> Option<Vec<expression::Expression>> {
+        let mut list = Vec::new();
+        if self.peek_token_is(end) {
+            self.next_token();
+            return Some(list);
+        }
+        self.next_token();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+        while self.peek_token_is(&token::TokenType::Comma) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(Precedence::Lowest)?);
+        }
+        if !self.expect_peek(end) {
+            return None;
+        }
+        Some(list)
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<expression::Expression> {
+        let token = self.cur_token();
+        let mut pairs = Vec::new();
+        while !self.peek_token_is(&token::TokenType::Rbrace) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+            if !self.expect_peek(&token::TokenType::Colon) {
+                return None;
+            }
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+            if !self.peek_token_is(&token::TokenType::Rbrace)
+                && !self.expect_peek(&token::TokenType::Comma)
+            {
+                return None;
+            }
+        }
+        self.next_token();
+        Some(expression::Expression::HashLiteral(
+            expression::HashLiteral {
+                token,
+                pairs,
+                rbrace: self.cur_token(),
+            },
+        ))
     }
 
     pub fn parse_program(&mut self) -> ast::Program {
@@ -138,6 +785,44 @@ impl Parser {
     }
 }
 
+// input をすべて parse する。エラーがあればまとめて返す
+pub fn parse(input: &str) -> Result<ast::Program, Vec<ParseError>> {
+    parse_with_spans(input).map_err(|errors| errors.into_iter().map(|(e, _)| e).collect())
+}
+
+// parse と同じだが、エラーの位置も返す
+pub fn parse_with_spans(input: &str) -> Result<ast::Program, Vec<(ParseError, token::Span)>> {
+    let mut p = Parser::new(lexer::Lexer::new(input.to_string()));
+    let program = p.parse_program();
+    let errors = p.errors_with_spans();
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+// 型注釈だけを parse する（`[int]` など）
+pub fn parse_type_annotation(input: &str) -> Result<annotation::TypeAnnotation, Vec<ParseError>> {
+    let mut p = Parser::new(lexer::Lexer::new(input.to_string()));
+    let annotation = p.parse_type_annotation();
+    if annotation.is_some() && !p.peek_token_is(&token::TokenType::Eof) {
+        let got = p.peek_token.as_ref().unwrap().typ.clone();
+        let span = p.peek_token.as_ref().unwrap().span();
+        p.error(
+            ParseError::UnexpectedPeekToken {
+                want: token::TokenType::Eof,
+                got,
+            },
+            span,
+        );
+    }
+    match annotation {
+        Some(annotation) if p.errors.is_empty() => Ok(annotation),
+        _ => Err(p.errors()),
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -165,7 +850,7 @@ mod test {
         assert_eq!(program.statements.len(), 3);
 
         {
-            let s = program.statements.get(0).unwrap();
+            let s = program.statements.first().unwrap();
             assert_let_statement(s, "x")
         }
         {
@@ -194,6 +879,45 @@ mod test {
         check_parser_error(&mut p);
     }
 
+    #[test]
+    fn test_lex_error() {
+        let input = r"
+        let x = 12ab;
+        let y = @;
+        "
+        .to_string();
+
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let _ = p.parse_program();
+        let errors: Vec<String> = p.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "2:17: malformed number \"12ab\"".to_string(),
+                "3:17: unexpected character '@'".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_error_only() {
+        // 字句エラーの後ろに構文エラーを重ねない
+        for (input, code) in [
+            ("let s = \"a\\u\";", "E0003"),
+            ("let s = \"abc", "E0002"),
+            ("1 @ 2;", "E0001"),
+            ("f(1, 2x);", "E0004"),
+            ("let c = 1; /* open", "E0005"),
+        ] {
+            let codes: Vec<&str> = match super::parse(input) {
+                Err(errors) => errors.iter().map(|e| e.code()).collect(),
+                Ok(_) => Vec::new(),
+            };
+            assert_eq!(codes, vec![code], "{}", input);
+        }
+    }
+
     #[test]
     fn test_return_statement() {
         let input = r"
@@ -229,12 +953,13 @@ mod test {
                         token::TokenType::Ident,
                         Bytes::from("myVar"),
                     )),
-                    value: Some(ast::expression::Expression::Identifier(
+                    annotation: None,
+                    value: ast::expression::Expression::Identifier(
                         ast::expression::Identifier::new(token::Token::new(
                             token::TokenType::Ident,
                             Bytes::from("anotherVar"),
                         )),
-                    )),
+                    ),
                 },
             )],
         };
@@ -242,6 +967,279 @@ mod test {
         assert_eq!(program.string(), "let myVar = anotherVar;".to_string())
     }
 
+    #[test]
+    fn test_operator_precedence() {
+        for (input, expected) in [
+            ("-a * b", "((-a) * b);"),
+            ("!-a", "(!(-a));"),
+            ("a + b + c", "((a + b) + c);"),
+            ("a + b - c", "((a + b) - c);"),
+            ("a * b * c", "((a * b) * c);"),
+            ("a * b / c", "((a * b) / c);"),
+            ("a + b / c", "(a + (b / c));"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f);"),
+            ("3 + 4; -5 * 5", "(3 + 4);\n((-5) * 5);"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4));"),
+            ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4));"),
+            (
+                "3 + 4 * 5 == 3 * 1 + 4 * 5",
+                "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)));",
+            ),
+            ("true", "true;"),
+            ("3 > 5 == false", "((3 > 5) == false);"),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4);"),
+            ("(5 + 5) * 2", "((5 + 5) * 2);"),
+            ("-(5 + 5)", "(-(5 + 5));"),
+            ("!(true == true)", "(!(true == true));"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d);"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)));",
+            ),
+            (
+                "add(a + b + c * d / f + g)",
+                "add((((a + b) + ((c * d) / f)) + g));",
+            ),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d);",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])));",
+            ),
+            ("-a.b(1)[0].c", "(-(((a.b)(1)[0]).c));"),
+            (
+                "import \"lib/a.mk\" as a; export let x = a.y;",
+                "import \"lib/a.mk\" as a;\nexport let x = (a.y);",
+            ),
+        ] {
+            let program = parse(input);
+            assert_eq!(program.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_literal_expressions() {
+        for (input, expected) in [
+            ("foobar;", "foobar;"),
+            ("5;", "5;"),
+            (r#""hello\tworld\"";"#, r#""hello\tworld\"";"#),
+            ("[1, 2 * 2, 3 + 3]", "[1, (2 * 2), (3 + 3)];"),
+            ("[]", "[];"),
+            (r#"{"one": 1, "two": 2}"#, r#"{"one": 1, "two": 2};"#),
+            ("{}", "{};"),
+            (
+                r#"{"one": 0 + 1, true: 15 / 5}"#,
+                r#"{"one": (0 + 1), true: (15 / 5)};"#,
+            ),
+            ("if (x < y) { x }", "if ((x < y)) { x; };"),
+            (
+                "if (x < y) { x } else { y }",
+                "if ((x < y)) { x; } else { y; };",
+            ),
+            ("fn(x, y) { x + y; }", "fn(x, y) { (x + y); };"),
+            ("fn() {}", "fn() { };"),
+            ("fn(x) { return x; }(5)", "fn(x) { return x; }(5);"),
+            ("let x = 5; return x", "let x = 5;\nreturn x;"),
+        ] {
+            let program = parse(input);
+            assert_eq!(program.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let describe = |input: &str| -> Vec<(String, String)> {
+            super::parse_with_spans(input)
+                .unwrap_err()
+                .into_iter()
+                .map(|(e, span)| (e.to_string(), span.to_string()))
+                .take(2)
+                .collect()
+        };
+        assert_eq!(
+            describe("let x = ;\nlet 5 = 1;"),
+            vec![
+                (
+                    "no prefix parse function for ; found".to_string(),
+                    "1:9-1:10".to_string()
+                ),
+                (
+                    "expected next token to be Ident, got Int instead".to_string(),
+                    "2:5-2:6".to_string()
+                ),
+            ]
+        );
+        // 閉じていないブロックは `{` の位置
+        assert_eq!(
+            describe("let f = fn() {\n  if (x) { 1 }\n"),
+            vec![("unterminated block".to_string(), "1:14-1:15".to_string())]
+        );
+        // 字句エラーはエラーの位置から token の終わりまで
+        assert_eq!(
+            describe("\"a\\q\"")[0],
+            (
+                "1:3: invalid escape sequence \"\\\\q\"".to_string(),
+                "1:3-1:6".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let codes = |input: &str| -> Vec<&str> {
+            super::parse(input)
+                .unwrap_err()
+                .iter()
+                .map(|e| e.code())
+                .collect()
+        };
+        assert_eq!(codes("let = 5;"), vec!["E0101", "E0102"]);
+        assert_eq!(codes("99999999999999999999"), vec!["E0103"]);
+        assert_eq!(codes("fn() { 1"), vec!["E0106"]);
+        // 字句エラーは LexError のコード
+        assert_eq!(codes("\"a")[0], "E0002");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (input, expected) in [
+            ("let = 5;", "expected next token to be Ident, got = instead"),
+            ("+ 1;", "no prefix parse function for + found"),
+            (
+                "99999999999999999999",
+                "could not parse \"99999999999999999999\" as integer",
+            ),
+            ("{1 2}", "expected next token to be :, got Int instead"),
+            ("let x: integer = 1;", "\"integer\" is not a type"),
+            (
+                "fn(a: [int) {}",
+                "expected next token to be ], got ) instead",
+            ),
+            ("fn() -> {}", "\"}\" is not a type"),
+            (
+                "fn() { import \"a.mk\" as a; }",
+                "import is only allowed at the top level",
+            ),
+            (
+                "if (x) { export let y = 1; }",
+                "export is only allowed at the top level",
+            ),
+            (
+                "export 1;",
+                "expected next token to be Let, got Int instead",
+            ),
+            ("a.1", "expected next token to be Ident, got Int instead"),
+            (
+                "try { 1 };",
+                "expected next token to be Catch, got ; instead",
+            ),
+            (
+                "try { 1 } catch e { 2 }",
+                "expected next token to be (, got Ident instead",
+            ),
+            ("if (x) { 1", "unterminated block"),
+        ] {
+            let l = Lexer::new(input.to_string());
+            let mut p = Parser::new(l);
+            let _ = p.parse_program();
+            let errors = p.errors();
+            assert_eq!(errors.first().unwrap().to_string(), expected, "{}", input);
+        }
+    }
+
+    // parse → 出力 → parse で同じ木になるか
+    fn assert_round_trip(input: &str) {
+        let program = parse(input);
+        let printed = program.to_string();
+        let reparsed = parse(&printed);
+        assert_eq!(program, reparsed, "{}\n{}", input, printed);
+        assert_eq!(printed, reparsed.to_string());
+    }
+
+    #[test]
+    fn test_round_trip_corpus() {
+        for input in [
+            "let five = 5; let ten = 10;",
+            "let add = fn(x, y) { x + y; }; let result = add(five, ten);",
+            "!-5 * 3 / (2 - -1) < 10 > 5 == true != false;",
+            r#"let s = "a \"quoted\"\n\tstring\\"; s;"#,
+            "if (5 < 10) { return true; } else { return false; }",
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10);",
+            "let map = fn(arr, f) { let iter = fn(arr, acc) { if (len(arr) == 0) { acc } else { iter(rest(arr), push(acc, f(first(arr)))) } }; iter(arr, []); };",
+            r#"let h = {"one": 1, 2: [1, 2, 3], true: {"nested": fn() { 1 }}}; h["one"]; h[2][0];"#,
+            "fn(x) { x }(1)(2)[3];",
+            "let newAdder = fn(x) { fn(y) { x + y } }; newAdder(2)(3);",
+            "{}; []; {1: 2}; [[]];",
+            "if (if (a) { b } else { c }) { d }",
+            "-(-(-1)); !!true;",
+            "let x: int = 5; let f = fn(a: str, b: int, c) -> bool { true };",
+            "let v = try { f(); } catch (e) { throw e; } finally { g(); }; try { 1 } finally { 2 }",
+            "let g: fn([int], {str: any}) -> fn() -> int = fn(xs: [int], h) { fn() -> int { 1 } };",
+        ] {
+            assert_round_trip(input);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_generated() {
+        // 乱数は再現できるように線形合同法で作る
+        let mut seed: u64 = 7;
+        let mut next = |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        fn gen(next: &mut dyn FnMut(u64) -> u64, depth: u32) -> String {
+            let leaf = depth == 0 || next(3) == 0;
+            if leaf {
+                return match next(5) {
+                    0 => ["x", "y", "z"][next(3) as usize].to_string(),
+                    1 => format!("{}", next(1000)),
+                    2 => format!("\"s{}\\n\"", next(10)),
+                    3 => "true".to_string(),
+                    _ => "false".to_string(),
+                };
+            }
+            let ops = ["+", "-", "*", "/", "<", ">", "==", "!="];
+            match next(9) {
+                0 => format!("-{}", gen(next, depth - 1)),
+                1 => format!("!{}", gen(next, depth - 1)),
+                2 => format!("({})", gen(next, depth - 1)),
+                3 => format!(
+                    "if ({}) {{ {} }} else {{ {} }}",
+                    gen(next, depth - 1),
+                    gen(next, depth - 1),
+                    gen(next, depth - 1)
+                ),
+                4 => format!("fn(a, b) {{ return {}; }}", gen(next, depth - 1)),
+                5 => format!("f({}, {})", gen(next, depth - 1), gen(next, depth - 1)),
+                6 => format!("[{}, {}]", gen(next, depth - 1), gen(next, depth - 1)),
+                7 => format!("{}[{}]", gen(next, depth - 1), gen(next, depth - 1)),
+                _ => format!(
+                    "{} {} {}",
+                    gen(next, depth - 1),
+                    ops[next(ops.len() as u64) as usize],
+                    gen(next, depth - 1)
+                ),
+            }
+        }
+        for _ in 0..300 {
+            let input = format!("let v = {}; {};", gen(&mut next, 4), gen(&mut next, 4));
+            assert_round_trip(&input);
+        }
+    }
+
+    fn parse(input: &str) -> ast::Program {
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_error(&mut p);
+        program
+    }
+
     fn assert_let_statement(s: &ast::statement::Statement, expected_name: &str) {
         assert_eq!(s.token_literal(), "let");
 
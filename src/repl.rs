@@ -1,30 +1,422 @@
-use std::io;
-use std::io::BufRead;
-use std::io::Write;
+use std::cell::RefCell;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
 
-use crate::{lexer, token};
+use rustyline::{error::ReadlineError, history::DefaultHistory};
+
+use crate::{lexer, object, token};
+
+pub mod session;
+
+pub use session::Session;
+
+pub const PROMPT: &str = ">> ";
+// 括弧が閉じるまで続きを読むときのプロンプト
+pub const CONTINUATION_PROMPT: &str = ".. ";
+
+// 履歴を保存するファイル。HOME がなければ保存しない
+pub fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".interpreter_rs_history"))
+}
+
+pub enum Line {
+    Line(String),
+    // Ctrl-C。入力中のものを捨てる
+    Interrupted,
+    Eof,
+}
+
+// 1 行ずつ読むところを、端末の行編集と普通の入力とで差し替えられるようにする
+// out は結果を書く先。自分でプロンプトを表示しないものはそこに書く
+pub trait LineReader {
+    fn read_line(&mut self, prompt: &str, out: &mut dyn Write) -> io::Result<Line>;
+    fn add_history(&mut self, _entry: &str) {}
+}
+
+// 端末ではない入力から読む
+pub struct PlainReader<R>(pub R);
+
+impl<R: BufRead> LineReader for PlainReader<R> {
+    fn read_line(&mut self, prompt: &str, out: &mut dyn Write) -> io::Result<Line> {
+        out.write_all(prompt.as_bytes())?;
+        out.flush()?;
+        let mut buf = String::new();
+        if self.0.read_line(&mut buf)? == 0 {
+            return Ok(Line::Eof);
+        }
+        Ok(Line::Line(
+            buf.trim_end_matches(&['\r', '\n'][..]).to_string(),
+        ))
+    }
+}
+
+// REPL と評価中の組み込み関数とで同じ入出力を使う
+// 評価中の puts はその場で REPL の出力に書かれる
+pub(crate) struct Shared<T>(pub(crate) Rc<RefCell<T>>);
+
+impl<T> Shared<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Self(Rc::new(RefCell::new(inner)))
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<W: Write> Write for Shared<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+// 共有した入力から 1 行ずつ読む。REPL と read_line とで入力を取り合わないよう、1 行より先は読まない
+pub(crate) struct SharedLines<R> {
+    inner: Shared<R>,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl<R> SharedLines<R> {
+    pub(crate) fn new(inner: Shared<R>) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Read for SharedLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for SharedLines<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            self.inner
+                .0
+                .borrow_mut()
+                .read_until(b'\n', &mut self.line)?;
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+// 矢印キーでの履歴の移動や Tab での補完ができる端末用の行エディタ
+pub struct Editor {
+    editor: rustyline::Editor<Helper, DefaultHistory>,
+    history: Option<PathBuf>,
+}
+
+impl Editor {
+    // env は補完に使う名前を引く環境
+    pub fn new(history: Option<PathBuf>, env: object::Env) -> rustyline::Result<Self> {
+        let mut editor = rustyline::Editor::new()?;
+        editor.set_helper(Some(Helper { env }));
+        if let Some(path) = &history {
+            // 初回はファイルがないので失敗してよい
+            let _ = editor.load_history(path);
+        }
+        Ok(Self { editor, history })
+    }
+}
+
+impl LineReader for Editor {
+    fn read_line(&mut self, prompt: &str, _out: &mut dyn Write) -> io::Result<Line> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(Line::Line(line)),
+            Err(ReadlineError::Interrupted) => Ok(Line::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Line::Eof),
+            Err(ReadlineError::Io(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e.to_string())),
+        }
+    }
+
+    fn add_history(&mut self, entry: &str) {
+        let _ = self.editor.add_history_entry(entry);
+        if let Some(path) = &self.history {
+            let _ = self.editor.save_history(path);
+        }
+    }
+}
+
+// 補完だけを行う。ヒントや色づけ、入力の検査はしない
+pub struct Helper {
+    env: object::Env,
+}
+
+impl rustyline::completion::Completer for Helper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let completions = session::complete(line, pos, &self.env);
+        Ok((
+            completions.start,
+            completions
+                .candidates
+                .into_iter()
+                .map(|c| c.label)
+                .collect(),
+        ))
+    }
+}
+
+impl rustyline::hint::Hinter for Helper {
+    type Hint = String;
+}
+
+impl rustyline::highlight::Highlighter for Helper {}
+
+impl rustyline::validate::Validator for Helper {}
+
+impl rustyline::Helper for Helper {}
+
+// 括弧が閉じていないか、文字列やコメントの途中で終わっていれば true
+pub fn is_incomplete(input: &str) -> bool {
+    let mut l = lexer::Lexer::new(input.to_string());
+    let mut depth = 0;
+    for t in &mut l {
+        match t.typ {
+            token::TokenType::Lbrace | token::TokenType::Lparne | token::TokenType::Lbracket => {
+                depth += 1
+            }
+            token::TokenType::Rbrace | token::TokenType::Rparne | token::TokenType::Rbracket => {
+                depth -= 1
+            }
+            _ => {}
+        }
+    }
+    depth > 0 || l.state().mode != lexer::LexMode::Normal
+}
+
+// 評価中の read_line や puts も reader と writer を使う
 pub fn start<R: io::Read, W: io::Write>(reader: R, writer: W) {
-    let mut reader = io::BufReader::new(reader);
-    let mut writer = io::BufWriter::new(writer);
+    let reader = Shared::new(io::BufReader::new(reader));
+    let mut writer = Shared::new(io::BufWriter::new(writer));
+    let mut session = Session::with_context(
+        session::context()
+            .with_stdin(SharedLines::new(reader.clone()))
+            .with_stdout(writer.clone())
+            .with_stderr(writer.clone()),
+    );
+    let mut lines = PlainReader(SharedLines::new(reader));
+    if let Err(e) = run_session(&mut session, &mut lines, &mut writer) {
+        eprintln!("error={}", e);
+    }
+}
+
+// 端末で行エディタを使って起動する。評価中の read_line は端末から直接読む
+pub fn start_interactive<W: io::Write>(writer: W) {
+    let mut writer = Shared::new(io::BufWriter::new(writer));
+    let mut session = Session::with_context(
+        session::context()
+            .with_stdout(writer.clone())
+            .with_stderr(writer.clone()),
+    );
+    let mut editor = match Editor::new(history_path(), session.env().clone()) {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("error={}", e);
+            return;
+        }
+    };
+    if let Err(e) = run_session(&mut session, &mut editor, &mut writer) {
+        eprintln!("error={}", e);
+    }
+}
+
+// 入力がそろうまで行を読み、そろったらまとめて評価する
+pub fn run<L: LineReader, W: io::Write>(lines: &mut L, writer: &mut W) -> io::Result<()> {
+    run_session(&mut Session::new(), lines, writer)
+}
+
+pub fn run_session<L: LineReader, W: io::Write>(
+    session: &mut Session<'_>,
+    lines: &mut L,
+    writer: &mut W,
+) -> io::Result<()> {
+    let mut input = String::new();
     loop {
-        let mut buf = String::new();
-        let result = reader.read_line(&mut buf);
-        match result {
-            Ok(_) => {
-                let mut l = lexer::Lexer::new(buf);
-                loop {
-                    let next = l.next_token();
-                    if next.typ == token::TokenType::Eof {
-                        break;
-                    }
-                    let _ = writer.write(format!("{:?}\n", next).as_bytes());
-                    let _ = writer.flush();
-                }
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        match lines.read_line(prompt, writer)? {
+            Line::Line(line) => {
+                input.push_str(&line);
+                input.push('\n');
             }
-            Err(e) => {
-                println!("error={}", e);
-                break;
+            Line::Interrupted => {
+                input.clear();
+                continue;
             }
+            Line::Eof => return Ok(()),
+        }
+        if is_incomplete(&input) {
+            continue;
+        }
+        let entry = std::mem::take(&mut input);
+        if entry.trim().is_empty() {
+            continue;
+        }
+        lines.add_history(entry.trim_end());
+        session.execute(&entry, writer)?;
+        writer.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use super::{is_incomplete, run, Line, LineReader, Shared};
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(!is_incomplete("let x = 1;"));
+        assert!(is_incomplete("let f = fn(x) {"));
+        assert!(is_incomplete("f(1,\n[2"));
+        assert!(!is_incomplete("let f = fn(x) {\n x\n};"));
+        assert!(is_incomplete("\"abc"));
+        assert!(is_incomplete("/* comment"));
+        // 閉じ括弧が多いときは続きを待たずにエラーにさせる
+        assert!(!is_incomplete("}"));
+    }
+
+    // 決まった行を返し、読んだときのプロンプトと履歴を記録する
+    struct Script {
+        lines: Vec<Line>,
+        prompts: Vec<String>,
+        history: Vec<String>,
+    }
+
+    impl LineReader for Script {
+        fn read_line(&mut self, prompt: &str, _out: &mut dyn io::Write) -> io::Result<Line> {
+            self.prompts.push(prompt.to_string());
+            Ok(if self.lines.is_empty() {
+                Line::Eof
+            } else {
+                self.lines.remove(0)
+            })
+        }
+        fn add_history(&mut self, entry: &str) {
+            self.history.push(entry.to_string());
+        }
+    }
+
+    #[test]
+    fn test_multi_line_input() {
+        let mut script = Script {
+            lines: vec![
+                Line::Line("fn(x) {".to_string()),
+                Line::Line("x".to_string()),
+                Line::Line("}".to_string()),
+                Line::Line("let a = (".to_string()),
+                Line::Interrupted,
+                Line::Line("".to_string()),
+                Line::Line("1".to_string()),
+            ],
+            prompts: Vec::new(),
+            history: Vec::new(),
         };
+        let mut out = Vec::new();
+        run(&mut script, &mut out).unwrap();
+
+        assert_eq!(
+            script.prompts,
+            vec![">> ", ".. ", ".. ", ">> ", ".. ", ">> ", ">> ", ">> "]
+        );
+        assert_eq!(script.history, vec!["fn(x) {\nx\n}", "1"]);
+        assert_eq!(String::from_utf8(out).unwrap(), "fn(x) { x; }\n1\n");
+    }
+
+    #[test]
+    fn test_plain_reader() {
+        let mut out = Vec::new();
+        super::start("let x = 1;\nx + 2\n".as_bytes(), &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(), ">> >> 3\n>> ");
+    }
+
+    #[test]
+    fn test_shared_io() {
+        // 評価中の read_line は REPL と同じ入力から次の行を読み、print はその場で書かれる
+        let mut out = Vec::new();
+        super::start(
+            "let a = read_line();\nhello\nprint(\"name? \"); read_line()\nmonkey\na\n".as_bytes(),
+            &mut out,
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ">> >> name? monkey\n>> hello\n>> "
+        );
+
+        // eputs も REPL の出力に順番どおりに書かれる
+        let mut out = Vec::new();
+        super::start(
+            "puts(1); eputs(2); puts(3);
+"
+            .as_bytes(),
+            &mut out,
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), ">> 1\n2\n3\n>> ");
+    }
+
+    #[test]
+    fn test_flush_before_input() {
+        // 入力を待つときには、それまでの出力が書き出されている
+        let output = Shared::new(Vec::new());
+        let mut input = Watch {
+            lines: vec!["print(\"name? \"); read_line()\n", "monkey\n"],
+            output: output.clone(),
+            seen: Vec::new(),
+        };
+        super::start(&mut input, output.clone());
+        assert_eq!(input.seen, vec![">> ", ">> name? "]);
+    }
+
+    // 1 行ずつ返し、読まれたときに出力に書かれていたものを記録する
+    struct Watch {
+        lines: Vec<&'static str>,
+        output: Shared<Vec<u8>>,
+        seen: Vec<String>,
+    }
+
+    impl io::Read for Watch {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.lines.is_empty() {
+                return Ok(0);
+            }
+            self.seen
+                .push(String::from_utf8(self.output.0.borrow().clone()).unwrap());
+            let line = self.lines.remove(0).as_bytes();
+            buf[..line.len()].copy_from_slice(line);
+            Ok(line.len())
+        }
     }
 }
@@ -0,0 +1,314 @@
+// REPL の状態。入力を評価し、`:` で始まる入力はメタコマンドとして扱う
+use std::{
+    io::{self, Write},
+    time::Instant,
+};
+
+use crate::{ast, completion, evaluator, lexer, object, parser};
+
+const HELP: &str = "\
+:tokens <src>  print the tokens of <src>
+:ast <src>     print the syntax tree of <src>
+:env           list the bindings in the session
+:load <file>   evaluate a file in the session
+:reset         clear all bindings
+:time <expr>   evaluate <expr> and print how long it took
+:help          show this message";
+
+const COMMANDS: [&str; 7] = ["tokens", "ast", "env", "load", "reset", "time", "help"];
+
+pub struct Session<'io> {
+    env: object::Env,
+    // :load したファイルからの import もここに残る
+    loader: evaluator::Loader<'io>,
+}
+
+impl Default for Session<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// REPL の評価で使う既定の context。import や read_file ではカレントディレクトリの下を読める
+pub(crate) fn context<'io>() -> evaluator::Context<'io> {
+    evaluator::Context::new(
+        evaluator::Capabilities::default().allow_read("."),
+        Vec::new(),
+    )
+}
+
+impl Session<'static> {
+    // 評価中の入出力はプロセスの標準入出力を使う
+    pub fn new() -> Self {
+        Self::with_context(context())
+    }
+}
+
+impl<'io> Session<'io> {
+    pub fn with_context(context: evaluator::Context<'io>) -> Self {
+        Self {
+            env: object::Environment::new(),
+            loader: evaluator::Loader::with_context(context),
+        }
+    }
+
+    pub fn env(&self) -> &object::Env {
+        &self.env
+    }
+
+    pub fn execute(&mut self, input: &str, out: &mut dyn Write) -> io::Result<()> {
+        let input = input.trim();
+        if let Some(command) = input.strip_prefix(':') {
+            let (name, argument) = match command.split_once(char::is_whitespace) {
+                Some((name, argument)) => (name, argument.trim()),
+                None => (command, ""),
+            };
+            return self.command(name, argument, out);
+        }
+        if let Some(program) = parse(input, out)? {
+            self.eval(&program, out)?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, name: &str, argument: &str, out: &mut dyn Write) -> io::Result<()> {
+        match name {
+            "tokens" => {
+                for token in lexer::Lexer::new(argument.to_string()) {
+                    writeln!(out, "{:?}", token)?;
+                }
+            }
+            "ast" => {
+                if let Some(program) = parse(argument, out)? {
+                    writeln!(out, "{}", ast::sexp::to_sexp(&program))?;
+                }
+            }
+            "env" => {
+                for (name, value) in self.env.borrow().bindings() {
+                    writeln!(out, "{} = {}", name, value)?;
+                }
+            }
+            "load" => match std::fs::read_to_string(argument) {
+                Ok(source) => {
+                    if let Some(program) = parse(&source, out)? {
+                        self.eval_file(&program, Some(std::path::Path::new(argument)), out)?;
+                    }
+                }
+                Err(e) => writeln!(out, "error: cannot read {}: {}", argument, e)?,
+            },
+            // 行エディタの補完も同じ環境を見ているので、作り直さずに空にする
+            "reset" => {
+                self.env.borrow_mut().clear();
+                self.loader.clear();
+            }
+            "time" => {
+                if let Some(program) = parse(argument, out)? {
+                    let start = Instant::now();
+                    self.eval(&program, out)?;
+                    writeln!(out, "time: {:?}", start.elapsed())?;
+                }
+            }
+            "help" => writeln!(out, "{}", HELP)?,
+            _ => writeln!(out, "unknown command :{} (see :help)", name)?,
+        }
+        Ok(())
+    }
+
+    // null でなければ結果を出力する
+    fn eval(&mut self, program: &ast::Program, out: &mut dyn Write) -> io::Result<()> {
+        self.eval_file(program, None, out)
+    }
+
+    // file があれば import をそのディレクトリから探す
+    fn eval_file(
+        &mut self,
+        program: &ast::Program,
+        file: Option<&std::path::Path>,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let result = self.loader.eval_program(program, &self.env, file);
+        match result {
+            Ok(object::Object::Null) => Ok(()),
+            Ok(object) => writeln!(out, "{}", object),
+            Err(e) => {
+                writeln!(out, "error[{}]: {}", e.code(), e)?;
+                // エラーになった位置と、呼び出し中だった関数を内側から順に出す
+                if let Some(span) = self.loader.span() {
+                    writeln!(out, "    at {}", span.start)?;
+                }
+                for line in evaluator::trace_lines(self.loader.trace()) {
+                    writeln!(out, "    {}", line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// 行頭の `:` に続く語はメタコマンド、それ以外はソースとして補完する
+pub fn complete(input: &str, cursor: usize, env: &object::Env) -> completion::Completions {
+    if let Some(command) = input.strip_prefix(':') {
+        if cursor >= 1 && cursor <= input.len() && !input[..cursor].contains(char::is_whitespace) {
+            let prefix = &command[..cursor - 1];
+            return completion::Completions {
+                start: 1,
+                candidates: COMMANDS
+                    .iter()
+                    .filter(|name| name.starts_with(prefix))
+                    .map(|name| completion::Candidate {
+                        label: name.to_string(),
+                        kind: completion::Kind::Command,
+                    })
+                    .collect(),
+            };
+        }
+    }
+    completion::complete(input, cursor, &*env.borrow())
+}
+
+fn parse(input: &str, out: &mut dyn Write) -> io::Result<Option<ast::Program>> {
+    match parser::parse(input) {
+        Ok(program) => Ok(Some(program)),
+        Err(errors) => {
+            for e in errors {
+                writeln!(out, "error[{}]: {}", e.code(), e)?;
+            }
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{complete, Session};
+    use crate::{evaluator, repl::Shared};
+
+    // 評価中の出力も REPL の出力と同じバッファに受け取る
+    struct Repl {
+        session: Session<'static>,
+        output: Shared<Vec<u8>>,
+    }
+
+    impl Repl {
+        fn new() -> Self {
+            Self::with_context(super::context())
+        }
+
+        fn with_context(context: evaluator::Context<'static>) -> Self {
+            let output = Shared::new(Vec::new());
+            let context = context
+                .with_stdout(output.clone())
+                .with_stderr(output.clone());
+            Self {
+                session: Session::with_context(context),
+                output,
+            }
+        }
+    }
+
+    fn execute(repl: &mut Repl, input: &str) -> String {
+        repl.session
+            .execute(input, &mut repl.output.clone())
+            .unwrap();
+        String::from_utf8(std::mem::take(&mut *repl.output.0.borrow_mut())).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut s = Repl::new();
+        assert_eq!(execute(&mut s, "let x = 2;"), "");
+        assert_eq!(execute(&mut s, "x * 3"), "6\n");
+        assert_eq!(
+            execute(&mut s, "y"),
+            "error[E0204]: identifier not found: y\n    at 1:1\n"
+        );
+        assert_eq!(execute(&mut s, "puts(x); eputs(\"e\"); x"), "2\ne\n2\n");
+        assert_eq!(
+            execute(&mut s, "let f = fn(a) { a + true }; f(x)"),
+            "error[E0201]: type mismatch: INTEGER + BOOLEAN\n    at 1:17\n    in <anonymous> (let f) at 1:29\n"
+        );
+        // 前のエラーのトレースは残らない
+        assert_eq!(
+            execute(&mut s, "1 / 0"),
+            "error[E0209]: division by zero\n    at 1:1\n"
+        );
+        assert_eq!(
+            execute(&mut s, "[1, 2][x + 1]"),
+            "error[E0220]: index out of range: 3 for length 2\n    at 1:8\n"
+        );
+        assert_eq!(
+            execute(&mut s, "let = 1"),
+            "error[E0101]: expected next token to be Ident, got = instead\nerror[E0102]: no prefix parse function for = found\n"
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut s = Repl::new();
+        assert!(execute(&mut s, ":tokens let").starts_with("Token { typ: Let"));
+        assert_eq!(
+            execute(&mut s, ":ast 1 + x"),
+            "(program\n  (expr (infix + (int 1) (ident x))))\n"
+        );
+
+        execute(&mut s, "let b = [1, \"a\"]; let a = fn(x) { x };");
+        assert_eq!(
+            execute(&mut s, ":env"),
+            "a = fn(x) { x; }\nb = [1, \"a\"]\n"
+        );
+        execute(&mut s, ":reset");
+        assert_eq!(execute(&mut s, ":env"), "");
+
+        let out = execute(&mut s, ":time 1 + 1");
+        assert!(out.starts_with("2\ntime: "), "{}", out);
+
+        let dir = std::env::temp_dir().join(format!("interpreter-rs-repl-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lib.mk");
+        std::fs::write(
+            &path,
+            "import \"util.mk\" as util; let double = util.double;",
+        )
+        .unwrap();
+        std::fs::write(dir.join("util.mk"), "export let double = fn(n) { n * 2 };").unwrap();
+        // :load したファイルからの import も読む許可がいる
+        assert_eq!(
+            execute(&mut s, &format!(":load {}", path.display())),
+            format!(
+                "error[E0217]: permission denied: import needs read access to {}\n    at 1:1\n",
+                dir.join("util.mk").display()
+            )
+        );
+        let mut s = Repl::with_context(evaluator::Context::new(
+            evaluator::Capabilities::default().allow_read(&dir),
+            Vec::new(),
+        ));
+        assert_eq!(execute(&mut s, &format!(":load {}", path.display())), "");
+        assert_eq!(execute(&mut s, "double(21)"), "42\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(execute(&mut s, ":load missing.mk").starts_with("error: cannot read missing.mk"));
+
+        assert!(execute(&mut s, ":help").contains(":tokens <src>"));
+        // 未知のコマンドは評価しない
+        assert_eq!(
+            execute(&mut s, ":double 1"),
+            "unknown command :double (see :help)\n"
+        );
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut s = Repl::new();
+        execute(&mut s, "let total = 1;");
+        let labels = |input: &str, cursor: usize| -> Vec<String> {
+            complete(input, cursor, s.session.env())
+                .candidates
+                .into_iter()
+                .map(|c| c.label)
+                .collect()
+        };
+        assert_eq!(labels(":t", 2), vec!["tokens", "time"]);
+        assert_eq!(labels(":time to", 8), vec!["total"]);
+        assert_eq!(labels("t", 1), vec!["throw", "total", "true", "try"]);
+    }
+}
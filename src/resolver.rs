@@ -0,0 +1,520 @@
+// 識別子がどの宣言を指しているかを評価の前に解決する
+// 結果は識別子の NodeId をキーにした表で、評価器や lint、エディタ支援から引ける
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::{
+    ast::arena::{Arena, NodeId, NodeKind},
+    token,
+};
+
+// 宣言の種類。参照側から見たときは、外側の関数のローカル変数は Free になる
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Scope {
+    Global,
+    Local,
+    Parameter,
+    Free,
+    Builtin,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub struct DeclarationId(usize);
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Declaration {
+    pub name: String,
+    // Global, Local, Parameter, Builtin のいずれか
+    pub scope: Scope,
+    // 宣言した識別子。組み込み関数は None
+    pub node: Option<NodeId>,
+    pub span: Option<token::Span>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Reference {
+    pub declaration: DeclarationId,
+    pub scope: Scope,
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum ResolveError {
+    #[error("{}: undefined identifier {name:?}", .span.start)]
+    Undefined { name: String, span: token::Span },
+}
+
+impl ResolveError {
+    pub fn span(&self) -> token::Span {
+        match self {
+            ResolveError::Undefined { span, .. } => *span,
+        }
+    }
+
+    // 実行時の未定義の識別子と同じコードにする
+    pub fn code(&self) -> &'static str {
+        match self {
+            ResolveError::Undefined { .. } => "E0204",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Resolution {
+    declarations: Vec<Declaration>,
+    references: HashMap<NodeId, Reference>,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolution {
+    pub fn declaration(&self, id: DeclarationId) -> &Declaration {
+        &self.declarations[id.0]
+    }
+
+    pub fn declarations(&self) -> impl Iterator<Item = (DeclarationId, &Declaration)> {
+        self.declarations
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (DeclarationId(i), d))
+    }
+
+    // 識別子を使っている位置から引く
+    pub fn reference(&self, node: NodeId) -> Option<&Reference> {
+        self.references.get(&node)
+    }
+
+    // 使っている識別子でも宣言している識別子でも、その宣言を返す
+    pub fn definition(&self, node: NodeId) -> Option<DeclarationId> {
+        self.reference(node).map(|r| r.declaration).or_else(|| {
+            self.declarations()
+                .find(|(_, d)| d.node == Some(node))
+                .map(|(id, _)| id)
+        })
+    }
+
+    // 宣言を参照している識別子をソース順に返す
+    pub fn references(&self, id: DeclarationId) -> Vec<NodeId> {
+        let mut nodes: Vec<NodeId> = self
+            .references
+            .iter()
+            .filter(|(_, r)| r.declaration == id)
+            .map(|(node, _)| *node)
+            .collect();
+        nodes.sort();
+        nodes
+    }
+
+    // 一度でも参照された宣言
+    pub fn used(&self) -> HashSet<DeclarationId> {
+        self.references.values().map(|r| r.declaration).collect()
+    }
+
+    pub fn errors(&self) -> &[ResolveError] {
+        &self.errors
+    }
+}
+
+// builtins は組み込み関数の名前。同じ名前の宣言があればそちらを優先する
+pub fn resolve(arena: &Arena, builtins: &[&str]) -> Resolution {
+    let mut r = Resolver {
+        arena,
+        frames: vec![Vec::new()],
+        builtins: Vec::new(),
+        pending: Vec::new(),
+        resolution: Resolution::default(),
+    };
+    for name in builtins {
+        let id = r.add(name, Scope::Builtin, None);
+        r.builtins.push(id);
+    }
+    r.node(arena.root());
+    r.resolve_pending();
+    let mut resolution = r.resolution;
+    resolution.errors.sort_by_key(|e| e.span().start.offset);
+    resolution
+}
+
+struct Resolver<'a> {
+    arena: &'a Arena,
+    // 関数ごとの宣言。先頭はグローバル
+    frames: Vec<Vec<DeclarationId>>,
+    builtins: Vec<DeclarationId>,
+    // 関数の中で今の関数の外を指す識別子。囲んでいる関数を閉じるまで決めない
+    pending: Vec<Pending>,
+    resolution: Resolution,
+}
+
+struct Pending {
+    name: String,
+    node: NodeId,
+    // 見つけたときの frames の深さ
+    seen: usize,
+    // 見つけた時点で見えていた宣言と、その frames の深さ。組み込み関数なら深さは None
+    found: Option<(Reference, Option<usize>)>,
+}
+
+impl Resolver<'_> {
+    fn add(&mut self, name: &str, scope: Scope, node: Option<NodeId>) -> DeclarationId {
+        let id = DeclarationId(self.resolution.declarations.len());
+        self.resolution.declarations.push(Declaration {
+            name: name.to_string(),
+            scope,
+            node,
+            span: node.map(|node| self.arena[node].span),
+        });
+        id
+    }
+
+    fn name(&self, identifier: NodeId) -> &str {
+        match &self.arena[identifier].kind {
+            NodeKind::Identifier { name } => name,
+            kind => unreachable!("not an identifier: {:?}", kind),
+        }
+    }
+
+    fn declare(&mut self, identifier: NodeId, scope: Scope) {
+        let name = self.name(identifier).to_string();
+        let id = self.add(&name, scope, Some(identifier));
+        self.frames.last_mut().unwrap().push(id);
+    }
+
+    // 後から宣言したものを優先する
+    fn find(&self, ids: &[DeclarationId], name: &str) -> Option<DeclarationId> {
+        ids.iter()
+            .rev()
+            .copied()
+            .find(|id| self.resolution.declaration(*id).name == name)
+    }
+
+    // 宣言と、それを見つけた frames の深さを返す
+    fn lookup(&self, name: &str) -> Option<(Reference, Option<usize>)> {
+        let current = self.frames.len() - 1;
+        for (depth, frame) in self.frames.iter().enumerate().rev() {
+            if let Some(declaration) = self.find(frame, name) {
+                let scope = match self.resolution.declaration(declaration).scope {
+                    Scope::Global => Scope::Global,
+                    _ if depth < current => Scope::Free,
+                    scope => scope,
+                };
+                return Some((Reference { declaration, scope }, Some(depth)));
+            }
+        }
+        self.find(&self.builtins, name).map(|declaration| {
+            let reference = Reference {
+                declaration,
+                scope: Scope::Builtin,
+            };
+            (reference, None)
+        })
+    }
+
+    // 関数は呼ばれたときに囲んでいる関数やグローバルの変数を探すので、後から宣言した名前も見える
+    // 今の関数を閉じるときに、その中の関数が外を指している名前を今の関数の宣言すべてから探す
+    fn resolve_pending(&mut self) {
+        let depth = self.frames.len() - 1;
+        for pending in std::mem::take(&mut self.pending) {
+            match pending.found {
+                Some((reference, Some(found))) if found == depth => {
+                    self.resolution.references.insert(pending.node, reference);
+                    continue;
+                }
+                _ => {}
+            }
+            match self.find(&self.frames[depth], &pending.name) {
+                Some(declaration) if pending.seen > depth => {
+                    let scope = if depth == 0 {
+                        Scope::Global
+                    } else {
+                        Scope::Free
+                    };
+                    let reference = Reference { declaration, scope };
+                    self.resolution.references.insert(pending.node, reference);
+                }
+                _ if depth == 0 => match pending.found {
+                    Some((reference, _)) => {
+                        self.resolution.references.insert(pending.node, reference);
+                    }
+                    None => self.undefined(pending.name, pending.node),
+                },
+                _ => self.pending.push(pending),
+            }
+        }
+    }
+
+    fn undefined(&mut self, name: String, node: NodeId) {
+        let span = self.arena[node].span;
+        self.resolution
+            .errors
+            .push(ResolveError::Undefined { name, span });
+    }
+
+    fn local_scope(&self) -> Scope {
+        if self.frames.len() == 1 {
+            Scope::Global
+        } else {
+            Scope::Local
+        }
+    }
+
+    fn node(&mut self, id: NodeId) {
+        let arena = self.arena;
+        match &arena[id].kind {
+            // 関数は再帰呼び出しできるよう先に名前を束縛する
+            NodeKind::LetStatement { name, value, .. } => {
+                let scope = self.local_scope();
+                if let NodeKind::FunctionLiteral { .. } = arena[*value].kind {
+                    self.declare(*name, scope);
+                    self.node(*value);
+                } else {
+                    self.node(*value);
+                    self.declare(*name, scope);
+                }
+            }
+            NodeKind::ImportStatement { alias, .. } => {
+                let scope = self.local_scope();
+                self.declare(*alias, scope);
+            }
+            NodeKind::Identifier { name } => {
+                let depth = self.frames.len() - 1;
+                match self.lookup(name) {
+                    Some((reference, Some(found))) if found == depth => {
+                        self.resolution.references.insert(id, reference);
+                    }
+                    found if depth > 0 => self.pending.push(Pending {
+                        name: name.clone(),
+                        node: id,
+                        seen: depth,
+                        found,
+                    }),
+                    Some((reference, _)) => {
+                        self.resolution.references.insert(id, reference);
+                    }
+                    None => self.undefined(name.clone(), id),
+                }
+            }
+            NodeKind::FunctionLiteral {
+                parameters, body, ..
+            } => {
+                self.frames.push(Vec::new());
+                for parameter in parameters {
+                    if let NodeKind::Parameter { name, .. } = arena[*parameter].kind {
+                        self.declare(name, Scope::Parameter);
+                    }
+                }
+                self.node(*body);
+                self.resolve_pending();
+                self.frames.pop();
+            }
+            // ブロックはスコープを作らないので、catch の引数は今のスコープに入る
+            NodeKind::CatchClause { parameter, body } => {
+                let scope = self.local_scope();
+                self.declare(*parameter, scope);
+                self.node(*body);
+            }
+            // `.` の後ろの名前は参照ではない
+            NodeKind::MemberExpression { object, .. } => self.node(*object),
+            kind => {
+                for child in kind.children() {
+                    self.node(child);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve, Resolution, Scope};
+    use crate::{
+        ast::{self, arena::Arena},
+        parser,
+    };
+
+    fn resolve_source(input: &str, builtins: &[&str]) -> (Arena, Resolution) {
+        let arena = Arena::from_program(&parser::parse(input).unwrap());
+        let resolution = resolve(&arena, builtins);
+        (arena, resolution)
+    }
+
+    // 識別子ごとに「名前@行:列 -> 参照の種類 宣言の位置」を並べる
+    fn describe(arena: &Arena, r: &Resolution, input: &str) -> Vec<String> {
+        let mut references: Vec<_> = r.references.iter().collect();
+        references.sort_by_key(|(node, _)| **node);
+        references
+            .into_iter()
+            .map(|(node, reference)| {
+                let span = arena[*node].span;
+                let d = r.declaration(reference.declaration);
+                let at = d
+                    .span
+                    .map_or_else(|| "builtin".to_string(), |s| s.start.to_string());
+                format!(
+                    "{}@{} -> {:?} {}",
+                    &input[span.start.offset..span.end.offset],
+                    span.start,
+                    reference.scope,
+                    at
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let input = "let g = 1; let f = fn(a) { let l = a; fn() { a + l + g + len(f) } }; h(g);";
+        let (arena, r) = resolve_source(input, &["len"]);
+        assert_eq!(
+            describe(&arena, &r, input),
+            vec![
+                "a@1:36 -> Parameter 1:23",
+                "a@1:46 -> Free 1:23",
+                "l@1:50 -> Free 1:32",
+                "g@1:54 -> Global 1:5",
+                "len@1:58 -> Builtin builtin",
+                "f@1:62 -> Global 1:16",
+                "g@1:72 -> Global 1:5",
+            ]
+        );
+        assert_eq!(
+            r.errors().iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec!["1:70: undefined identifier \"h\""]
+        );
+        assert_eq!(r.errors()[0].code(), "E0204");
+    }
+
+    #[test]
+    fn test_shadowing_and_order() {
+        // 後から宣言したものを指し、関数の中からは後のグローバル変数も見える
+        let input =
+            "let x = 1; let x = x; let f = fn() { later }; let later = 2; let len = 3; len;";
+        let (arena, r) = resolve_source(input, &["len"]);
+        assert_eq!(
+            describe(&arena, &r, input),
+            vec![
+                "x@1:20 -> Global 1:5",
+                "later@1:38 -> Global 1:51",
+                "len@1:75 -> Global 1:66",
+            ]
+        );
+        assert!(r.errors().is_empty());
+
+        let (_, r) = resolve_source("let y = y;", &[]);
+        assert_eq!(r.errors().len(), 1);
+
+        // 囲んでいる関数で後から宣言されるローカル変数は Free で参照する
+        let input = "let outer = fn() { let f = fn() { g() }; let g = fn() { 1 }; f() }; outer();";
+        let (arena, r) = resolve_source(input, &[]);
+        assert_eq!(
+            describe(&arena, &r, input),
+            vec![
+                "g@1:35 -> Free 1:46",
+                "f@1:62 -> Local 1:24",
+                "outer@1:69 -> Global 1:5",
+            ]
+        );
+        assert!(r.errors().is_empty());
+
+        // 同じ関数の中で後から宣言する名前は参照できない
+        let (_, r) = resolve_source("let f = fn() { let a = b; let b = 1; a };", &[]);
+        assert_eq!(
+            r.errors().iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            vec!["1:24: undefined identifier \"b\""]
+        );
+    }
+
+    #[test]
+    fn test_enclosing_scope() {
+        // 囲んでいる関数で後から宣言したローカル変数は、同じ名前のグローバル変数より優先する
+        let input = "let g = 1; let outer = fn() { let f = fn() { g }; let g = 2; f() }; outer();";
+        let (arena, r) = resolve_source(input, &[]);
+        assert_eq!(
+            describe(&arena, &r, input),
+            vec![
+                "g@1:46 -> Free 1:55",
+                "f@1:62 -> Local 1:35",
+                "outer@1:69 -> Global 1:16",
+            ]
+        );
+        assert!(r.errors().is_empty());
+
+        // 見つけた時点で見えている宣言が囲んでいる関数にあれば、それを指す
+        let input = "let f = fn(len) { let h = fn() { len(g) }; let g = 1; h };";
+        let (arena, r) = resolve_source(input, &["len"]);
+        assert_eq!(
+            describe(&arena, &r, input),
+            vec![
+                "len@1:34 -> Free 1:12",
+                "g@1:38 -> Free 1:48",
+                "h@1:55 -> Local 1:23",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_catch() {
+        // catch の引数は今のスコープに宣言する
+        let input = "let f = fn() { try { 1 } catch (e) { e } }; try { f() } catch (e) { e }";
+        let (arena, r) = resolve_source(input, &[]);
+        assert_eq!(
+            describe(&arena, &r, input),
+            vec![
+                "e@1:38 -> Local 1:33",
+                "f@1:51 -> Global 1:5",
+                "e@1:69 -> Global 1:64",
+            ]
+        );
+        assert!(r.errors().is_empty());
+    }
+
+    #[test]
+    fn test_modules() {
+        // import の別名は宣言で、`.` の後ろの名前は参照ではない
+        let input = "import \"m.mk\" as m; export let v = m.value; m.f(v);";
+        let (arena, r) = resolve_source(input, &[]);
+        assert_eq!(
+            describe(&arena, &r, input),
+            vec![
+                "m@1:36 -> Global 1:18",
+                "m@1:45 -> Global 1:18",
+                "v@1:49 -> Global 1:32",
+            ]
+        );
+        assert!(r.errors().is_empty());
+    }
+
+    #[test]
+    fn test_definition_and_references() {
+        let input = "let a = 1; let b = fn(x) { x + a }; b(a);";
+        let (arena, r) = resolve_source(input, &[]);
+        let (a, declaration) = r.declarations().next().unwrap();
+        assert_eq!(
+            (declaration.name.as_str(), declaration.scope),
+            ("a", Scope::Global)
+        );
+
+        let references = r.references(a);
+        let offsets: Vec<usize> = references
+            .iter()
+            .map(|node| arena[*node].span.start.offset)
+            .collect();
+        assert_eq!(offsets, vec![31, 38]);
+        for node in references {
+            assert_eq!(r.definition(node), Some(a));
+        }
+        assert_eq!(r.definition(declaration.node.unwrap()), Some(a));
+    }
+
+    #[test]
+    fn test_identical_spans() {
+        // JSON から読んだ AST はどのノードも同じ span を持つが、ノードごとに区別する
+        let input = "let a = 1; let b = fn(x) { x + a }; b(a);";
+        let program = parser::parse(input).unwrap();
+        let program = ast::json::from_json(&ast::json::to_json(&program)).unwrap();
+        let arena = Arena::from_program(&program);
+        let r = resolve(&arena, &[]);
+        let (a, _) = r.declarations().next().unwrap();
+        assert_eq!(r.references(a).len(), 2);
+        let x = r.declarations().find(|(_, d)| d.name == "x").unwrap().0;
+        assert_eq!(r.references(x).len(), 1);
+    }
+}
@@ -3,13 +3,15 @@ use std::fmt::Display;
 use bytes::Bytes;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) enum TokenType {
+pub enum TokenType {
     Illegal, // ILLEGAL
     Eof,     // EOF
 
     // identifier, literal
-    Ident, // IDENT
-    Int,   // INT
+    Ident,   // IDENT
+    Int,     // INT
+    String,  // STRING
+    Comment, // COMMENT
 
     // operator
     Assign,   // =
@@ -22,6 +24,8 @@ pub(crate) enum TokenType {
     Lt, // <
     Gt, // >
 
+    Arrow, // ->
+
     Comma,     // ,
     Semicolon, // ;
 
@@ -31,6 +35,11 @@ pub(crate) enum TokenType {
     Lbrace, //{
     Rbrace, // }
 
+    Lbracket, // [
+    Rbracket, // ]
+    Colon,    // :
+    Dot,      // .
+
     Eq,    // ==
     NotEq, // !=
 
@@ -42,6 +51,13 @@ pub(crate) enum TokenType {
     If,       // if
     Else,     // else
     Retrun,   // return
+    Import,   // import
+    Export,   // export
+    As,       // as
+    Throw,    // throw
+    Try,      // try
+    Catch,    // catch
+    Finally,  // finally
 }
 
 impl Display for TokenType {
@@ -51,6 +67,8 @@ impl Display for TokenType {
             TokenType::Eof => "Eof",
             TokenType::Ident => "Ident",
             TokenType::Int => "Int",
+            TokenType::String => "String",
+            TokenType::Comment => "Comment",
             TokenType::Assign => "=",
             TokenType::Plus => "+",
             TokenType::Minus => "-",
@@ -59,12 +77,17 @@ impl Display for TokenType {
             TokenType::Slash => "/",
             TokenType::Lt => "<",
             TokenType::Gt => ">",
+            TokenType::Arrow => "->",
             TokenType::Comma => ",",
             TokenType::Semicolon => ";",
             TokenType::Lparne => "(",
             TokenType::Rparne => ")",
             TokenType::Lbrace => "{",
             TokenType::Rbrace => "}",
+            TokenType::Lbracket => "[",
+            TokenType::Rbracket => "]",
+            TokenType::Colon => ":",
+            TokenType::Dot => ".",
             TokenType::Eq => "==",
             TokenType::NotEq => "!=",
             TokenType::Function => "Function",
@@ -74,20 +97,99 @@ impl Display for TokenType {
             TokenType::If => "If",
             TokenType::Else => "Else",
             TokenType::Retrun => "Return",
+            TokenType::Import => "Import",
+            TokenType::Export => "Export",
+            TokenType::As => "As",
+            TokenType::Throw => "Throw",
+            TokenType::Try => "Try",
+            TokenType::Catch => "Catch",
+            TokenType::Finally => "Finally",
         };
         f.write_str(s)
     }
 }
 
+// 入力中の位置。line, column は 1 始まり
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// 入力中の範囲。end は範囲の直後の位置
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+
+    // start は self から、end は other から取った範囲
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
 // TODO to enum
-#[derive(Debug)]
-pub(crate) struct Token {
-    pub(crate) typ: TokenType,
-    pub(crate) literal: Bytes,
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub typ: TokenType,
+    // 入力の Bytes を slice したもの（コピーはしない）
+    pub literal: Bytes,
+    pub pos: Position,
+    // token の直後の位置。文字列はエスケープがあると literal と長さが変わるので別に持つ
+    pub end: Position,
+}
+
+// 位置は比較しない。同じ種類・同じ文字列なら同じ token とみなす
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.typ == other.typ && self.literal == other.literal
+    }
 }
 
 impl Token {
-    pub(crate) fn new(typ: TokenType, literal: Bytes) -> Self {
-        Self { typ, literal }
+    pub fn new(typ: TokenType, literal: Bytes) -> Self {
+        Self::with_span(typ, literal, Span::default())
+    }
+
+    pub fn with_span(typ: TokenType, literal: Bytes, span: Span) -> Self {
+        Self {
+            typ,
+            literal,
+            pos: span.start,
+            end: span.end,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.pos, self.end)
     }
 }
@@ -0,0 +1,869 @@
+// 型注釈と単一化による型検査
+// 注釈のない引数や組み込み関数は any（動的型）として扱い、any とはどの型とも矛盾しない
+// 注釈のない let は右辺から型を推論する。関数の戻り値や空の配列の要素など、そこで決まらなかった型は any にする
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use thiserror::Error;
+
+use crate::{
+    ast::{
+        annotation::TypeAnnotation,
+        arena::{Arena, NodeId, NodeKind},
+        Node,
+    },
+    token,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Type {
+    Int,
+    Bool,
+    Str,
+    Any,
+    Array(Box<Type>),
+    Hash(Box<Type>, Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    // 推論中の型変数
+    Var(usize),
+}
+
+impl Type {
+    pub fn from_annotation(annotation: &TypeAnnotation) -> Self {
+        match annotation {
+            TypeAnnotation::Named(_) => match annotation.token_literal() {
+                "int" => Type::Int,
+                "bool" => Type::Bool,
+                "str" => Type::Str,
+                _ => Type::Any,
+            },
+            TypeAnnotation::Array { element, .. } => {
+                Type::Array(Box::new(Type::from_annotation(element)))
+            }
+            TypeAnnotation::Hash { key, value, .. } => Type::Hash(
+                Box::new(Type::from_annotation(key)),
+                Box::new(Type::from_annotation(value)),
+            ),
+            TypeAnnotation::Function {
+                parameters,
+                return_type,
+                ..
+            } => Type::Function(
+                parameters.iter().map(Type::from_annotation).collect(),
+                Box::new(Type::from_annotation(return_type)),
+            ),
+        }
+    }
+}
+
+// 型注釈と同じ書き方で出力する
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => f.write_str("int"),
+            Type::Bool => f.write_str("bool"),
+            Type::Str => f.write_str("str"),
+            Type::Any | Type::Var(_) => f.write_str("any"),
+            Type::Array(element) => write!(f, "[{}]", element),
+            Type::Hash(key, value) => write!(f, "{{{}: {}}}", key, value),
+            Type::Function(parameters, return_type) => {
+                f.write_str("fn(")?;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    parameter.fmt(f)?;
+                }
+                write!(f, ") -> {}", return_type)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum TypeError {
+    #[error("{}: type mismatch: expected {expected}, found {found}", .span.start)]
+    Mismatch {
+        expected: Type,
+        found: Type,
+        span: token::Span,
+    },
+    #[error("{}: expected {expected} argument(s), found {found}", .span.start)]
+    Arity {
+        expected: usize,
+        found: usize,
+        span: token::Span,
+    },
+    #[error("{}: {typ} is not a function", .span.start)]
+    NotCallable { typ: Type, span: token::Span },
+    #[error("{}: {typ} cannot be indexed", .span.start)]
+    NotIndexable { typ: Type, span: token::Span },
+}
+
+impl TypeError {
+    pub fn span(&self) -> token::Span {
+        match self {
+            TypeError::Mismatch { span, .. }
+            | TypeError::Arity { span, .. }
+            | TypeError::NotCallable { span, .. }
+            | TypeError::NotIndexable { span, .. } => *span,
+        }
+    }
+
+    // ドキュメントやテストで使うエラーコード。番号は変えない
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::Mismatch { .. } => "E0301",
+            TypeError::Arity { .. } => "E0302",
+            TypeError::NotCallable { .. } => "E0303",
+            TypeError::NotIndexable { .. } => "E0304",
+        }
+    }
+}
+
+// 検査の結果。let と引数で宣言した名前の型を、名前の識別子の NodeId から引ける
+#[derive(Debug, Default)]
+pub struct Typing {
+    bindings: HashMap<NodeId, Type>,
+    errors: Vec<TypeError>,
+}
+
+impl Typing {
+    pub fn type_of(&self, node: NodeId) -> Option<&Type> {
+        self.bindings.get(&node)
+    }
+
+    pub fn errors(&self) -> &[TypeError] {
+        &self.errors
+    }
+}
+
+pub fn check(arena: &Arena) -> Typing {
+    let mut c = Checker {
+        arena,
+        substitution: Vec::new(),
+        scopes: vec![HashMap::new()],
+        returns: Vec::new(),
+        bindings: Vec::new(),
+        errors: Vec::new(),
+    };
+    if let NodeKind::Program { statements } = &arena[arena.root()].kind {
+        c.statements(statements);
+    }
+
+    let bindings = std::mem::take(&mut c.bindings);
+    Typing {
+        bindings: bindings
+            .into_iter()
+            .map(|(node, t)| (node, c.resolve(&t)))
+            .collect(),
+        errors: c.errors,
+    }
+}
+
+struct Checker<'a> {
+    arena: &'a Arena,
+    // Var(i) に束縛された型
+    substitution: Vec<Option<Type>>,
+    // 関数ごとの名前の型（ブロックはスコープを作らない）
+    // 注釈で型を決めた名前は true
+    scopes: Vec<HashMap<String, (Type, bool)>>,
+    // 検査中の関数の戻り値
+    returns: Vec<Return>,
+    bindings: Vec<(NodeId, Type)>,
+    errors: Vec<TypeError>,
+}
+
+// 関数の戻り値の型と、注釈がないときに値を返すところの型がそろっているか
+struct Return {
+    typ: Type,
+    annotated: bool,
+    mixed: bool,
+}
+
+impl Checker<'_> {
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    // 束縛済みの型変数を先頭だけたどる
+    fn prune(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(i) => match &self.substitution[*i] {
+                Some(bound) => self.prune(bound),
+                None => t.clone(),
+            },
+            _ => t.clone(),
+        }
+    }
+
+    // 型変数をすべて置き換える。束縛されていないものは any にする
+    fn resolve(&self, t: &Type) -> Type {
+        match self.prune(t) {
+            Type::Var(_) => Type::Any,
+            Type::Array(element) => Type::Array(Box::new(self.resolve(&element))),
+            Type::Hash(key, value) => {
+                Type::Hash(Box::new(self.resolve(&key)), Box::new(self.resolve(&value)))
+            }
+            Type::Function(parameters, return_type) => Type::Function(
+                parameters.iter().map(|p| self.resolve(p)).collect(),
+                Box::new(self.resolve(&return_type)),
+            ),
+            t => t,
+        }
+    }
+
+    // 決まらなかった型変数を any にする
+    // 最初に使ったところで型が決まってしまうと、注釈のないコードを実行できるのに誤りとして報告してしまう
+    fn settle(&mut self, t: &Type) {
+        match self.prune(t) {
+            Type::Var(i) => self.substitution[i] = Some(Type::Any),
+            Type::Array(element) => self.settle(&element),
+            Type::Hash(key, value) => {
+                self.settle(&key);
+                self.settle(&value);
+            }
+            Type::Function(parameters, return_type) => {
+                for p in &parameters {
+                    self.settle(p);
+                }
+                self.settle(&return_type);
+            }
+            _ => {}
+        }
+    }
+
+    fn occurs(&self, var: usize, t: &Type) -> bool {
+        match self.prune(t) {
+            Type::Var(i) => i == var,
+            Type::Array(element) => self.occurs(var, &element),
+            Type::Hash(key, value) => self.occurs(var, &key) || self.occurs(var, &value),
+            Type::Function(parameters, return_type) => {
+                parameters.iter().any(|p| self.occurs(var, p)) || self.occurs(var, &return_type)
+            }
+            _ => false,
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        match (self.prune(a), self.prune(b)) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Var(i), Type::Var(j)) if i == j => true,
+            (Type::Var(i), t) | (t, Type::Var(i)) => {
+                if self.occurs(i, &t) {
+                    return false;
+                }
+                self.substitution[i] = Some(t);
+                true
+            }
+            (Type::Array(a), Type::Array(b)) => self.unify(&a, &b),
+            (Type::Hash(ak, av), Type::Hash(bk, bv)) => {
+                self.unify(&ak, &bk) && self.unify(&av, &bv)
+            }
+            (Type::Function(ap, ar), Type::Function(bp, br)) => {
+                ap.len() == bp.len()
+                    && ap.iter().zip(&bp).all(|(a, b)| self.unify(a, b))
+                    && self.unify(&ar, &br)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    // 失敗したときは途中の束縛を戻す
+    fn try_unify(&mut self, a: &Type, b: &Type) -> bool {
+        let saved = self.substitution.clone();
+        let ok = self.unify(a, b);
+        if !ok {
+            self.substitution = saved;
+        }
+        ok
+    }
+
+    fn expect(&mut self, expected: &Type, found: &Type, span: token::Span) {
+        if !self.try_unify(expected, found) {
+            self.errors.push(TypeError::Mismatch {
+                expected: self.resolve(expected),
+                found: self.resolve(found),
+                span,
+            });
+        }
+    }
+
+    fn span(&self, id: NodeId) -> token::Span {
+        self.arena[id].span
+    }
+
+    fn declare(&mut self, identifier: NodeId, t: Type, annotated: bool) {
+        self.bindings.push((identifier, t.clone()));
+        if let NodeKind::Identifier { name } = &self.arena[identifier].kind {
+            self.scopes
+                .last_mut()
+                .unwrap()
+                .insert(name.clone(), (t, annotated));
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map_or(Type::Any, |(t, _)| t.clone())
+    }
+
+    // 注釈で型を決めた名前を参照する式か
+    fn is_annotated(&self, id: NodeId) -> bool {
+        match &self.arena[id].kind {
+            NodeKind::Identifier { name } => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .is_some_and(|(_, annotated)| *annotated),
+            _ => false,
+        }
+    }
+
+    fn block(&mut self, block: NodeId) -> Type {
+        match &self.arena[block].kind {
+            NodeKind::BlockStatement { statements } => self.statements(statements),
+            _ => Type::Any,
+        }
+    }
+
+    // 最後の式文の型を返す。値を返さないときは any
+    fn statements(&mut self, statements: &[NodeId]) -> Type {
+        let arena = self.arena;
+        let mut last = Type::Any;
+        for s in statements {
+            last = match &arena[*s].kind {
+                NodeKind::LetStatement { .. } => {
+                    self.let_statement(*s);
+                    Type::Any
+                }
+                NodeKind::ReturnStatement { value } => {
+                    let t = self.expression(*value);
+                    self.returned(&t, self.span(*value));
+                    Type::Any
+                }
+                NodeKind::ExpressionStatement { expression } => self.expression(*expression),
+                // モジュールの中身は検査しないので any
+                NodeKind::ImportStatement { alias, .. } => {
+                    self.declare(*alias, Type::Any, false);
+                    Type::Any
+                }
+                NodeKind::ExportStatement { statement } => {
+                    self.let_statement(*statement);
+                    Type::Any
+                }
+                NodeKind::ThrowStatement { value } => {
+                    self.expression(*value);
+                    Type::Any
+                }
+                _ => Type::Any,
+            };
+        }
+        last
+    }
+
+    fn let_statement(&mut self, id: NodeId) {
+        let arena = self.arena;
+        let (name, annotation, value) = match &arena[id].kind {
+            NodeKind::LetStatement {
+                name,
+                annotation,
+                value,
+            } => (*name, annotation, *value),
+            _ => return,
+        };
+        let annotated = annotation.as_ref().map(Type::from_annotation);
+        // 関数は再帰呼び出しできるよう先に名前を束縛する
+        if let NodeKind::FunctionLiteral { .. } = arena[value].kind {
+            let is_annotated = annotated.is_some();
+            let declared = annotated.unwrap_or_else(|| self.fresh());
+            self.declare(name, declared.clone(), is_annotated);
+            let t = self.expression(value);
+            self.expect(&declared, &t, self.span(value));
+            return;
+        }
+        let t = self.expression(value);
+        match annotated {
+            Some(declared) => {
+                self.expect(&declared, &t, self.span(value));
+                self.declare(name, declared, true);
+            }
+            None => self.declare(name, t, false),
+        }
+    }
+
+    // 注釈があれば合うかを調べ、なければ値を返すところの型を合わせる
+    // そろわないか any が混じれば、どれを返しても誤りにしないよう any にする
+    fn returned(&mut self, t: &Type, span: token::Span) {
+        let (expected, annotated, mut mixed) = match self.returns.last() {
+            Some(r) => (r.typ.clone(), r.annotated, r.mixed),
+            None => return,
+        };
+        if annotated {
+            self.expect(&expected, t, span);
+            return;
+        }
+        if self.prune(t) == Type::Any {
+            mixed = true;
+        }
+        self.join(&expected, t, &mut mixed);
+        self.returns.last_mut().unwrap().mixed = mixed;
+    }
+
+    // 要素の型がそろわなければ any にする
+    fn join(&mut self, element: &Type, t: &Type, mixed: &mut bool) {
+        if !*mixed && !self.try_unify(element, t) {
+            *mixed = true;
+        }
+    }
+
+    fn expression(&mut self, id: NodeId) -> Type {
+        let arena = self.arena;
+        match &arena[id].kind {
+            NodeKind::Identifier { name } => self.lookup(name),
+            NodeKind::IntegerLiteral { .. } => Type::Int,
+            NodeKind::StringLiteral { .. } => Type::Str,
+            NodeKind::Boolean { .. } => Type::Bool,
+            NodeKind::PrefixExpression { operator, right } => {
+                let t = self.expression(*right);
+                if operator == "-" {
+                    self.expect(&Type::Int, &t, self.span(*right));
+                    Type::Int
+                } else {
+                    Type::Bool
+                }
+            }
+            NodeKind::InfixExpression {
+                left,
+                operator,
+                right,
+            } => self.infix_expression(*left, operator, *right),
+            NodeKind::IfExpression {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expression(*condition);
+                let consequence = self.block(*consequence);
+                let alternative = match alternative {
+                    Some(alternative) => self.block(*alternative),
+                    // else がなければ null になりうる
+                    None => return Type::Any,
+                };
+                let any = [&consequence, &alternative]
+                    .iter()
+                    .any(|t| self.prune(t) == Type::Any);
+                if !any && self.try_unify(&consequence, &alternative) {
+                    consequence
+                } else {
+                    Type::Any
+                }
+            }
+            NodeKind::FunctionLiteral {
+                parameters,
+                return_type,
+                body,
+            } => self.function_literal(parameters, return_type.as_ref(), *body),
+            // catch には何が届くかわからないので、値も any にする
+            NodeKind::TryExpression {
+                block,
+                catch,
+                finally,
+            } => {
+                self.block(*block);
+                if let Some(catch) = catch {
+                    if let NodeKind::CatchClause { parameter, body } = &arena[*catch].kind {
+                        self.declare(*parameter, Type::Any, false);
+                        self.block(*body);
+                    }
+                }
+                if let Some(finally) = finally {
+                    self.block(*finally);
+                }
+                Type::Any
+            }
+            NodeKind::CallExpression {
+                function: callee,
+                arguments: argument_nodes,
+            } => {
+                let function = self.expression(*callee);
+                let arguments: Vec<Type> =
+                    argument_nodes.iter().map(|a| self.expression(*a)).collect();
+                match self.prune(&function) {
+                    Type::Function(parameters, return_type) => {
+                        if parameters.len() != arguments.len() {
+                            self.errors.push(TypeError::Arity {
+                                expected: parameters.len(),
+                                found: arguments.len(),
+                                span: self.span(id),
+                            });
+                        } else {
+                            for ((parameter, argument), node) in
+                                parameters.iter().zip(&arguments).zip(argument_nodes)
+                            {
+                                self.expect(parameter, argument, self.span(*node));
+                            }
+                        }
+                        *return_type
+                    }
+                    Type::Any => Type::Any,
+                    Type::Var(_) => {
+                        let return_type = self.fresh();
+                        let t = Type::Function(arguments, Box::new(return_type.clone()));
+                        self.unify(&function, &t);
+                        return_type
+                    }
+                    typ => {
+                        self.errors.push(TypeError::NotCallable {
+                            typ: self.resolve(&typ),
+                            span: self.span(*callee),
+                        });
+                        Type::Any
+                    }
+                }
+            }
+            NodeKind::ArrayLiteral { elements } => {
+                let element = self.fresh();
+                let mut mixed = false;
+                for item in elements {
+                    let t = self.expression(*item);
+                    self.join(&element, &t, &mut mixed);
+                }
+                let t = Type::Array(Box::new(if mixed { Type::Any } else { element }));
+                self.settle(&t);
+                t
+            }
+            NodeKind::IndexExpression { left, index } => {
+                let left_type = self.expression(*left);
+                let index_type = self.expression(*index);
+                match self.prune(&left_type) {
+                    Type::Array(element) => {
+                        self.expect(&Type::Int, &index_type, self.span(*index));
+                        *element
+                    }
+                    // 違う型のキーは実行すると null になるだけなので、注釈で決めた型のときだけ調べる
+                    Type::Hash(key, value) => {
+                        if self.is_annotated(*left) {
+                            self.expect(&key, &index_type, self.span(*index));
+                        }
+                        *value
+                    }
+                    Type::Any | Type::Var(_) => Type::Any,
+                    typ => {
+                        self.errors.push(TypeError::NotIndexable {
+                            typ: self.resolve(&typ),
+                            span: self.span(*left),
+                        });
+                        Type::Any
+                    }
+                }
+            }
+            NodeKind::MemberExpression { object, .. } => {
+                self.expression(*object);
+                Type::Any
+            }
+            NodeKind::HashLiteral { pairs } => {
+                let (key, value) = (self.fresh(), self.fresh());
+                let (mut mixed_key, mut mixed_value) = (false, false);
+                for (k, v) in pairs {
+                    let t = self.expression(*k);
+                    self.join(&key, &t, &mut mixed_key);
+                    let t = self.expression(*v);
+                    self.join(&value, &t, &mut mixed_value);
+                }
+                let key = if mixed_key { Type::Any } else { key };
+                let value = if mixed_value { Type::Any } else { value };
+                let t = Type::Hash(Box::new(key), Box::new(value));
+                self.settle(&t);
+                t
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn infix_expression(&mut self, left_node: NodeId, operator: &str, right_node: NodeId) -> Type {
+        let left = self.expression(left_node);
+        let right = self.expression(right_node);
+        let operand = |c: &mut Self, t: Type| {
+            c.expect(&t, &left, c.span(left_node));
+            c.expect(&t, &right, c.span(right_node));
+        };
+        match operator {
+            // 文字列の連結か整数の足し算
+            "+" => {
+                let heads = [self.prune(&left), self.prune(&right)];
+                if heads.contains(&Type::Str) {
+                    operand(self, Type::Str);
+                    Type::Str
+                } else if heads.contains(&Type::Int) {
+                    operand(self, Type::Int);
+                    Type::Int
+                } else if heads.contains(&Type::Any) {
+                    Type::Any
+                } else {
+                    operand(self, Type::Int);
+                    Type::Int
+                }
+            }
+            "<" | ">" => {
+                operand(self, Type::Int);
+                Type::Bool
+            }
+            "==" | "!=" => {
+                self.expect(&left, &right, self.span(right_node));
+                Type::Bool
+            }
+            _ => {
+                operand(self, Type::Int);
+                Type::Int
+            }
+        }
+    }
+
+    fn function_literal(
+        &mut self,
+        parameters: &[NodeId],
+        return_annotation: Option<&TypeAnnotation>,
+        body: NodeId,
+    ) -> Type {
+        let arena = self.arena;
+        self.scopes.push(HashMap::new());
+        let parameters: Vec<Type> = parameters
+            .iter()
+            .filter_map(|p| match &arena[*p].kind {
+                NodeKind::Parameter { name, annotation } => {
+                    let t = annotation.as_ref().map_or(Type::Any, Type::from_annotation);
+                    self.declare(*name, t.clone(), annotation.is_some());
+                    Some(t)
+                }
+                _ => None,
+            })
+            .collect();
+        let return_type = match return_annotation {
+            Some(annotation) => Type::from_annotation(annotation),
+            None => self.fresh(),
+        };
+        self.returns.push(Return {
+            typ: return_type.clone(),
+            annotated: return_annotation.is_some(),
+            mixed: false,
+        });
+        let body_type = self.block(body);
+        if let NodeKind::BlockStatement { statements } = &arena[body].kind {
+            if let Some(last) = statements.last() {
+                if let NodeKind::ExpressionStatement { .. } = arena[*last].kind {
+                    self.returned(&body_type, self.span(*last));
+                }
+            }
+        }
+        let return_type = match self.returns.pop() {
+            Some(Return { mixed: true, .. }) => Type::Any,
+            _ => return_type,
+        };
+        self.scopes.pop();
+        let t = Type::Function(parameters, Box::new(return_type));
+        self.settle(&t);
+        t
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, Type, Typing};
+    use crate::{
+        ast::{
+            self,
+            arena::{Arena, NodeKind},
+        },
+        evaluator,
+        object::Environment,
+        parser,
+    };
+
+    fn typing(input: &str) -> (Arena, Typing) {
+        let arena = Arena::from_program(&parser::parse(input).unwrap());
+        let typing = check(&arena);
+        (arena, typing)
+    }
+
+    fn errors(input: &str) -> Vec<String> {
+        typing(input)
+            .1
+            .errors()
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = [
+            (
+                "let x = 5; x + true;",
+                vec!["1:16: type mismatch: expected int, found bool"],
+            ),
+            (
+                "let x: int = \"five\";",
+                vec!["1:14: type mismatch: expected int, found str"],
+            ),
+            (
+                "let f = fn(a: str, b: int) -> bool { a }; f(1, 2); f(\"a\");",
+                vec![
+                    "1:38: type mismatch: expected bool, found str",
+                    "1:45: type mismatch: expected str, found int",
+                    "1:52: expected 2 argument(s), found 1",
+                ],
+            ),
+            (
+                "let f = fn(x: int) -> int { if (x > 0) { return \"big\"; } x }; f(1);",
+                vec!["1:49: type mismatch: expected int, found str"],
+            ),
+            (
+                "let s = \"a\"; s - 1; s(1); 1[0];",
+                vec![
+                    "1:14: type mismatch: expected int, found str",
+                    "1:21: str is not a function",
+                    "1:27: int cannot be indexed",
+                ],
+            ),
+            ("let xs: [int] = []; xs[0] + 1;", vec![]),
+            (
+                "let xs: [int] = []; let ys: [str] = xs;",
+                vec!["1:37: type mismatch: expected [str], found [int]"],
+            ),
+            (
+                "1 == \"a\";",
+                vec!["1:6: type mismatch: expected int, found str"],
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(errors(input), expected, "{}", input);
+        }
+
+        let (_, typing) = typing("let s = \"a\"; s - 1; s(1); 1[0]; let f = fn(a) { a }; f();");
+        let codes: Vec<&str> = typing.errors().iter().map(|e| e.code()).collect();
+        assert_eq!(codes, vec!["E0301", "E0303", "E0304", "E0302"]);
+    }
+
+    #[test]
+    fn test_hash_keys() {
+        // 違う型のキーは実行すると null になるので、キーの型は注釈で決めたときだけ調べる
+        assert_eq!(errors("let h = {\"a\": 1}; h[1];"), Vec::<String>::new());
+        assert_eq!(
+            errors("let h: {str: int} = {\"a\": 1}; h[1] + 1; let f = fn(g: {int: str}) { g[\"k\"] }; f({});"),
+            vec![
+                "1:33: type mismatch: expected str, found int",
+                "1:71: type mismatch: expected int, found str",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_code() {
+        // 注釈のない引数は any なので、実行時にしかわからない組み合わせは通す
+        for input in [
+            "let add = fn(a, b) { a + b }; add(1, 2); add(\"a\", \"b\");",
+            "let x = if (true) { 1 } else { \"one\" }; x + \"s\"; x - 1;",
+            "let mixed = [1, \"two\", true]; mixed[0] + 1; mixed[1] + \"s\";",
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10);",
+            "len([1, 2]) + 1; puts(\"x\");",
+            "let apply = fn(f, x) { f(x) }; apply(fn(y) { y * 2 }, 3);",
+        ] {
+            assert_eq!(errors(input), Vec::<String>::new(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_no_false_positives() {
+        // 注釈がなく実行してもエラーにならないプログラムは、検査でも誤りにしない
+        for input in [
+            "let id = fn(x) { x }; let a = id(\"s\") + \"t\"; let b = id(2) - 1; [a, b]",
+            "let h = {}; let f = fn() { h[\"k\"] }; let a: int = f(); let b: str = f(); [a, b]",
+            "let h = {}; let k = fn() { h }; k()[\"a\"]; k()[1]",
+            "let empty = fn() { [] }; let a: [int] = empty(); let b: [str] = empty(); [a, b]",
+            "let loop = fn(n) { if (n > 0) { loop(n - 1) } else { n } }; loop(3) + 1",
+            "let wrap = fn(x) { [x] }; [wrap(1)[0] + 1, wrap(\"s\")[0] + \"t\"]",
+            "let pick = fn(a, b) { if (a) { a } else { b } }; pick(1, 2) + 1; pick(\"a\", \"b\") + \"c\"",
+            "let compose = fn(f, g) { fn(x) { f(g(x)) } }; compose(fn(x) { x + 1 }, fn(x) { x * 2 })(3)",
+            "let make = fn() { {} }; let a = make(); let b = make(); a[1]; b[\"s\"]",
+            "let xs = []; let ys = if (true) { xs } else { [1] }; let zs = if (true) { xs } else { [\"s\"] }; zs",
+            // return と最後の式で返す型が違う
+            "let f = fn(x) { if (x) { return 1; } \"s\" }; f(true)",
+            "let f = fn(x, y) { if (x) { return 1; } y }; f(false, \"s\") + \"t\"",
+            // リテラルから推論したキーと違う型のキーは null になるだけ
+            "let h = {1: \"a\"}; h[\"x\"]",
+        ] {
+            let program = parser::parse(input).unwrap();
+            assert!(
+                evaluator::eval_program(&program, &Environment::new()).is_ok(),
+                "{}",
+                input
+            );
+            assert_eq!(errors(input), Vec::<String>::new(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_identical_spans() {
+        // JSON から読んだ AST はどのノードも同じ span を持つが、ノードごとに型を持つ
+        let program = parser::parse("let a = 1; let b = \"s\"; a + b;").unwrap();
+        let program = ast::json::from_json(&ast::json::to_json(&program)).unwrap();
+        let arena = Arena::from_program(&program);
+        let typing = check(&arena);
+        let types: Vec<String> = arena
+            .iter()
+            .filter_map(|(_, node)| match node.kind {
+                NodeKind::LetStatement { name, .. } => {
+                    Some(typing.type_of(name).unwrap().to_string())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(types, vec!["int", "str"]);
+        assert_eq!(typing.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_inferred_types() {
+        let (arena, typing) = typing(
+            "let n = 1; let xs = [n, 2]; let h = {\"k\": xs}; let f = fn(a: str) { a + \"!\" }; let e = []; let g = fn(x) { if (x) { return 1; } 2 }; let k = fn(x) { if (x) { return 1; } \"s\" };",
+        );
+        let types: Vec<String> = arena
+            .iter()
+            .filter_map(|(_, node)| match node.kind {
+                NodeKind::LetStatement { name, .. } => {
+                    Some(typing.type_of(name).unwrap().to_string())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                "int",
+                "[int]",
+                "{str: [int]}",
+                "fn(str) -> str",
+                "[any]",
+                "fn(any) -> int",
+                "fn(any) -> any"
+            ]
+        );
+        assert_eq!(
+            Type::from_annotation(
+                &parser::parse_type_annotation("fn([int]) -> {str: any}").unwrap()
+            ),
+            Type::Function(
+                vec![Type::Array(Box::new(Type::Int))],
+                Box::new(Type::Hash(Box::new(Type::Str), Box::new(Type::Any)))
+            )
+        );
+    }
+}